@@ -1,28 +1,89 @@
 #![feature(macro_metavar_expr)]
 
-use std::{env::args, path::PathBuf, str::FromStr};
+use std::{path::PathBuf, sync::mpsc};
 
-use color_eyre::eyre::OptionExt;
-use elytra_cli::ElytraDevice;
-use elytra_cli::wasm::WasmDevice;
-use elytra_cli::tcp::TcpServer;
+use clap::Parser;
+use elytra_cli::device::{parse_device_type, DeviceType};
+use elytra_cli::tcp::{load_psk, TcpServer, DEFAULT_ADDR};
+use elytra_cli::ws::WsServer;
+
+/// Serves a device (wasm file, TCP address or serial) over TCP and WebSocket
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct ServerArgs {
+    /// Device to serve
+    #[arg(value_parser = parse_device_type)]
+    device: DeviceType,
+
+    /// Address to listen on
+    #[arg(short, long, default_value = DEFAULT_ADDR)]
+    bind: String,
+
+    /// File with the pre-shared key (64 hex digits) that clients need to set up an encrypted session with,
+    /// the key is taken from ELYTRA_PSK if not given
+    #[arg(long)]
+    psk_file: Option<PathBuf>,
+
+    /// Address to serve a WebSocket endpoint for the web UI on, e.g. localhost:48001.
+    /// WebSocket clients can't use the pre-shared key, so with a key it has to be a loopback address
+    #[arg(long)]
+    ws: Option<String>,
+
+    /// Origin of web pages that may connect to the WebSocket endpoint, defaults to pages served from localhost
+    #[arg(long = "origin")]
+    origins: Vec<String>,
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     eprintln!("elytra mock impl server");
 
-    let file_path = args().skip(1).next().map(|s|  PathBuf::from_str(&s).unwrap())
-        .ok_or_eyre("Missing argument FILE")?;
+    let args = ServerArgs::parse();
+    let psk = load_psk(args.psk_file.as_deref(), "ELYTRA_PSK")?;
+
+    // the first server to fail ends the process with its error
+    let (failed, first_failure) = mpsc::channel();
 
-    let mut device = WasmDevice::new(&file_path)?;
+    if let Some(addr) = &args.ws {
+        let server = WsServer::bind(addr)?.with_origins(args.origins.clone());
+        let local_addr = server.local_addr()?;
+        if psk.is_some() && !local_addr.ip().is_loopback() {
+            Err(format!("The WebSocket endpoint on {local_addr} would make the device reachable without the pre-shared key, bind it to localhost instead"))?
+        }
+        eprintln!("WebSocket endpoint on ws://{local_addr}");
+        // like TCP clients, every socket gets its own device session
+        let (device_type, failed) = (args.device.clone(), failed.clone());
+        std::thread::spawn(move || {
+            let res = server.serve(move || device_type.open(None));
+            let _ = failed.send(("WebSocket", res));
+        });
+    }
 
-    let mut server = TcpServer::new()?;
-    loop {
-        eprint!("Waiting for connection... ");
-        let bytes = server.recieve()?;
-        eprintln!("Recieved: {:02x?}", bytes);
-        let bytes = device.send_command_raw(bytes.try_into().unwrap())?;
-        eprintln!("Response: {:02x?}", bytes);
-        server.respond(&bytes)?;
-        eprintln!();
+    let mut server = TcpServer::bind(&args.bind)?;
+    if let Some(psk) = psk {
+        eprintln!("Requiring encrypted sessions");
+        server = server.with_psk(psk);
     }
+    eprintln!("Listening on {}", server.local_addr()?);
+
+    // every client gets its own device session, so that logins and transactions aren't shared
+    let device_type = args.device;
+    std::thread::spawn(move || {
+        let res = server.serve_sessions(move || {
+            let mut device = device_type.open(None)?;
+            Ok(move |bytes| {
+                eprintln!("Recieved: {:02x?}", bytes);
+                let bytes = device.send_command_raw(bytes)?;
+                for (level, message) in device.get_device_log() {
+                    eprintln!("Device {level}: {message}");
+                }
+                eprintln!("Response: {:02x?}", bytes);
+                Ok(bytes)
+            })
+        });
+        let _ = failed.send(("TCP", res));
+    });
+
+    let (name, res) = first_failure.recv()?;
+    res.map_err(|e| format!("{name} server failed: {e}"))?;
+    Ok(())
 }
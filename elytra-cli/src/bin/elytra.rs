@@ -1,42 +1,28 @@
-use std::net::ToSocketAddrs;
-use std::{error::Error, net::SocketAddr};
-use std::path::PathBuf;
+use std::error::Error;
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use elytra_conf::auth::AccessLevel;
 use elytra_conf::entry::ExtraFlags;
-use elytra_conf::config::QueryTargetKey;
+use elytra_conf::config::{EntryType, QueryTargetKey};
 use elytra_conf::values::ValueType;
+use elytra_conf::job::JobState;
+use std::io::Write;
 
 use owo_colors::{AnsiColors, OwoColorize};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
-use elytra_cli::{ElytraDevice, tcp::TcpDevice, wasm::WasmDevice, Entry, LayoutEntry, Section, tui};
-
-#[derive(Debug, Clone)]
-enum DeviceType {
-    Tcp(Vec<SocketAddr>),
-    Wasm(PathBuf),
-    Serial
-}
+use elytra_cli::{ElytraDevice, LogItem, bridge::{parse_listen, BridgeOptions, Listen}, device::{parse_device_type, DeviceType, SharedDevice}, mock::MockDevice, record::RecordingDevice, tcp::load_psk, Entry, JobStatus, LayoutEntry, Section, tui};
+use elytra_cli::decode::{describe_exchange, describe_request, describe_response, parse_hex_frame};
+use elytra_cli::format::{blob_has_digest, blob_max_size, format_entry_value, format_hex, parse_entry_value};
+use sha2::{Digest, Sha256};
 
 fn parse_query_prop(s: &str) -> Result<QueryTargetKey, String> {
     QueryTargetKey::try_from(s).map_err(|e| format!("{:?}", e))
 }
 
-fn parse_device_type(s: &str) -> Result<DeviceType, String> {
-    if let Ok(addrs) = ToSocketAddrs::to_socket_addrs(s) {
-        return Ok(DeviceType::Tcp(addrs.collect()))
-    }
-
-    if s == "serial" {
-       return Ok(DeviceType::Serial)
-    }
-
-    let path = PathBuf::from(s);
-    if path.exists() && path.is_file() {
-        Ok(DeviceType::Wasm(path))
-    } else {
-        Err(format!("Not a valid device or wasm file path: \"{}\"", s))
-    }
+fn parse_access_level(s: &str) -> Result<AccessLevel, String> {
+    s.parse().map_err(|_| format!("Not a valid access level: \"{s}\" (public, user or admin)"))
 }
 
 #[derive(Debug, Subcommand)]
@@ -50,20 +36,112 @@ enum Commands {
     /// Query for field value
     Value(ValueArgs),
 
+    /// Write a new field value
+    Set(SetArgs),
+
+    /// Set a time, date or timestamp field from the host clock
+    SyncTime(ValueArgs),
+
     /// Send a specific query command
     Query(QueryArgs),
 
     /// View a section summary
-    Sections
+    Sections,
+
+    /// Manage instances of repeated sections
+    #[command(subcommand)]
+    Group(GroupCommands),
+
+    /// Run an action, passing any parameters
+    Action(ActionArgs),
+
+    /// Check on or cancel long-running actions
+    #[command(subcommand)]
+    Job(JobCommands),
+
+    /// Transfer a firmware image to the device
+    Flash(FlashArgs),
+
+    /// Transfer the contents of blob props, like certificates
+    #[command(subcommand)]
+    Blob(BlobCommands),
+
+    /// Write prop values from a file of `Name = value` lines, applying all or none of them
+    Import { file: PathBuf },
+
+    /// Reset props to their default values
+    Reset(ResetArgs),
+
+    /// Expose a device on another transport, e.g. a serial device over TCP
+    Bridge(BridgeArgs),
+
+    /// Describe hex dumps of frames, e.g. from a logic analyzer or USB capture
+    Decode(DecodeArgs),
+
+    /// Serve an in-memory device described by a schema file, for developing hosts before there is firmware
+    Mock(MockArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum BlobCommands {
+    /// Write a file to a blob prop
+    Put { name: String, file: PathBuf },
+
+    /// Read a blob prop into a file
+    Get { name: String, file: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum JobCommands {
+    /// Show the progress of a job
+    Status { id: u8 },
+
+    /// Cancel a running job
+    Cancel { id: u8 },
+}
+
+#[derive(Debug, Subcommand)]
+enum GroupCommands {
+    /// List the instances that are in use
+    List { section: u8 },
+
+    /// Add a new instance
+    Add { section: u8 },
+
+    /// Remove an instance
+    Remove { section: u8, instance: u8 },
+
+    /// Query for the value of an instance prop
+    Value { section: u8, instance: u8, prop: u8 },
+
+    /// Write a new value to an instance prop
+    Set { section: u8, instance: u8, prop: u8, value: String },
 }
 
 /// Elytra command line tool
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct AppArgs {
-    /// Device
+    /// Device (TCP address, serial:<port>, replay:<recording>, mock:<schema> or wasm file), required by all commands but bridge, decode and mock
     #[arg(short, long, value_parser = parse_device_type)]
-    device: DeviceType,
+    device: Option<DeviceType>,
+
+    /// Log in before running the command, with the PIN or passphrase from ELYTRA_PIN or asked for on the terminal
+    #[arg(long)]
+    login: bool,
+
+    /// Access level to log in at
+    #[arg(long, default_value_t = AccessLevel::User, value_parser = parse_access_level)]
+    access: AccessLevel,
+
+    /// File with the pre-shared key (64 hex digits) to encrypt and authenticate TCP connections with,
+    /// the key is taken from ELYTRA_PSK if not given
+    #[arg(long)]
+    psk_file: Option<PathBuf>,
+
+    /// Record the session with the device to a file, which can be replayed with `-d replay:<file>`
+    #[arg(long)]
+    record: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Option<Commands>
@@ -72,29 +150,237 @@ struct AppArgs {
 fn main() -> Result<(), Box<dyn Error>> {
     
     let cli = AppArgs::parse();
+    let psk = load_psk(cli.psk_file.as_deref(), "ELYTRA_PSK")?;
+
+    if let Some(Commands::Bridge(args)) = cli.command {
+        let device = open_device(&args.from, psk, cli.record.as_deref())?;
+        return run_bridge(args, device)
+    }
+    if let Some(Commands::Decode(args)) = cli.command {
+        return run_decode(args)
+    }
+    if let Some(Commands::Mock(args)) = cli.command {
+        return run_mock(args, cli.record.as_deref())
+    }
+
+    let Some(device_type) = cli.device else {
+        AppArgs::command().error(clap::error::ErrorKind::MissingRequiredArgument, "--device is required").exit()
+    };
+    let mut device = open_device(&device_type, psk, cli.record.as_deref())?;
 
-    let device: Box<dyn ElytraDevice> = match cli.device {
-        DeviceType::Wasm(path) => Box::new(WasmDevice::new(&path)?),
-        DeviceType::Tcp(addrs) => Box::new(TcpDevice::new(addrs.as_slice())?),
-        DeviceType::Serial =>  Err("Serial support is not implemented".to_owned())?
+    let pin = match std::env::var("ELYTRA_PIN") {
+        Ok(pin) => Some(pin),
+        Err(_) if cli.login => Some(prompt_secret("PIN")?),
+        Err(_) => None,
     };
+    if let Some(pin) = &pin {
+        let res = device.login(cli.access, pin);
+        // the login command contains the secret, so it is left out of the printed log
+        device.get_log();
+        res.map_err(|e| format!("Login failed: {e}"))?;
+    }
 
     match cli.command.unwrap_or(Commands::Tui) {
         Commands::Tui => tui::run(device),
         Commands::Query(args) => run_query(device, args),
         Commands::Info => run_info(device),
         Commands::Value(ValueArgs{entry, index}) => run_value(device, entry, index),
+        Commands::Set(SetArgs{entry, index, value}) => run_set(device, entry, index, value),
+        Commands::SyncTime(ValueArgs{entry, index}) => run_sync_time(device, entry, index),
         Commands::Sections => run_sections(device),
+        Commands::Group(command) => run_group(device, command),
+        Commands::Action(ActionArgs{index, args, detach, timeout}) => run_action(device, index, args, detach, Duration::from_secs(timeout)),
+        Commands::Job(command) => run_job(device, command),
+        Commands::Flash(args) => run_flash(device, args),
+        Commands::Blob(command) => run_blob(device, command),
+        Commands::Import { file } => run_import(device, file),
+        Commands::Reset(ResetArgs{index, all}) => run_reset(device, index, all),
+        Commands::Bridge(_) => unreachable!("bridges are run without opening the device"),
+        Commands::Decode(_) => unreachable!("decoding doesn't need a device"),
+        Commands::Mock(_) => unreachable!("mocks are run without opening the device"),
     }
 
 }
 
+/// Reads a secret from the terminal without echoing it
+fn prompt_secret(prompt: &str) -> Result<String, Box<dyn Error>> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+    eprint!("{prompt}: ");
+    std::io::stderr().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let mut secret = String::new();
+    let res = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(secret),
+            KeyCode::Backspace => { secret.pop(); },
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break Err("Cancelled".into()),
+            KeyCode::Char(c) => secret.push(c),
+            _ => {},
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    eprintln!();
+    res
+}
+
+#[derive(Debug, Args)]
+struct BridgeArgs {
+    /// Device to expose (TCP address, serial:<port> or wasm file)
+    #[arg(long, value_parser = parse_device_type)]
+    from: DeviceType,
+
+    /// Where to expose it: tcp:<addr>, ws:<addr> or pty for a pseudo-terminal speaking the serial protocol
+    #[arg(long, value_parser = parse_listen)]
+    to: Listen,
+
+    /// File with the pre-shared key (64 hex digits) that TCP clients need to set up an encrypted session with,
+    /// the key is taken from ELYTRA_LISTEN_PSK if not given
+    #[arg(long)]
+    listen_psk_file: Option<PathBuf>,
+
+    /// Origin of web pages allowed to connect over WebSocket, defaults to pages served from localhost
+    #[arg(long = "origin")]
+    origins: Vec<String>,
+}
+
+fn open_device(device_type: &DeviceType, psk: Option<[u8; 32]>, record: Option<&Path>) -> Result<Box<dyn ElytraDevice>, Box<dyn Error>> {
+    let device = device_type.open(psk)?;
+    Ok(match record {
+        Some(path) => Box::new(RecordingDevice::create(device, path, &device_type.to_string())?),
+        None => device,
+    })
+}
+
+fn run_bridge(args: BridgeArgs, device: Box<dyn ElytraDevice>) -> Result<(), Box<dyn Error>> {
+    let device = SharedDevice::new(device);
+    elytra_cli::bridge::run(device, &args.to, BridgeOptions {
+        psk: load_psk(args.listen_psk_file.as_deref(), "ELYTRA_LISTEN_PSK")?,
+        origins: args.origins,
+    })
+}
+
+#[derive(Debug, Args)]
+struct MockArgs {
+    /// TOML file describing the entries, layout and values of the device
+    schema: PathBuf,
+
+    /// Where to serve it: tcp:<addr>, ws:<addr> or pty for a pseudo-terminal speaking the serial protocol
+    #[arg(long, value_parser = parse_listen, default_value = "tcp:localhost:48000")]
+    to: Listen,
+
+    /// JSON file to keep written prop values in, so that they are kept across restarts
+    #[arg(long)]
+    state: Option<PathBuf>,
+
+    /// File with the pre-shared key (64 hex digits) that TCP clients need to set up an encrypted session with,
+    /// the key is taken from ELYTRA_LISTEN_PSK if not given
+    #[arg(long)]
+    listen_psk_file: Option<PathBuf>,
+
+    /// Origin of web pages allowed to connect over WebSocket, defaults to pages served from localhost
+    #[arg(long = "origin")]
+    origins: Vec<String>,
+}
+
+fn run_mock(args: MockArgs, record: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut mock = MockDevice::load(&args.schema)?;
+    if let Some(state) = args.state {
+        mock = mock.with_state(state)?;
+    }
+    let mut device: Box<dyn ElytraDevice> = Box::new(mock);
+    if let Some(path) = record {
+        device = Box::new(RecordingDevice::create(device, path, &format!("mock {}", args.schema.display()))?);
+    }
+    elytra_cli::bridge::run(SharedDevice::new(device), &args.to, BridgeOptions {
+        psk: load_psk(args.listen_psk_file.as_deref(), "ELYTRA_LISTEN_PSK")?,
+        origins: args.origins,
+    })
+}
+
+#[derive(Debug, Args)]
+struct DecodeArgs {
+    /// Frames in hex, alternating between requests and responses. Prefix a frame with `>` or `<`
+    /// to mark it as a request or response. Frames are read from stdin, one per line, if none are given
+    frames: Vec<String>,
+}
+
+fn run_decode(args: DecodeArgs) -> Result<(), Box<dyn Error>> {
+    let frames = match args.frames.is_empty() {
+        true => std::io::stdin().lines().collect::<Result<Vec<_>, _>>()?,
+        false => args.frames,
+    };
+    let mut request = None;
+    for frame in frames.iter().map(|f| f.trim()).filter(|f| !f.is_empty() && !f.starts_with('#')) {
+        let (is_request, hex) = match (frame.strip_prefix('>'), frame.strip_prefix('<')) {
+            (Some(hex), _) => (true, hex),
+            (_, Some(hex)) => (false, hex),
+            _ => (request.is_none(), frame),
+        };
+        let bytes = parse_hex_frame(hex).map_err(|e| format!("{e}: \"{frame}\""))?;
+        if is_request {
+            println!("{} {}", "~>".bright_green(), describe_request(&bytes));
+            request = Some(bytes);
+        } else {
+            // without the request, only the status and payload are known
+            let description = describe_response(&request.take().unwrap_or([0; 64]), &bytes);
+            println!("{} {}", "<~".bright_magenta(), description);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Args)]
 struct ValueArgs {
     entry: char,
     index: u8,
 }
 
+#[derive(Debug, Args)]
+struct SetArgs {
+    entry: char,
+    index: u8,
+    value: String,
+}
+
+#[derive(Debug, Args)]
+struct ResetArgs {
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    index: Option<u8>,
+    /// Reset every prop, including those of repeated section instances
+    #[arg(long)]
+    all: bool,
+}
+
+#[derive(Debug, Args)]
+struct ActionArgs {
+    index: u8,
+    /// Parameter values, in order
+    args: Vec<String>,
+    /// Don't wait for long-running actions to finish, just print the job id
+    #[arg(long)]
+    detach: bool,
+    /// Seconds to wait for long-running actions before giving up
+    #[arg(long, default_value_t = 300)]
+    timeout: u64,
+}
+
+#[derive(Debug, Args)]
+struct FlashArgs {
+    image: PathBuf,
+    /// Number of times a failed write is retried before giving up
+    #[arg(long, default_value_t = 3)]
+    retries: usize,
+    /// Only transfer and verify the image, without committing it
+    #[arg(long)]
+    no_commit: bool,
+}
+
 #[derive(Debug, Args)]
 struct QueryArgs {
     entry: char,
@@ -104,14 +390,264 @@ struct QueryArgs {
 }
 
 fn run_value(mut device: Box<dyn ElytraDevice + 'static>, entry_type: char, index: u8) -> Result<(), Box<dyn Error>> {
-    let res = device.get_value((entry_type as u8).try_into()?, index);
-    if let Err(e) = res {
-        println!("Error: {e:?}");
+    let entry_type: EntryType = (entry_type as u8).try_into()?;
+    let res = device.get_entry(entry_type as u8, index).and_then(|entry| {
+        let value = device.get_value(entry_type, index)?;
+        let default = device.get_default(entry_type, index).ok();
+        Ok((entry, value, default))
+    });
+    print_log(device.take_log());
+    match res {
+        Ok((entry, value, default)) => {
+            let value = format_entry_value(&entry, &value[1..])?;
+            match default.map(|d| format_entry_value(&entry, &d[1..])).transpose()? {
+                Some(default) if default != value => {
+                    println!("{}: {} {}", entry.name.bright_yellow(), value, format!("(default: {default})").dimmed());
+                },
+                _ => println!("{}: {}", entry.name.bright_yellow(), value),
+            }
+        },
+        Err(e) => println!("Error: {e:?}"),
+    }
+    Ok(())
+}
+
+fn run_set(mut device: Box<dyn ElytraDevice + 'static>, entry_type: char, index: u8, value: String) -> Result<(), Box<dyn Error>> {
+    let entry_type: EntryType = (entry_type as u8).try_into()?;
+    let entry = device.get_entry(entry_type as u8, index)?;
+    let payload = parse_entry_value(&entry, &value)?;
+    let res = match entry_type {
+        EntryType::Prop => device.check_writable(index),
+        _ => Ok(()),
+    }.and_then(|_| device.set_value(entry_type, index, &payload));
+    print_log(device.take_log());
+    res
+}
+
+fn run_import(mut device: Box<dyn ElytraDevice + 'static>, file: PathBuf) -> Result<(), Box<dyn Error>> {
+    let input = std::fs::read_to_string(&file)?;
+    let prop_count = device.get_info()?.prop_count as usize;
+    let props = device.get_entries(EntryType::Prop as u8, prop_count)?;
+    print_log(device.take_log());
+
+    let mut writes = vec![];
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            Err(format!("{}:{}: expected `Name = value`", file.display(), line_no + 1))?
+        };
+        let (name, value) = (name.trim(), value.trim());
+        let Some(index) = props.iter().position(|p| p.name.eq_ignore_ascii_case(name)) else {
+            Err(format!("{}:{}: no prop named \"{name}\"", file.display(), line_no + 1))?
+        };
+        let payload = parse_entry_value(&props[index], value)
+            .map_err(|e| format!("{}:{}: {e}", file.display(), line_no + 1))?;
+        writes.push((index, payload));
+    }
+
+    let res = device.transaction(|device| {
+        for (index, payload) in &writes {
+            device.set_value(EntryType::Prop, *index as u8, payload)?;
+        }
+        Ok(())
+    });
+    print_log(device.take_log());
+    res?;
+    for (index, _) in &writes {
+        println!("Imported {}", props[*index].name.bright_yellow());
+    }
+    Ok(())
+}
+
+fn run_reset(mut device: Box<dyn ElytraDevice + 'static>, index: Option<u8>, all: bool) -> Result<(), Box<dyn Error>> {
+    let indices = match index {
+        Some(index) if !all => vec![index],
+        _ => (0..device.get_info()?.prop_count).collect(),
+    };
+    let mut props = vec![];
+    for index in indices {
+        let entry = device.get_entry(EntryType::Prop as u8, index)?;
+        let value = device.get_value(EntryType::Prop, index)?;
+        let default = device.get_default(EntryType::Prop, index)?;
+        props.push((entry, value, default));
+    }
+
+    let res = match index {
+        Some(index) if !all => device.reset_prop(index),
+        _ => device.reset_all(),
+    };
+    print_log(device.take_log());
+    res?;
+    for (entry, value, default) in &props {
+        let value = format_entry_value(entry, &value[1..])?;
+        let default = format_entry_value(entry, &default[1..])?;
+        if value != default {
+            println!("{}: {} → {}", entry.name.bright_yellow(), value.dimmed(), default);
+        }
     }
-    print_log(device.get_log());
     Ok(())
 }
 
+fn run_sync_time(mut device: Box<dyn ElytraDevice + 'static>, entry_type: char, index: u8) -> Result<(), Box<dyn Error>> {
+    let res = device.sync_time((entry_type as u8).try_into()?, index);
+    print_log(device.take_log());
+    res
+}
+
+fn run_action(mut device: Box<dyn ElytraDevice + 'static>, index: u8, args: Vec<String>, detach: bool, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    let entry = device.get_entry(EntryType::Action as u8, index)?;
+    let signature = device.get_action_signature(&entry, index)?;
+    if args.len() > signature.params.len() {
+        Err(format!("{} takes {} parameter(s) {}", entry.name, signature.params.len(), signature))?
+    }
+    let payloads = args.iter().zip(&signature.params)
+        .map(|(arg, param)| parse_entry_value(param, arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if entry.flags.contains(ExtraFlags::IsLongRunning) {
+        let id = device.start_job(index, &payloads);
+        print_log(device.take_log());
+        let id = id?;
+        println!("Started job #{}", id.bright_blue());
+        if detach {
+            return Ok(())
+        }
+        let status = device.wait_for_job(id, timeout, print_job_progress)?;
+        println!();
+        return match status.state {
+            JobState::Done => Ok(()),
+            state => Err(format!("Job {state:?}: {}", status.message))?,
+        }
+    }
+
+    let res = device.run_action(index, &payloads);
+    print_log(device.take_log());
+    let res = res?;
+    if let Some(result) = &signature.result {
+        println!("{}: {}", result.name.bright_yellow(), format_entry_value(result, &res[1..])?);
+    }
+    Ok(())
+}
+
+fn print_job_progress(status: &JobStatus) {
+    print!("\r{} {:40}", status.progress_bar(30).bright_green(), status.message);
+    let _ = std::io::stdout().flush();
+}
+
+fn run_job(mut device: Box<dyn ElytraDevice + 'static>, command: JobCommands) -> Result<(), Box<dyn Error>> {
+    let res = match command {
+        JobCommands::Status { id } => device.get_job_status(id).map(|status| {
+            println!("Job #{} {:?}: {} {}", id.bright_blue(), status.state, status.progress_bar(30), status.message);
+        }),
+        JobCommands::Cancel { id } => device.cancel_job(id),
+    };
+    print_log(device.take_log());
+    res
+}
+
+fn run_flash(mut device: Box<dyn ElytraDevice + 'static>, args: FlashArgs) -> Result<(), Box<dyn Error>> {
+    let image = std::fs::read(&args.image)?;
+    let size = image.len();
+    let res = device.flash_image(&image, args.retries, |device, written| {
+        // the transfer takes many commands, so the log is only printed for failures
+        device.get_log();
+        print_transfer_progress(written, size);
+    });
+    println!();
+    let crc = res.inspect_err(|_| print_log(device.take_log()))?;
+    println!("Verified image, CRC-32 {}", format!("{crc:08x}").bright_blue());
+    if args.no_commit {
+        return Ok(())
+    }
+    let res = device.dfu_commit();
+    print_log(device.take_log());
+    res?;
+    println!("Committed image");
+    Ok(())
+}
+
+fn run_blob(mut device: Box<dyn ElytraDevice + 'static>, command: BlobCommands) -> Result<(), Box<dyn Error>> {
+    let name = match &command {
+        BlobCommands::Put { name, .. } | BlobCommands::Get { name, .. } => name,
+    };
+    let (prop, entry) = device.find_prop(name)?;
+    if entry.variant != ValueType::Blob as u8 {
+        Err(format!("{} is not a blob prop", entry.name))?
+    }
+    let res = match command {
+        BlobCommands::Put { file, .. } => {
+            let data = std::fs::read(&file)?;
+            let max_size = blob_max_size(&entry) as usize;
+            if data.len() > max_size {
+                Err(format!("{} can hold at most {max_size} bytes, {} is {} bytes", entry.name, file.display(), data.len()))?
+            }
+            device.write_blob(prop, &data, |device, written| {
+                device.get_log();
+                print_transfer_progress(written, data.len());
+            }).map(|_| data)
+        },
+        BlobCommands::Get { file, .. } => device.read_blob(prop, |device, read, total| {
+            device.get_log();
+            print_transfer_progress(read, total);
+        }).and_then(|data| {
+            std::fs::write(&file, &data)?;
+            Ok(data)
+        }),
+    };
+    println!();
+    let data = res.inspect_err(|_| print_log(device.take_log()))?;
+    println!("Transferred {} bytes", data.len().bright_blue());
+
+    if blob_has_digest(&entry) {
+        let digest = device.get_blob_digest(prop);
+        print_log(device.take_log());
+        let digest = digest?;
+        if digest[..] != Sha256::digest(&data)[..] {
+            Err("SHA-256 digest of the device blob does not match")?
+        }
+        println!("Verified SHA-256 {}", format_hex(&digest, "").bright_blue());
+    }
+    Ok(())
+}
+
+fn print_transfer_progress(done: usize, total: usize) {
+    let status = JobStatus {
+        id: 0,
+        state: JobState::Running,
+        progress: (done * 100 / total.max(1)) as u8,
+        message: format!("{done}/{total} bytes"),
+    };
+    print_job_progress(&status);
+}
+
+fn run_group(mut device: Box<dyn ElytraDevice + 'static>, command: GroupCommands) -> Result<(), Box<dyn Error>> {
+    let res = match command {
+        GroupCommands::List { section } => device.list_instances(section).map(|instances| {
+            println!("Instances: {:?}", instances.bright_blue());
+        }),
+        GroupCommands::Add { section } => device.add_instance(section).map(|instance| {
+            println!("Added instance #{}", instance.bright_blue());
+        }),
+        GroupCommands::Remove { section, instance } => device.remove_instance(section, instance),
+        GroupCommands::Value { section, instance, prop } => device.get_entry(b'p', prop).and_then(|entry| {
+            let value = device.get_group_value(section, instance, prop)?;
+            if value[0] == 1 {
+                println!("{}[{}]: {}", entry.name.bright_yellow(), instance, format_entry_value(&entry, &value[1..])?);
+            }
+            Ok(())
+        }),
+        GroupCommands::Set { section, instance, prop, value } => device.get_entry(b'p', prop).and_then(|entry| {
+            let payload = parse_entry_value(&entry, &value)?;
+            device.set_group_value(section, instance, prop, &payload)
+        }),
+    };
+    print_log(device.take_log());
+    res
+}
+
 fn run_info(mut device: Box<dyn ElytraDevice + 'static>) -> Result<(), Box<dyn Error>> {
     let info = device.get_info()?;
     println!("Version: {}", info.proto_version);
@@ -125,7 +661,7 @@ fn run_info(mut device: Box<dyn ElytraDevice + 'static>) -> Result<(), Box<dyn E
 
 fn run_sections(mut device: Box<dyn ElytraDevice + 'static>) -> Result<(), Box<dyn Error>> {
     let info = device.get_info()?;
-    print_log(device.get_log());
+    print_log(device.take_log());
     println!("Querying {} section(s)...", info.section_count.bright_blue());
 
     // println!("")
@@ -134,74 +670,127 @@ fn run_sections(mut device: Box<dyn ElytraDevice + 'static>) -> Result<(), Box<d
     for i in 0..info.section_count {
         println!(" ~ Querying section #{} basic", i.bright_blue());
         let mut section_entry = device.get_entry(b's', i)?;
-        print_log(device.get_log());
+        print_log(device.take_log());
         println!(" ~ Querying section #{} layout", i.bright_blue());
         let layout_ids = device.get_layout(i)?;
-        print_log(device.get_log());
+        print_log(device.take_log());
         println!(" ~ Querying section extras...");
         if section_entry.flags.contains(ExtraFlags::HasHelp) {
             section_entry.help = Some(device.get_extra(b's', i, b'h')?);
-            print_log(device.get_log());
+            print_log(device.take_log());
         }
         if section_entry.flags.contains(ExtraFlags::HasIcon) {
             section_entry.icon = Some(device.get_extra(b's', i, b'i')?);
-            print_log(device.get_log());
+            print_log(device.take_log());
         }
 
         println!(" ~ Querying {} fields(s)...", layout_ids.len().bright_blue());
-        let mut layout: Vec<(LayoutEntry, Entry)>  = layout_ids.into_iter().map(|layout| {
-            match layout {
-                LayoutEntry::Prop(li) => device.get_entry(b'c', li),
-                LayoutEntry::Info(li) => device.get_entry(b'i', li),
-            }.map(|entry| (layout, entry) ).unwrap()
-        }).collect();
-        print_log(device.get_log());
+        let mut layout: Vec<(LayoutEntry, Entry)> = Vec::with_capacity(layout_ids.len());
+        for le in layout_ids {
+            layout.push((le, device.get_layout_entry(i, le)?));
+        }
+        print_log(device.take_log());
         
         println!(" ~ Querying field extras...");
         for (le, entry) in layout.iter_mut() {
-            let (vt, index) = match le {
-                LayoutEntry::Prop(li) => (b'c', li),
-                LayoutEntry::Info(li) => (b'i', li),
+            if let LayoutEntry::Action(ai) = le
+                && entry.constraints[..2] != [0, 0] {
+                entry.signature = Some(device.get_action_signature(entry, *ai)?);
+            }
+            // Nested sections are queried on their own
+            let Some((vt, index)) = le.entry_ref().filter(|(vt, _)| *vt != EntryType::Section as u8) else {
+                continue
             };
     
             if entry.flags.contains(ExtraFlags::HasHelp) {
-                entry.help = Some(device.get_extra(vt, *index, b'h')?)
+                entry.help = Some(device.get_extra(vt, index, b'h')?)
             }
             if entry.flags.contains(ExtraFlags::HasIcon) {
-                entry.icon = Some(device.get_extra(vt, *index, b'i')?)
+                entry.icon = Some(device.get_extra(vt, index, b'i')?)
             }
         }
-        print_log(device.get_log());
+        print_log(device.take_log());
+
+        let conditions = if section_entry.flags.contains(ExtraFlags::HasConditions) {
+            println!(" ~ Querying field conditions...");
+            device.get_conditions(i, layout.len())?
+        } else {
+            vec![]
+        };
+        print_log(device.take_log());
 
         let section = Section {
             entry: section_entry,
             layout,
+            conditions,
         };
         sections.push(section);
     }
 
     println!();
     println!("{}", "Sections:".bright_white());
-    for (i, section) in sections.iter().enumerate() {
-        print!("- Section #{}: {}", i.bright_cyan(), section.entry.name.bright_yellow());
-        if let Some(help) = &section.entry.help {
-            println!(" {}", help.bright_black());
-        } else {
-            println!();
-        }
-        if let Some(icon) = &section.entry.icon {
-            println!("  Icon: {}", icon.bright_white());
-        }
+    let mut visited = Vec::new();
+    for root in elytra_cli::root_sections(&sections) {
+        print_section(&sections, root, 0, &mut visited);
+    }
+    Ok(())
+}
+
+fn print_section(sections: &[Section], index: usize, depth: usize, visited: &mut Vec<usize>) {
+    let indent = "  ".repeat(depth);
+    let Some(section) = sections.get(index) else {
+        println!("{indent}- Section #{}: {}", index.bright_cyan(), "Missing".bright_red());
+        return
+    };
+    if visited.contains(&index) {
+        println!("{indent}- Section #{}: {} ({})", index.bright_cyan(), section.entry.name.bright_yellow(), "Cycle".bright_red());
+        return
+    }
+    visited.push(index);
+
+    print!("{indent}- Section #{}: {}", index.bright_cyan(), section.entry.name.bright_yellow());
+    if section.entry.flags.contains(ExtraFlags::IsRepeated) {
+        print!(" ({} x{})", "Repeated".bright_blue(), section.entry.constraints[0]);
+    }
+    if let Some(help) = &section.entry.help {
+        println!(" {}", help.bright_black());
+    } else {
         println!();
+    }
+    if let Some(icon) = &section.entry.icon {
+        println!("{indent}  Icon: {}", icon.bright_white());
+    }
+    println!();
 
-        for (l, entry) in &section.layout {
-            let (field_type, ft_col) = match l {
-                LayoutEntry::Prop(_) => ("C", AnsiColors::BrightGreen),
-                LayoutEntry::Info(_) => ("I", AnsiColors::BrightMagenta),
-            };
+    for (position, (l, entry)) in section.layout.iter().enumerate() {
+        let (field_type, ft_col) = match l {
+            LayoutEntry::Prop(_) => ("C", AnsiColors::BrightGreen),
+            LayoutEntry::Info(_) => ("I", AnsiColors::BrightMagenta),
+            LayoutEntry::Action(_) => ("A", AnsiColors::BrightCyan),
+            LayoutEntry::Section(si) => {
+                print_section(sections, *si as usize, depth + 1, visited);
+                continue
+            },
+            LayoutEntry::Heading(_) => {
+                println!("{indent}  {}", format!("== {} ==", entry.name).bright_white());
+                println!();
+                continue
+            },
+            LayoutEntry::Separator => {
+                println!("{indent}  {}", "────────".bright_black());
+                println!();
+                continue
+            },
+        };
+
+        if let LayoutEntry::Action(_) = l {
+            print!("{indent}  [{}] {}", field_type.color(ft_col), entry.name.bright_yellow());
+            if let Some(signature) = &entry.signature {
+                print!(" {}", signature.bright_blue());
+            }
+        } else {
             let vt = ValueType::try_from(entry.variant).unwrap();
-            
-            print!("  [{}] {} {}", 
+            print!("{indent}  [{}] {} {}", 
                 field_type.color(ft_col), 
                 vt.to_string().bright_blue(), 
                 entry.name.bright_yellow());
@@ -211,25 +800,28 @@ fn run_sections(mut device: Box<dyn ElytraDevice + 'static>) -> Result<(), Box<d
             } else {
                 print!(" ({})", "Writable".bright_green());
             }
-            if let Some(help) = &entry.help {
-                println!(" {}", help.bright_black());
-            } else {
-                println!();
-            }
+        }
+        if entry.access() != AccessLevel::Public {
+            print!(" ({})", entry.access().bright_magenta());
+        }
+        if let Some(help) = &entry.help {
+            println!(" {}", help.bright_black());
+        } else {
+            println!();
+        }
 
-            // print!("      Flags: ");
-    
-            if let Some(icon) = &entry.icon {
-                println!("      Icon: {}", icon.bright_white());
-            }
+        // print!("      Flags: ");
 
-            println!();
-            
-            
+        if let Some(icon) = &entry.icon {
+            println!("{indent}      Icon: {}", icon.bright_white());
+        }
+        if let Some(condition) = section.condition(position) {
+            println!("{indent}      Condition: {}", condition.bright_white());
         }
+
         println!();
     }
-    Ok(())
+    println!();
 }
 
 
@@ -239,18 +831,31 @@ fn run_query(mut device: Box<dyn ElytraDevice + 'static>, args: QueryArgs) -> Re
     let index: u8 = args.index;
     let prop: QueryTargetKey = args.prop;
     let _ = device.send_command(&[b'q', entry as u8, index, prop as u8])?;
-    print_log(device.get_log());
+    print_log(device.take_log());
 
     Ok(())
 }
 
-fn print_log(log: Vec<([u8; 64],[u8; 64])>) {
-    for (out_bytes, in_bytes) in log {
-        eprint!("\r{} ", "~>".bright_green());
-        print_bytes(&out_bytes);
-        eprint!("\r{} ", "<~".bright_magenta());
-        print_bytes(&in_bytes);
-        eprintln!();
+fn print_log(log: Vec<LogItem>) {
+    for item in log {
+        match item {
+            LogItem::Exchange(out_bytes, in_bytes) => {
+                eprint!("\r{} ", "~>".bright_green());
+                print_bytes(&out_bytes);
+                eprint!("\r{} ", "<~".bright_magenta());
+                print_bytes(&in_bytes);
+                eprintln!("{}\n", describe_exchange(&out_bytes, &in_bytes).bright_blue());
+            },
+            LogItem::Device(level, message) => {
+                let color = match level {
+                    log::Level::Error => AnsiColors::BrightRed,
+                    log::Level::Warn => AnsiColors::BrightYellow,
+                    log::Level::Info => AnsiColors::BrightGreen,
+                    log::Level::Debug | log::Level::Trace => AnsiColors::BrightBlack,
+                };
+                eprintln!("\r{} {} {message}", "#>".bright_black(), format!("{level:<5}").color(color));
+            },
+        }
     }
 }
 
@@ -0,0 +1,75 @@
+use std::{error::Error, time::Duration};
+
+use elytra_conf::secure::KEY_LEN;
+use serialport::{SerialPort, TTYPort};
+
+use crate::{device::SharedDevice, serial::serve_lines, tcp::TcpServer, ws::WsServer};
+
+/// Where a bridged device is exposed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listen {
+    /// Binary frames on a TCP address
+    Tcp(String),
+    /// Binary frames in WebSocket messages
+    Ws(String),
+    /// Serial protocol lines on a new pseudo-terminal
+    Pty,
+}
+
+pub fn parse_listen(s: &str) -> Result<Listen, String> {
+    match s.split_once(':') {
+        Some(("tcp", addr)) => Ok(Listen::Tcp(addr.to_owned())),
+        Some(("ws", addr)) => Ok(Listen::Ws(addr.to_owned())),
+        None if s == "pty" => Ok(Listen::Pty),
+        _ => Err(format!("Not a valid listen spec: \"{s}\" (tcp:<addr>, ws:<addr> or pty)")),
+    }
+}
+
+pub struct BridgeOptions {
+    /// Pre-shared key that TCP clients need to set up an encrypted session with
+    pub psk: Option<[u8; KEY_LEN]>,
+    /// Origins allowed to connect over WebSocket, see [`WsServer::with_origins`]
+    pub origins: Vec<String>,
+}
+
+/// Exposes `device` on `to` until the listener fails, converting between the encodings of the transports
+pub fn run(device: SharedDevice, to: &Listen, options: BridgeOptions) -> Result<(), Box<dyn Error>> {
+    match to {
+        Listen::Tcp(addr) => {
+            let mut server = TcpServer::bind(addr)?;
+            if let Some(psk) = options.psk {
+                server = server.with_psk(psk);
+            }
+            eprintln!("Bridging to tcp:{}", server.local_addr()?);
+            server.serve(move |bytes| device.send(bytes))?;
+        },
+        Listen::Ws(addr) => {
+            let server = WsServer::bind(addr)?.with_origins(options.origins);
+            eprintln!("Bridging to ws://{}", server.local_addr()?);
+            server.serve(move || Ok(Box::new(device.clone())))?;
+        },
+        Listen::Pty => {
+            let (mut master, slave) = TTYPort::pair()?;
+            // blocks until the client sends a line, rather than timing out
+            master.set_timeout(Duration::from_secs(24 * 60 * 60))?;
+            eprintln!("Bridging to serial:{}", slave.name().unwrap_or_default());
+            // the slave end is kept open so that clients can come and go
+            let _slave = slave;
+            serve_lines(master, |bytes| device.send(bytes))?;
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn listen_specs() {
+        assert_eq!(Ok(Listen::Tcp("0.0.0.0:48000".into())), parse_listen("tcp:0.0.0.0:48000"));
+        assert_eq!(Ok(Listen::Ws("localhost:48001".into())), parse_listen("ws:localhost:48001"));
+        assert_eq!(Ok(Listen::Pty), parse_listen("pty"));
+        assert!(parse_listen("usb:1").is_err());
+    }
+}
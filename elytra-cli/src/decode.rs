@@ -0,0 +1,212 @@
+use std::error::Error;
+
+use color_eyre::eyre::eyre;
+use elytra_conf::{auth::AccessLevel, command::{CommandError, CommandKey}, config::{EntryType, QueryTargetKey}, entry::ExtraFlags, values::{ColorFormat, ValueType}};
+
+use crate::{format::format_hex, serial::{trimmed, unhex}};
+
+/// Parses the hex dump of a frame, as copied from a logic analyzer or USB capture,
+/// e.g. `72 01 00 00`, `0x72, 0x01` or `7201`. Missing bytes are zero padding.
+pub fn parse_hex_frame(input: &str) -> Result<[u8; 64], Box<dyn Error>> {
+    let digits: String = input.split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .filter(|token| !token.is_empty())
+        .map(|token| token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token))
+        .map(|token| if token.len() == 1 { format!("0{token}") } else { token.to_owned() })
+        .collect();
+    if digits.is_empty() {
+        return Err(eyre!("Empty frame"))?
+    }
+    let mut bytes = [0u8; 64];
+    unhex(&digits, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Splits a type name into words, e.g. `ReadProp` into `Read Prop`
+fn words(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() && !out.is_empty() {
+            out.push(' ');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Hex of the bytes without the zero padding, followed by the text if they are printable
+fn payload(bytes: &[u8]) -> String {
+    let bytes = trimmed(bytes);
+    match bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') && !bytes.is_empty() {
+        true => format!("{} '{}'", format_hex(bytes, " "), String::from_utf8_lossy(bytes)),
+        false => format_hex(bytes, " "),
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    format!("'{}'", String::from_utf8_lossy(trimmed(bytes)))
+}
+
+fn entry_type_name(b: u8) -> String {
+    EntryType::try_from(b).map_or_else(|_| format!("Entry {b:02x}"), |t| format!("{t:?}"))
+}
+
+/// Describes a request frame, e.g. `Query Prop 3 Field`
+pub fn describe_request(bytes: &[u8; 64]) -> String {
+    let Ok(key) = CommandKey::try_from(bytes[0]) else {
+        return format!("Unknown command {:02x} {}", bytes[0], payload(&bytes[1..]))
+    };
+    let name = words(&format!("{key:?}"));
+    let args = &bytes[1..];
+    match key {
+        CommandKey::ReadProp | CommandKey::ReadInfo | CommandKey::ResetProp | CommandKey::JobStatus | CommandKey::CancelJob => {
+            format!("{name} {}", args[0])
+        },
+        CommandKey::WriteProp | CommandKey::WriteInfo => format!("{name} {} = {}", args[0], payload(&args[1..])),
+        CommandKey::Query => {
+            let target = QueryTargetKey::try_from(args[2])
+                .map_or_else(|_| format!("target {:02x}", args[2]), |t| format!("{t:?}"));
+            let mut out = format!("Query {} {} {target}", entry_type_name(args[0]), args[1]);
+            match trimmed(&args[3..]) {
+                [] => {},
+                [lo] => out += &format!(" {lo}"),
+                [lo, hi] => out += &format!(" {}", u16::from_le_bytes([*lo, *hi])),
+                rest => out += &format!(" {}", format_hex(rest, " ")),
+            }
+            out
+        },
+        CommandKey::Action => match trimmed(&args[1..]) {
+            [] => format!("Action {}", args[0]),
+            _ => format!("Action {} ({})", args[0], payload(&args[1..])),
+        },
+        CommandKey::ReadGroupProp => format!("{name}: section {}, instance {}, prop {}", args[0], args[1], args[2]),
+        CommandKey::WriteGroupProp => format!("{name}: section {}, instance {}, prop {} = {}", args[0], args[1], args[2], payload(&args[3..])),
+        CommandKey::AddInstance | CommandKey::ListInstances => format!("{name}: section {}", args[0]),
+        CommandKey::RemoveInstance => format!("{name}: section {}, instance {}", args[0], args[1]),
+        // the secret is never shown
+        CommandKey::Login => format!("Login as {}", AccessLevel::try_from(args[0]).map_or_else(|_| format!("level {}", args[0]), |l| l.to_string())),
+        _ => match trimmed(args) {
+            [] => name,
+            _ => format!("{name} {}", payload(args)),
+        },
+    }
+}
+
+/// Describes the response to `request`, e.g. `flags [ReadOnly], type Integer, range 0..100, name 'Volume'`
+pub fn describe_response(request: &[u8; 64], response: &[u8; 64]) -> String {
+    if response[0] != 1 {
+        let error = CommandError::try_from(response[1]).map_or_else(|_| format!("Error {:02x}", response[1]), |e| format!("Error {e}"));
+        return match trimmed(&response[2..]) {
+            [] => error,
+            message => format!("{error}: {}", String::from_utf8_lossy(message)),
+        }
+    }
+    match (CommandKey::try_from(request[0]), QueryTargetKey::try_from(request[3])) {
+        (Ok(CommandKey::Query), Ok(QueryTargetKey::Field)) => describe_field(request[1], response),
+        (Ok(CommandKey::Query), Ok(QueryTargetKey::Icon | QueryTargetKey::Help | QueryTargetKey::Option | QueryTargetKey::Heading)) => text(&response[1..]),
+        (Ok(CommandKey::Meta), _) => format!(
+            "protocol v{}, {} sections, {} props, {} infos, {} actions",
+            response[1], response[2], response[3], response[4], response[5]
+        ),
+        (Ok(CommandKey::Login), _) => format!("granted {}", AccessLevel::try_from(response[1]).map_or_else(|_| format!("level {}", response[1]), |l| l.to_string())),
+        _ => match trimmed(&response[1..]) {
+            [] => "OK".to_owned(),
+            _ => format!("OK {}", payload(&response[1..])),
+        },
+    }
+}
+
+/// Describes a request and its response, e.g. `Read Prop 1 → OK 05`
+pub fn describe_exchange(request: &[u8; 64], response: &[u8; 64]) -> String {
+    format!("{} → {}", describe_request(request), describe_response(request, response))
+}
+
+fn describe_field(entry_type: u8, res: &[u8; 64]) -> String {
+    let flags = ExtraFlags::from_bits_truncate(u16::from_le_bytes([res[1], res[2]]));
+    let flags = flags.iter_names().map(|(name, _)| name).collect::<Vec<_>>().join(", ");
+    let variant = res[3];
+    let variant_name = ValueType::try_from(variant).map_or_else(|_| format!("{variant:02x}"), |vt| vt.to_string());
+    let constraints: [u8; 8] = res[4..12].try_into().unwrap();
+    let mut out = format!("flags [{flags}], type {variant_name}");
+    if let Some(constraints) = describe_constraints(entry_type, variant, res, constraints) {
+        out += &format!(", {constraints}");
+    }
+    out + &format!(", name {}", text(&res[12..]))
+}
+
+/// The constraints are packed according to the entry, see [`Constraints::bits`](elytra_conf::entry::Constraints::bits)
+fn describe_constraints(entry_type: u8, variant: u8, res: &[u8; 64], c: [u8; 8]) -> Option<String> {
+    if c == [0; 8] {
+        return None
+    }
+    let flags = ExtraFlags::from_bits_truncate(u16::from_le_bytes([res[1], res[2]]));
+    let u32_at = |i: usize| u32::from_le_bytes(c[i..i + 4].try_into().unwrap());
+    let u16_at = |i: usize| u16::from_le_bytes(c[i..i + 2].try_into().unwrap());
+    Some(match (EntryType::try_from(entry_type), ValueType::try_from(variant)) {
+        (Ok(EntryType::Action), _) => format!("{} params{}", c[0], if c[1] == 1 { ", has result" } else { "" }),
+        (Ok(EntryType::Section), _) => format!("{} instances", u64::from_le_bytes(c)),
+        (_, Ok(vt)) if vt == ValueType::Options || flags.contains(ExtraFlags::HasOptions) => {
+            format!("{} options, min {}, max {}", u32_at(0), u16_at(4), u16_at(6))
+        },
+        (_, Ok(ValueType::Integer)) => format!("range {}..{}", u32_at(0) as i32, u32_at(4) as i32),
+        (_, Ok(ValueType::Color)) => ColorFormat::try_from(c[0]).map_or_else(|_| format!("color format {:02x}", c[0]), |f| format!("{f:?}")),
+        (_, Ok(ValueType::Blob)) => format!("max size {}{}", u32_at(0), if c[4] == 1 { ", has digest" } else { "" }),
+        (_, Ok(ValueType::Text | ValueType::Secret | ValueType::Bytes)) => format!("max length {}", u64::from_le_bytes(c)),
+        _ => format!("constraints {}", format_hex(&c, " ")),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(bytes: &[u8]) -> [u8; 64] {
+        let mut frame = [0u8; 64];
+        frame[..bytes.len()].copy_from_slice(bytes);
+        frame
+    }
+
+    #[test]
+    fn hex_dumps() {
+        let expected = frame(b"r\x01");
+        assert_eq!(expected, parse_hex_frame("72 01 00 00").unwrap());
+        assert_eq!(expected, parse_hex_frame("0x72, 0x1").unwrap());
+        assert_eq!(expected, parse_hex_frame("7201").unwrap());
+        assert!(parse_hex_frame("72 0g").is_err());
+        assert!(parse_hex_frame("720").is_err());
+        assert!(parse_hex_frame("").is_err());
+    }
+
+    #[test]
+    fn field_query() {
+        let request = frame(b"qp\x03f");
+        assert_eq!("Query Prop 3 Field", describe_request(&request));
+
+        let flags = (ExtraFlags::ReadOnly | ExtraFlags::HasHelp).bits().to_le_bytes();
+        let response = frame(&[
+            &[1, flags[0], flags[1], ValueType::Integer as u8][..],
+            &(-1500i32).to_le_bytes(), &1500i32.to_le_bytes(),
+            b"Brightness Offset",
+        ].concat());
+        assert_eq!(
+            "flags [ReadOnly, HasHelp], type Integer, range -1500..1500, name 'Brightness Offset'",
+            describe_response(&request, &response)
+        );
+    }
+
+    #[test]
+    fn other_commands() {
+        assert_eq!("Write Prop 2 = 66 6f 6f 'foo'", describe_request(&frame(b"w\x02foo")));
+        assert_eq!("Query Prop 0 Option 9", describe_request(&frame(b"qp\x00o\x09")));
+        assert_eq!("Login as admin", describe_request(&frame(b"k\x02\x041234")));
+        assert_eq!("Meta", describe_request(&frame(b"m")));
+        assert_eq!(
+            "protocol v2, 1 sections, 4 props, 6 infos, 0 actions",
+            describe_response(&frame(b"m"), &frame(&[1, 2, 1, 4, 6]))
+        );
+        assert_eq!(
+            "Read Prop 9 → Error InvalidField: no such prop",
+            describe_exchange(&frame(b"r\x09"), &frame(b"\x00\x04no such prop"))
+        );
+        assert_eq!("Read Prop 1 → OK 05", describe_exchange(&frame(b"r\x01"), &frame(&[1, 5])));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{error::Error, fmt::Display, net::{SocketAddr, ToSocketAddrs}, path::PathBuf, sync::{Arc, Mutex}};
+
+use elytra_conf::secure::KEY_LEN;
+
+use crate::{ElytraDevice, mock::MockDevice, record::ReplayDevice, serial::SerialDevice, tcp::TcpDevice, wasm::WasmDevice};
+
+#[derive(Debug, Clone)]
+pub enum DeviceType {
+    Tcp(Vec<SocketAddr>),
+    /// Module built with `elytra_wasm!`, its props are stored next to it in `<name>.store.json`
+    Wasm(PathBuf),
+    Serial(PathBuf),
+    /// Answers from a session recorded with `--record`
+    Replay(PathBuf),
+    /// In-memory device described by a schema file, see [`MockDevice`]
+    Mock(PathBuf),
+}
+
+impl Display for DeviceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceType::Tcp(addrs) => write!(f, "tcp {}", addrs.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")),
+            DeviceType::Wasm(path) => write!(f, "wasm {}", path.display()),
+            DeviceType::Serial(path) => write!(f, "serial {}", path.display()),
+            DeviceType::Replay(path) => write!(f, "replay {}", path.display()),
+            DeviceType::Mock(path) => write!(f, "mock {}", path.display()),
+        }
+    }
+}
+
+impl DeviceType {
+    /// Opens a new session with the device, TCP connections are encrypted if `psk` is given
+    pub fn open(&self, psk: Option<[u8; KEY_LEN]>) -> Result<Box<dyn ElytraDevice>, Box<dyn Error>> {
+        Ok(match self {
+            DeviceType::Wasm(path) => Box::new(WasmDevice::new(path)?.with_store(path.with_extension("store.json"))?),
+            DeviceType::Tcp(addrs) => match psk {
+                Some(psk) => Box::new(TcpDevice::new_secure(addrs.as_slice(), psk)?),
+                None => Box::new(TcpDevice::new(addrs.as_slice())?),
+            },
+            DeviceType::Serial(path) => Box::new(SerialDevice::new(path)?),
+            DeviceType::Replay(path) => Box::new(ReplayDevice::load(path)?),
+            DeviceType::Mock(path) => Box::new(MockDevice::load(path)?),
+        })
+    }
+}
+
+pub fn parse_device_type(s: &str) -> Result<DeviceType, String> {
+    if let Ok(addrs) = ToSocketAddrs::to_socket_addrs(s) {
+        return Ok(DeviceType::Tcp(addrs.collect()))
+    }
+
+    if let Some(path) = s.strip_prefix("serial:") {
+       return Ok(DeviceType::Serial(PathBuf::from(path)))
+    }
+
+    if let Some(path) = s.strip_prefix("replay:") {
+       return Ok(DeviceType::Replay(PathBuf::from(path)))
+    }
+
+    if let Some(path) = s.strip_prefix("mock:") {
+       return Ok(DeviceType::Mock(PathBuf::from(path)))
+    }
+
+    let path = PathBuf::from(s);
+    if path.exists() && path.is_file() {
+        Ok(DeviceType::Wasm(path))
+    } else {
+        Err(format!("Not a valid device, serial:<port>, replay:<recording>, mock:<schema> or wasm file path: \"{}\"", s))
+    }
+}
+
+/// A device shared by several connections, every command holds the lock until its response
+#[derive(Clone)]
+pub struct SharedDevice {
+    device: Arc<Mutex<Box<dyn ElytraDevice>>>,
+    log: Vec<([u8; 64], [u8; 64])>,
+}
+
+impl SharedDevice {
+    pub fn new(device: Box<dyn ElytraDevice>) -> Self {
+        Self { device: Arc::new(Mutex::new(device)), log: vec![] }
+    }
+
+    pub fn send(&self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        self.device.lock().map_err(|_| "device lock poisoned")?.send_command_raw(bytes)
+    }
+}
+
+impl ElytraDevice for SharedDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        self.send(bytes)
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.log.push((bytes_out, bytes_in));
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        std::mem::take(&mut self.log)
+    }
+
+    fn get_device_log(&mut self) -> Vec<(log::Level, String)> {
+        self.device.lock().map(|mut device| device.get_device_log()).unwrap_or_default()
+    }
+}
@@ -0,0 +1,333 @@
+use std::{error::Error, net::{Ipv4Addr, Ipv6Addr}, time::SystemTime};
+
+use color_eyre::eyre::eyre;
+use elytra_conf::values::{ColorFormat, Date, ValueType};
+
+use crate::Entry;
+
+const NAMED_COLORS: [(&str, [u8; 3]); 16] = [
+    ("black", [0x00, 0x00, 0x00]),
+    ("white", [0xff, 0xff, 0xff]),
+    ("red", [0xff, 0x00, 0x00]),
+    ("green", [0x00, 0x80, 0x00]),
+    ("lime", [0x00, 0xff, 0x00]),
+    ("blue", [0x00, 0x00, 0xff]),
+    ("yellow", [0xff, 0xff, 0x00]),
+    ("cyan", [0x00, 0xff, 0xff]),
+    ("magenta", [0xff, 0x00, 0xff]),
+    ("orange", [0xff, 0xa5, 0x00]),
+    ("purple", [0x80, 0x00, 0x80]),
+    ("pink", [0xff, 0xc0, 0xcb]),
+    ("gray", [0x80, 0x80, 0x80]),
+    ("grey", [0x80, 0x80, 0x80]),
+    ("warmwhite", [0xff, 0xdf, 0xb0]),
+    ("off", [0x00, 0x00, 0x00]),
+];
+
+/// Formats a field value payload (the response without the status byte) for display
+pub fn format_value(value_type: ValueType, payload: &[u8]) -> String {
+    match value_type {
+        ValueType::Text | ValueType::Secret => text_from(payload),
+        ValueType::Status => format!("[{}] {}", payload[0], text_from(&payload[1..])),
+        ValueType::Integer => i64::from_le_bytes(payload[0..8].try_into().unwrap()).to_string(),
+        ValueType::Toggle => if payload[0] == 1 { "on".into() } else { "off".into() },
+        ValueType::Bytes => {
+            let len = payload.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            format_hex(&payload[..len], " ")
+        },
+        ValueType::Options => u16::from_le_bytes([payload[0], payload[1]]).to_string(),
+        ValueType::Ipv4 => Ipv4Addr::from(<[u8; 4]>::try_from(&payload[0..4]).unwrap()).to_string(),
+        ValueType::Ipv6 => Ipv6Addr::from(<[u8; 16]>::try_from(&payload[0..16]).unwrap()).to_string(),
+        ValueType::Mac => format_hex(&payload[0..6], ":"),
+        ValueType::Port => u16::from_le_bytes([payload[0], payload[1]]).to_string(),
+        ValueType::Time => format_time(u32::from_le_bytes(payload[0..4].try_into().unwrap()) as i64),
+        ValueType::Date => {
+            let year = u16::from_le_bytes([payload[0], payload[1]]);
+            format!("{year:04}-{:02}-{:02}", payload[2], payload[3])
+        },
+        ValueType::Timestamp => format_timestamp(i64::from_le_bytes(payload[0..8].try_into().unwrap())),
+        ValueType::Duration => format_duration(u32::from_le_bytes(payload[0..4].try_into().unwrap()) as u64),
+        ValueType::Color => format_color(ColorFormat::Rgb, payload),
+        ValueType::Blob => "(blob)".into(),
+    }
+}
+
+/// The value of a field as compared by layout conditions, if the value type supports it
+pub fn numeric_value(value_type: ValueType, payload: &[u8]) -> Option<i64> {
+    match value_type {
+        ValueType::Toggle => Some((payload[0] == 1) as i64),
+        ValueType::Options => Some(u16::from_le_bytes([payload[0], payload[1]]) as i64),
+        ValueType::Integer => Some(i64::from_le_bytes(payload[0..8].try_into().unwrap())),
+        _ => None,
+    }
+}
+
+/// Formats a field value payload, using the entry constraints where the value type depends on them
+pub fn format_entry_value(entry: &Entry, payload: &[u8]) -> Result<String, Box<dyn Error>> {
+    Ok(match ValueType::try_from(entry.variant)? {
+        ValueType::Color => format_color(ColorFormat::try_from(entry.constraints[0])?, payload),
+        ValueType::Blob => format!("(blob, up to {} bytes)", blob_max_size(entry)),
+        vt => format_value(vt, payload),
+    })
+}
+
+/// Parses user input for a field, using the entry constraints where the value type depends on them
+pub fn parse_entry_value(entry: &Entry, input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    match ValueType::try_from(entry.variant)? {
+        ValueType::Color => Ok(parse_color(input, ColorFormat::try_from(entry.constraints[0])?)?.to_vec()),
+        vt => parse_value(vt, input),
+    }
+}
+
+/// Parses user input into the payload of a write command for the given value type
+pub fn parse_value(value_type: ValueType, input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let input = input.trim();
+    Ok(match value_type {
+        ValueType::Text | ValueType::Secret => input.as_bytes().to_vec(),
+        ValueType::Integer => input.parse::<i64>()?.to_le_bytes().to_vec(),
+        ValueType::Toggle => match input.to_lowercase().as_str() {
+            "1" | "on" | "true" | "yes" | "enabled" => vec![1],
+            "0" | "off" | "false" | "no" | "disabled" => vec![0],
+            _ => Err(eyre!("Invalid toggle value: \"{input}\""))?,
+        },
+        ValueType::Bytes => parse_hex(input)?,
+        ValueType::Options => input.split(',')
+            .map(|o| o.trim().parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+        ValueType::Ipv4 => input.parse::<Ipv4Addr>()?.octets().to_vec(),
+        ValueType::Ipv6 => input.parse::<Ipv6Addr>()?.octets().to_vec(),
+        ValueType::Mac => parse_mac(input)?.to_vec(),
+        ValueType::Port => input.parse::<u16>()?.to_le_bytes().to_vec(),
+        ValueType::Time => (parse_time(input)? as u32).to_le_bytes().to_vec(),
+        ValueType::Date => {
+            let (year, month, day) = parse_date(input)?;
+            let mut bytes = year.to_le_bytes().to_vec();
+            bytes.extend([month, day]);
+            bytes
+        },
+        ValueType::Timestamp => parse_timestamp(input)?.to_le_bytes().to_vec(),
+        ValueType::Duration => u32::try_from(parse_duration(input)?)?.to_le_bytes().to_vec(),
+        ValueType::Color => parse_color(input, ColorFormat::Rgb)?.to_vec(),
+        ValueType::Status => Err(eyre!("Status values cannot be written"))?,
+        ValueType::Blob => Err(eyre!("Blob values are written using `elytra blob put`"))?,
+    })
+}
+
+/// The max size of a blob entry
+pub fn blob_max_size(entry: &Entry) -> u32 {
+    u32::from_le_bytes(entry.constraints[0..4].try_into().unwrap())
+}
+
+/// Whether the SHA-256 digest of a blob entry can be queried
+pub fn blob_has_digest(entry: &Entry) -> bool {
+    entry.constraints[4] == 1
+}
+
+/// Parses a MAC address using `:` or `-` as separators, or none at all
+pub fn parse_mac(input: &str) -> Result<[u8; 6], Box<dyn Error>> {
+    let bytes = parse_hex(input)?;
+    bytes.as_slice().try_into()
+        .map_err(|_| eyre!("MAC address needs to be 6 bytes, got {}", bytes.len()).into())
+}
+
+/// Returns the payload for setting a time related field to the current time of the host
+pub fn host_time_payload(value_type: ValueType) -> Result<Vec<u8>, Box<dyn Error>> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+    Ok(match value_type {
+        ValueType::Timestamp => now.to_le_bytes().to_vec(),
+        ValueType::Time => (now.rem_euclid(86400) as u32).to_le_bytes().to_vec(),
+        ValueType::Date => {
+            let (year, month, day) = civil_from_days(now.div_euclid(86400));
+            let mut bytes = (year as u16).to_le_bytes().to_vec();
+            bytes.extend([month, day]);
+            bytes
+        },
+        vt => Err(eyre!("{vt} fields cannot be set from the host clock"))?,
+    })
+}
+
+/// Parses a time of day in the `HH:MM[:SS]` format into seconds since midnight
+pub fn parse_time(input: &str) -> Result<i64, Box<dyn Error>> {
+    let parts = input.split(':')
+        .map(|p| p.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (hour, minute, second) = match parts.as_slice() {
+        [h, m] => (*h, *m, 0),
+        [h, m, s] => (*h, *m, *s),
+        _ => Err(eyre!("Invalid time \"{input}\", expected HH:MM[:SS]"))?,
+    };
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+        Err(eyre!("Time out of range: \"{input}\""))?
+    }
+    Ok(hour * 3600 + minute * 60 + second)
+}
+
+/// Parses a date in the `YYYY-MM-DD` format
+pub fn parse_date(input: &str) -> Result<(u16, u8, u8), Box<dyn Error>> {
+    let mut parts = input.splitn(3, '-');
+    let (Some(year), Some(month), Some(day)) = (parts.next(), parts.next(), parts.next()) else {
+        Err(eyre!("Invalid date \"{input}\", expected YYYY-MM-DD"))?
+    };
+    let (year, month, day) = (year.parse()?, month.parse()?, day.parse()?);
+    if !(Date { year, month, day }).is_valid() {
+        Err(eyre!("Date out of range: \"{input}\""))?
+    }
+    Ok((year, month, day))
+}
+
+/// Parses either a number of seconds since the Unix epoch or a UTC date time
+/// in the `YYYY-MM-DD[T| ]HH:MM[:SS][Z]` format
+pub fn parse_timestamp(input: &str) -> Result<i64, Box<dyn Error>> {
+    if let Ok(seconds) = input.parse::<i64>() {
+        return Ok(seconds)
+    }
+    let input = input.trim_end_matches('Z');
+    let Some((date, time)) = input.split_once(['T', ' ']) else {
+        Err(eyre!("Invalid timestamp \"{input}\", expected YYYY-MM-DDTHH:MM:SS"))?
+    };
+    let (year, month, day) = parse_date(date)?;
+    Ok(days_from_civil(year as i64, month, day) * 86400 + parse_time(time)?)
+}
+
+/// Parses a duration either as a plain number of seconds or using unit suffixes, like `1h 30m`
+pub fn parse_duration(input: &str) -> Result<u64, Box<dyn Error>> {
+    if let Ok(seconds) = input.parse::<u64>() {
+        return Ok(seconds)
+    }
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in input.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => Err(eyre!("Invalid duration unit '{c}' in \"{input}\""))?,
+        };
+        total += number.parse::<u64>()? * multiplier;
+        number.clear();
+    }
+    if !number.is_empty() {
+        Err(eyre!("Missing unit for the last value in \"{input}\""))?
+    }
+    Ok(total)
+}
+
+fn format_time(seconds: i64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86400));
+    format!("{year:04}-{month:02}-{day:02} {} UTC", format_time(timestamp.rem_euclid(86400)))
+}
+
+fn format_duration(seconds: u64) -> String {
+    if seconds == 0 {
+        return "0s".into()
+    }
+    [
+        (seconds / 86400, "d"),
+        (seconds / 3600 % 24, "h"),
+        (seconds / 60 % 60, "m"),
+        (seconds % 60, "s"),
+    ].iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, suffix)| format!("{value}{suffix}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Converts days since the Unix epoch into a (year, month, day) tuple
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) tuple into days since the Unix epoch
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    // See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parses a colour as `#rrggbb`, `#rgb` or a named colour, with an optional trailing
+/// alpha/white byte (`#rrggbbxx`) for formats that have four channels
+pub fn parse_color(input: &str, format: ColorFormat) -> Result<[u8; 4], Box<dyn Error>> {
+    let lower = input.to_lowercase();
+    if let Some((_, rgb)) = NAMED_COLORS.iter().find(|(name, _)| *name == lower) {
+        return Ok([rgb[0], rgb[1], rgb[2], 0]);
+    }
+    let digits = lower.trim_start_matches('#');
+    let digits = if digits.len() == 3 {
+        digits.chars().flat_map(|c| [c, c]).collect()
+    } else {
+        digits.to_owned()
+    };
+    let bytes = parse_hex(&digits)?;
+    match (bytes.as_slice(), format.channels()) {
+        ([r, g, b], _) => Ok([*r, *g, *b, 0]),
+        ([r, g, b, x], 4) => Ok([*r, *g, *b, *x]),
+        _ => Err(eyre!("Invalid colour \"{input}\", expected {}", match format {
+            ColorFormat::Rgb => "#rrggbb",
+            ColorFormat::Rgba => "#rrggbb[aa]",
+            ColorFormat::Rgbw => "#rrggbb[ww]",
+        }))?,
+    }
+}
+
+fn format_color(format: ColorFormat, payload: &[u8]) -> String {
+    format!("#{}", format_hex(&payload[..format.channels()], ""))
+}
+
+fn parse_hex(input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let digits: String = input.chars().filter(|c| !matches!(c, ':' | '-' | ' ')).collect();
+    if !digits.len().is_multiple_of(2) {
+        Err(eyre!("Uneven number of hex digits in \"{input}\""))?
+    }
+    (0..digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(Into::into))
+        .collect()
+}
+
+pub fn format_hex(bytes: &[u8], separator: &str) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(separator)
+}
+
+fn text_from(payload: &[u8]) -> String {
+    String::from_utf8_lossy(payload).trim_end_matches('\0').to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!((2024, 2, 29), parse_date("2024-02-29").unwrap());
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("2023-04-31").is_err());
+    }
+}
@@ -1,27 +1,132 @@
-use std::{error::Error, io::Write};
+// the elytra! macro used by the tests needs meta-variable expressions
+#![cfg_attr(test, feature(macro_metavar_expr))]
+
+use std::{error::Error, fmt::Display, io::Write, time::{Duration, Instant}};
 
 use color_eyre::eyre::{eyre};
-use elytra_conf::{command::CommandKey, config::{EntryType, QueryTargetKey}, entry::ExtraFlags};
+use elytra_conf::{
+    auth::AccessLevel,
+    blob::BLOB_DIGEST_LEN,
+    command::{CHUNK_SIZE, CommandError, CommandKey}, 
+    condition::{ConditionEffect, ConditionOp}, 
+    config::{EntryType, QueryTargetKey}, 
+    dfu::Crc32,
+    entry::{ExtraFlags, LAYOUT_HEADING, LAYOUT_SEPARATOR}, 
+    job::JobState, 
+    values::ValueType
+};
 
 pub mod wasm;
 pub mod tcp;
+pub mod ws;
+pub mod serial;
+pub mod device;
+pub mod bridge;
+pub mod record;
+pub mod decode;
+pub mod native;
+pub mod mock;
 pub mod tui;
+pub mod format;
+
+/// Time between two status requests while waiting for a job
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 pub trait ElytraDevice: Send {
     fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>>;
     fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]);
     fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])>;
+
+    /// Messages the device logged since the last call, for devices that forward their own logs
+    fn get_device_log(&mut self) -> Vec<(log::Level, String)> {
+        vec![]
+    }
+}
+
+/// An entry of the communication log of a device
+#[derive(Clone, Debug)]
+pub enum LogItem {
+    Exchange([u8; 64], [u8; 64]),
+    /// Message logged by the device itself
+    Device(log::Level, String),
 }
 
 pub struct Section {
     pub entry: Entry,
-    pub layout: Vec<(LayoutEntry, Entry)>
+    pub layout: Vec<(LayoutEntry, Entry)>,
+    /// Conditions of the layout entries, by layout position
+    pub conditions: Vec<Option<FieldCondition>>,
 }
 
-#[derive(Clone)]
+impl Section {
+    pub fn condition(&self, position: usize) -> Option<&FieldCondition> {
+        self.conditions.get(position).and_then(Option::as_ref)
+    }
+}
+
+/// The sections that are not nested in any other section, in index order
+pub fn root_sections(sections: &[Section]) -> impl Iterator<Item = usize> + '_ {
+    (0..sections.len()).filter(|index| !sections.iter().any(|s| 
+        s.layout.iter().any(|(le, _)| *le == LayoutEntry::Section(*index as u8))
+    ))
+}
+
+/// A dependency of a layout entry on the value of a prop
+#[derive(Clone, Copy, Debug)]
+pub struct FieldCondition {
+    pub effect: ConditionEffect,
+    pub op: ConditionOp,
+    pub prop: u8,
+    pub value: i64,
+}
+
+impl FieldCondition {
+    /// Checks the condition against the numeric value of the prop, see [`format::numeric_value`]
+    pub fn matches(&self, value: i64) -> bool {
+        match self.op {
+            ConditionOp::Equals => value == self.value,
+            ConditionOp::NotEquals => value != self.value,
+        }
+    }
+}
+
+impl Display for FieldCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let effect = match self.effect {
+            ConditionEffect::Visible => "shown",
+            ConditionEffect::Writable => "writable",
+        };
+        let op = match self.op {
+            ConditionOp::Equals => "=",
+            ConditionOp::NotEquals => "!=",
+        };
+        write!(f, "{effect} when prop #{} {op} {}", self.prop, self.value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum LayoutEntry {
     Info(u8),
     Prop(u8),
+    /// A nested section
+    Section(u8),
+    Action(u8),
+    /// A heading, by layout position
+    Heading(u8),
+    Separator,
+}
+
+impl LayoutEntry {
+    /// The entry type and index of entries that can be queried
+    pub fn entry_ref(&self) -> Option<(u8, u8)> {
+        match *self {
+            LayoutEntry::Info(ii) => Some((EntryType::Info as u8, ii)),
+            LayoutEntry::Prop(pi) => Some((EntryType::Prop as u8, pi)),
+            LayoutEntry::Section(si) => Some((EntryType::Section as u8, si)),
+            LayoutEntry::Action(ai) => Some((EntryType::Action as u8, ai)),
+            LayoutEntry::Heading(_) | LayoutEntry::Separator => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -33,7 +138,78 @@ pub struct Entry {
     pub icon: Option<String>,
     pub help: Option<String>,
     pub entry_type: u8,
-    pub layout: Option<Vec<LayoutEntry>>
+    pub layout: Option<Vec<LayoutEntry>>,
+    pub signature: Option<ActionSignature>,
+}
+
+/// Typed parameters and result of an action
+#[derive(Clone)]
+pub struct ActionSignature {
+    pub params: Vec<Entry>,
+    pub result: Option<Box<Entry>>,
+}
+
+impl Display for ActionSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params = self.params.iter()
+            .map(|p| format!("{}: {}", p.name, value_type_name(p.variant)))
+            .collect::<Vec<_>>();
+        write!(f, "({})", params.join(", "))?;
+        if let Some(result) = &self.result {
+            write!(f, " -> {}: {}", result.name, value_type_name(result.variant))?;
+        }
+        Ok(())
+    }
+}
+
+fn value_type_name(variant: u8) -> String {
+    ValueType::try_from(variant).map_or_else(|_| format!("{variant:02x}"), |vt| vt.to_string())
+}
+
+impl Entry {
+    /// A placeholder entry for headings and separators, named by the heading text
+    pub fn heading(name: String) -> Self {
+        Self {
+            name,
+            flags: ExtraFlags::ReadOnly,
+            variant: 0,
+            constraints: [0; 8],
+            icon: None,
+            help: None,
+            entry_type: LAYOUT_HEADING,
+            layout: None,
+            signature: None,
+        }
+    }
+
+    /// The access level needed to write the entry or run the action
+    pub fn access(&self) -> AccessLevel {
+        if self.flags.contains(ExtraFlags::RequiresAdmin) {
+            AccessLevel::Admin
+        } else if self.flags.contains(ExtraFlags::RequiresUser) {
+            AccessLevel::User
+        } else {
+            AccessLevel::Public
+        }
+    }
+}
+
+/// Progress of a long-running action
+#[derive(Clone, Debug)]
+pub struct JobStatus {
+    pub id: u8,
+    pub state: JobState,
+    /// Progress in percent (0-100)
+    pub progress: u8,
+    pub message: String,
+}
+
+impl JobStatus {
+    /// A text progress bar of the given width, e.g. `[#####     ] 50%`
+    pub fn progress_bar(&self, width: usize) -> String {
+        let filled = width * self.progress.min(100) as usize / 100;
+        format!("[{}{}] {:3}%", "#".repeat(filled), " ".repeat(width - filled), self.progress)
+    }
 }
 
 pub struct Info {
@@ -48,34 +224,61 @@ fn err_msg(bytes: &[u8]) -> String {
     String::from_utf8_lossy(&bytes[2..]).trim_end_matches('\0').to_owned()
 }
 
+fn ensure_ok(res: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+    if res[0] != 1 { return Err(eyre!("Got error response: {} ({:02x?}) ", err_msg(&res), &res[1]))? }
+    Ok(res)
+}
+
+/// Parses a field query response, the flags take one byte before protocol version 2 and two since
+pub(crate) fn parse_entry(res: [u8; 64], entry_type: u8, proto_version: u8) -> Result<Entry, Box<dyn Error>> {
+    if res[0] != 1 { return Err(eyre!("Got error response: {} ({:02x?}) ", err_msg(&res), &res[1]))? }
+    let (flags, res) = match proto_version {
+        0 | 1 => (res[1] as u16, &res[2..]),
+        _ => (u16::from_le_bytes([res[1], res[2]]), &res[3..]),
+    };
+    let flags = ExtraFlags::from_bits_truncate(flags);
+    let variant = res[0];
+    let mut constraints = [0u8; 8];
+    constraints.copy_from_slice(&res[1..9]);
+    let name = str::from_utf8(&res[9..])?.trim_end_matches('\0').to_owned();
+
+    Ok(Entry {
+        name,
+        flags,
+        variant,
+        constraints,
+        entry_type,
+        help: None,
+        icon: None,
+        layout: None,
+        signature: None,
+    })
+}
+
 impl dyn ElytraDevice {
+    /// Takes the exchanges since the last call, followed by the messages the device logged during them
+    pub fn take_log(&mut self) -> Vec<LogItem> {
+        let exchanges = self.get_log().into_iter().map(|(out, res)| LogItem::Exchange(out, res));
+        exchanges.chain(self.get_device_log().into_iter().map(|(level, message)| LogItem::Device(level, message))).collect()
+    }
+
     pub fn get_entry(&mut self, entry_type: u8, index: u8) -> Result<Entry, Box<dyn Error>> {
+        let proto_version = self.get_info()?.proto_version;
+        self.query_entry(entry_type, index, proto_version)
+    }
+
+    pub fn get_entries(&mut self, entry_type: u8, count: usize) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let proto_version = self.get_info()?.proto_version;
+        (0..count).map(|index| self.query_entry(entry_type, index as u8, proto_version)).collect()
+    }
+
+    fn query_entry(&mut self, entry_type: u8, index: u8, proto_version: u8) -> Result<Entry, Box<dyn Error>> {
         let res = self.send_command( &[
             CommandKey::Query as u8, 
             entry_type, index, 
             QueryTargetKey::Field as u8
         ])?;
-        if res[0] != 1 { return Err(eyre!("Got error response: {} ({:02x?}) ", err_msg(&res), &res[1]))? }
-        let flags = ExtraFlags::from_bits_truncate(res[1]);
-        let variant = res[2];
-        let mut constraints = [0u8; 8];
-        constraints.copy_from_slice(&res[3..11]);
-        let name = str::from_utf8(&res[11..])?.trim_end_matches('\0').to_owned();
-
-        Ok(Entry {
-            name,
-            flags,
-            variant,
-            constraints,
-            entry_type,
-            help: None,
-            icon: None,
-            layout: None,
-        })
-    }
-
-    pub fn get_entries(&mut self, entry_type: u8, count: usize) -> Result<Vec<Entry>, Box<dyn Error>> {
-        (0..count).map(|index| self.get_entry(entry_type, index as u8)).collect()
+        parse_entry(res, entry_type, proto_version)
     }
 
 
@@ -113,6 +316,119 @@ impl dyn ElytraDevice {
         Ok(res)
     }
 
+    pub fn set_value(&mut self, entry_type: EntryType, index: u8, payload: &[u8]) -> Result<(), Box<dyn Error>>  {
+        let command = match entry_type {
+            EntryType::Info => CommandKey::WriteInfo,
+            EntryType::Prop => CommandKey::WriteProp,
+            et => Err(eyre!("Invalid entry type {et:?}"))?,
+        };
+        ensure_ok(self.send_command(&[&[command as u8, index], payload].concat())?)?;
+        Ok(())
+    }
+
+    /// The default value of a prop or info field, in the same format as [`get_value`](Self::get_value)
+    pub fn get_default(&mut self, entry_type: EntryType, index: u8) -> Result<[u8; 64], Box<dyn Error>> {
+        ensure_ok(self.send_command(&[
+            CommandKey::Query as u8,
+            entry_type as u8, index,
+            QueryTargetKey::Default as u8
+        ])?)
+    }
+
+    pub fn reset_prop(&mut self, index: u8) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::ResetProp as u8, index])?)?;
+        Ok(())
+    }
+
+    /// Resets all props to their defaults, including those of repeated section instances
+    pub fn reset_all(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::ResetAll as u8])?)?;
+        Ok(())
+    }
+
+    /// Logs in at `level`, unlocking entries that require it until the session times out
+    pub fn login(&mut self, level: AccessLevel, secret: &str) -> Result<AccessLevel, Box<dyn Error>> {
+        if secret.len() > CHUNK_SIZE {
+            Err(eyre!("Secret is longer than {CHUNK_SIZE} bytes"))?
+        }
+        let res = ensure_ok(self.send_command(&[
+            &[CommandKey::Login as u8, level as u8, secret.len() as u8],
+            secret.as_bytes()
+        ].concat())?)?;
+        Ok(AccessLevel::try_from(res[1])?)
+    }
+
+    pub fn logout(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::Logout as u8])?)?;
+        Ok(())
+    }
+
+    /// Stages the following prop writes on the device until the transaction is committed
+    pub fn begin_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::BeginTransaction as u8])?)?;
+        Ok(())
+    }
+
+    /// Validates and applies the staged writes, failing if the device rejects them
+    pub fn commit_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::CommitTransaction as u8])?)?;
+        Ok(())
+    }
+
+    pub fn abort_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::AbortTransaction as u8])?)?;
+        Ok(())
+    }
+
+    /// Runs `f` in a transaction, committing if it succeeds and aborting otherwise
+    pub fn transaction<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+        self.begin_transaction()?;
+        match f(self) {
+            Ok(value) => self.commit_transaction().map(|_| value),
+            Err(e) => {
+                if let Err(abort_err) = self.abort_transaction() {
+                    log::warn!("Failed to abort transaction: {abort_err}");
+                }
+                Err(e)
+            },
+        }
+    }
+
+    pub fn get_group_value(&mut self, section: u8, instance: u8, prop: u8) -> Result<[u8; 64], Box<dyn Error>> {
+        self.send_command(&[CommandKey::ReadGroupProp as u8, section, instance, prop])
+    }
+
+    pub fn set_group_value(&mut self, section: u8, instance: u8, prop: u8, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        let command = [CommandKey::WriteGroupProp as u8, section, instance, prop];
+        ensure_ok(self.send_command(&[&command, payload].concat())?)?;
+        Ok(())
+    }
+
+    /// Adds a new instance to a repeated section, returning its instance index
+    pub fn add_instance(&mut self, section: u8) -> Result<u8, Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[CommandKey::AddInstance as u8, section])?)?;
+        Ok(res[1])
+    }
+
+    pub fn remove_instance(&mut self, section: u8, instance: u8) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::RemoveInstance as u8, section, instance])?)?;
+        Ok(())
+    }
+
+    /// Returns the indices of the instances of a repeated section that are in use
+    pub fn list_instances(&mut self, section: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[CommandKey::ListInstances as u8, section])?)?;
+        let used = u64::from_le_bytes(res[2..10].try_into()?);
+        Ok((0..64).filter(|i| used & (1 << i) != 0).collect())
+    }
+
+    /// Sets a time, date or timestamp field to the current time of the host
+    pub fn sync_time(&mut self, entry_type: EntryType, index: u8) -> Result<(), Box<dyn Error>> {
+        let entry = self.get_entry(entry_type as u8, index)?;
+        let payload = format::host_time_payload(ValueType::try_from(entry.variant)?)?;
+        self.set_value(entry_type, index, &payload)
+    }
+
     pub fn get_layout(&mut self, index: u8) -> Result<Vec<LayoutEntry>, Box<dyn Error>>  {
         let mut res = self.send_command(&[b'q', b's', index, b'l'])?.into_iter();
         assert_eq!(1, res.next().unwrap());
@@ -127,15 +443,278 @@ impl dyn ElytraDevice {
             if ft == 0 {
                 break;
             }
+            let position = entries.len() as u8;
             entries.push(match ft {
-                b'c' => LayoutEntry::Prop(ix),
+                b'p' => LayoutEntry::Prop(ix),
                 b'i' => LayoutEntry::Info(ix),
+                b's' => LayoutEntry::Section(ix),
+                b'a' => LayoutEntry::Action(ix),
+                LAYOUT_HEADING => LayoutEntry::Heading(position),
+                LAYOUT_SEPARATOR => LayoutEntry::Separator,
                 ft => panic!("Unknown field type: {:02x}", ft)
             });
         }
         Ok(entries)
     }
 
+    /// Queries the parameters and result of an action
+    pub fn get_action_signature(&mut self, action: &Entry, index: u8) -> Result<ActionSignature, Box<dyn Error>> {
+        let [param_count, has_result, ..] = action.constraints;
+        let query = [CommandKey::Query as u8, EntryType::Action as u8, index];
+        let proto_version = self.get_info()?.proto_version;
+        let params = (0..param_count as u16).map(|param| {
+            let [lo, hi] = param.to_le_bytes();
+            let res = self.send_command(&[&query[..], &[QueryTargetKey::Param as u8, lo, hi]].concat())?;
+            parse_entry(res, EntryType::Prop as u8, proto_version)
+        }).collect::<Result<Vec<_>, _>>()?;
+        let result = if has_result == 1 {
+            let res = self.send_command(&[&query[..], &[QueryTargetKey::Result as u8]].concat())?;
+            Some(Box::new(parse_entry(res, EntryType::Info as u8, proto_version)?))
+        } else {
+            None
+        };
+        Ok(ActionSignature { params, result })
+    }
+
+    /// Runs an action with the given argument payloads, returning the response
+    pub fn run_action(&mut self, index: u8, args: &[Vec<u8>]) -> Result<[u8; 64], Box<dyn Error>> {
+        let mut command = vec![CommandKey::Action as u8, index, args.len() as u8];
+        for arg in args {
+            command.push(arg.len() as u8);
+            command.extend(arg);
+        }
+        if command.len() > 64 {
+            Err(eyre!("Action arguments exceed the message size"))?
+        }
+        ensure_ok(self.send_command(&command)?)
+    }
+
+    /// Starts a long-running action, returning its job id
+    pub fn start_job(&mut self, index: u8, args: &[Vec<u8>]) -> Result<u8, Box<dyn Error>> {
+        Ok(self.run_action(index, args)?[1])
+    }
+
+    pub fn get_job_status(&mut self, id: u8) -> Result<JobStatus, Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[CommandKey::JobStatus as u8, id])?)?;
+        Ok(JobStatus {
+            id,
+            state: JobState::try_from(res[1])?,
+            progress: res[2],
+            message: String::from_utf8_lossy(&res[3..]).trim_end_matches('\0').to_owned(),
+        })
+    }
+
+    pub fn cancel_job(&mut self, id: u8) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::CancelJob as u8, id])?)?;
+        Ok(())
+    }
+
+    /// Polls a job until it has finished, calling `on_progress` with each status.
+    /// Fails if the job is still running after `timeout`, leaving the job itself running.
+    pub fn wait_for_job(&mut self, id: u8, timeout: Duration, mut on_progress: impl FnMut(&JobStatus)) -> Result<JobStatus, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.get_job_status(id)?;
+            on_progress(&status);
+            if status.state.is_finished() {
+                return Ok(status)
+            }
+            if Instant::now() >= deadline {
+                return Err(eyre!("Job #{id} did not finish within {}s", timeout.as_secs_f32()))?
+            }
+            std::thread::sleep(JOB_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    /// Starts or resumes a firmware transfer, returning the offset to continue writing from
+    pub fn dfu_begin(&mut self, size: u32, crc: u32) -> Result<u32, Box<dyn Error>> {
+        let mut bytes = vec![CommandKey::DfuBegin as u8];
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(crc.to_le_bytes());
+        let res = ensure_ok(self.send_command(&bytes)?)?;
+        Ok(u32::from_le_bytes(res[1..5].try_into()?))
+    }
+
+    /// Writes a chunk of at most [`CHUNK_SIZE`] bytes, returning the offset of the next chunk
+    pub fn dfu_write(&mut self, offset: u32, chunk: &[u8]) -> Result<u32, Box<dyn Error>> {
+        let mut bytes = vec![CommandKey::DfuWrite as u8];
+        bytes.extend(offset.to_le_bytes());
+        bytes.push(chunk.len() as u8);
+        bytes.extend(chunk);
+        let res = ensure_ok(self.send_command(&bytes)?)?;
+        Ok(u32::from_le_bytes(res[1..5].try_into()?))
+    }
+
+    /// Returns the CRC-32 of the written image, failing if it does not match
+    pub fn dfu_verify(&mut self) -> Result<u32, Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[CommandKey::DfuVerify as u8])?)?;
+        Ok(u32::from_le_bytes(res[1..5].try_into()?))
+    }
+
+    pub fn dfu_commit(&mut self) -> Result<(), Box<dyn Error>> {
+        ensure_ok(self.send_command(&[CommandKey::DfuCommit as u8])?)?;
+        Ok(())
+    }
+
+    /// Transfers and verifies a firmware image, without committing it.
+    ///
+    /// Failed writes are retried up to `retries` times in a row, resuming the transfer from the
+    /// offset reported by the device. `on_progress` is called with the number of bytes written.
+    pub fn flash_image(&mut self, image: &[u8], retries: usize, mut on_progress: impl FnMut(&mut Self, usize)) -> Result<u32, Box<dyn Error>> {
+        let size = u32::try_from(image.len())?;
+        let crc = Crc32::checksum(image);
+        let mut offset = self.dfu_begin(size, crc)?;
+        let mut failures = 0;
+        while offset < size {
+            let end = (offset as usize + CHUNK_SIZE).min(image.len());
+            match self.dfu_write(offset, &image[offset as usize..end]) {
+                Ok(next) => {
+                    offset = next;
+                    failures = 0;
+                },
+                Err(e) => {
+                    failures += 1;
+                    if failures > retries {
+                        return Err(e)
+                    }
+                    log::warn!("Write at {offset} failed, resuming: {e}");
+                    offset = self.dfu_begin(size, crc)?;
+                }
+            }
+            on_progress(self, offset as usize);
+        }
+        let device_crc = self.dfu_verify()?;
+        if device_crc != crc {
+            Err(eyre!("Image CRC mismatch: expected {crc:08x}, device has {device_crc:08x}"))?
+        }
+        Ok(crc)
+    }
+
+    /// Finds a prop by its name, ignoring case
+    pub fn find_prop(&mut self, name: &str) -> Result<(u8, Entry), Box<dyn Error>> {
+        let prop_count = self.get_info()?.prop_count;
+        for index in 0..prop_count {
+            let entry = self.get_entry(EntryType::Prop as u8, index)?;
+            if entry.name.eq_ignore_ascii_case(name) {
+                return Ok((index, entry))
+            }
+        }
+        Err(eyre!("No prop named \"{name}\""))?
+    }
+
+    /// Reads a blob prop, calling `on_progress` with the number of bytes read and the total length
+    pub fn read_blob(&mut self, prop: u8, mut on_progress: impl FnMut(&mut Self, usize, usize)) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = vec![];
+        loop {
+            let mut bytes = vec![CommandKey::BlobRead as u8, prop];
+            bytes.extend((data.len() as u32).to_le_bytes());
+            let res = ensure_ok(self.send_command(&bytes)?)?;
+            let total = u32::from_le_bytes(res[1..5].try_into()?) as usize;
+            let chunk_len = (res[5] as usize).min(CHUNK_SIZE);
+            data.extend(&res[6..6 + chunk_len]);
+            on_progress(self, data.len(), total);
+            if data.len() >= total {
+                return Ok(data)
+            }
+            if chunk_len == 0 {
+                Err(eyre!("Blob ended after {} of {total} bytes", data.len()))?
+            }
+        }
+    }
+
+    /// Replaces the contents of a blob prop, calling `on_progress` with the number of bytes written
+    pub fn write_blob(&mut self, prop: u8, data: &[u8], mut on_progress: impl FnMut(&mut Self, usize)) -> Result<(), Box<dyn Error>> {
+        let mut offset = 0;
+        // an empty write at offset 0 still clears the blob
+        for chunk in data.chunks(CHUNK_SIZE).chain(data.is_empty().then_some(&[][..])) {
+            let mut bytes = vec![CommandKey::BlobWrite as u8, prop];
+            bytes.extend((offset as u32).to_le_bytes());
+            bytes.push(chunk.len() as u8);
+            bytes.extend(chunk);
+            ensure_ok(self.send_command(&bytes)?)?;
+            offset += chunk.len();
+            on_progress(self, offset);
+        }
+        Ok(())
+    }
+
+    pub fn get_blob_digest(&mut self, prop: u8) -> Result<[u8; BLOB_DIGEST_LEN], Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[CommandKey::BlobDigest as u8, prop])?)?;
+        Ok(res[1..1 + BLOB_DIGEST_LEN].try_into()?)
+    }
+
+    pub fn get_heading(&mut self, section: u8, position: u8) -> Result<String, Box<dyn Error>> {
+        let res = ensure_ok(self.send_command(&[
+            CommandKey::Query as u8,
+            EntryType::Section as u8, section,
+            QueryTargetKey::Heading as u8, position, 0
+        ])?)?;
+        Ok(String::from_utf8_lossy(&res[1..]).trim_end_matches('\0').to_string())
+    }
+
+    /// Resolves an entry of the layout of `section` to its description, see [`Entry::heading`]
+    pub fn get_layout_entry(&mut self, section: u8, le: LayoutEntry) -> Result<Entry, Box<dyn Error>> {
+        match le {
+            LayoutEntry::Heading(position) => Ok(Entry::heading(self.get_heading(section, position)?)),
+            LayoutEntry::Separator => Ok(Entry::heading(String::new())),
+            le => {
+                let (entry_type, index) = le.entry_ref().unwrap();
+                self.get_entry(entry_type, index)
+            }
+        }
+    }
+
+    /// Returns the conditions of the first `count` layout entries of a section
+    pub fn get_conditions(&mut self, section: u8, count: usize) -> Result<Vec<Option<FieldCondition>>, Box<dyn Error>> {
+        (0..count as u16).map(|position| self.get_condition(section, position)).collect()
+    }
+
+    pub fn get_condition(&mut self, section: u8, position: u16) -> Result<Option<FieldCondition>, Box<dyn Error>> {
+        let [lo, hi] = position.to_le_bytes();
+        let res = self.send_command(&[
+            CommandKey::Query as u8,
+            EntryType::Section as u8, section,
+            QueryTargetKey::Condition as u8, lo, hi
+        ])?;
+        if res[0] != 1 && res[1] == CommandError::NoContent as u8 {
+            return Ok(None)
+        }
+        let res = ensure_ok(res)?;
+        Ok(Some(FieldCondition {
+            effect: ConditionEffect::try_from(res[1])?,
+            op: ConditionOp::try_from(res[2])?,
+            prop: res[3],
+            value: i64::from_le_bytes(res[4..12].try_into()?),
+        }))
+    }
+
+    /// Fails when a writable condition of a section showing the prop doesn't hold for the current values
+    pub fn check_writable(&mut self, prop: u8) -> Result<(), Box<dyn Error>> {
+        let section_count = self.get_info()?.section_count;
+        for section in 0..section_count {
+            if !self.get_entry(EntryType::Section as u8, section)?.flags.contains(ExtraFlags::HasConditions) {
+                continue
+            }
+            let layout = self.get_layout(section)?;
+            for position in (0..layout.len()).filter(|p| layout[*p] == LayoutEntry::Prop(prop)) {
+                let Some(condition) = self.get_condition(section, position as u16)? else {
+                    continue
+                };
+                if condition.effect != ConditionEffect::Writable {
+                    continue
+                }
+                let entry = self.get_entry(EntryType::Prop as u8, condition.prop)?;
+                let value = ensure_ok(self.get_value(EntryType::Prop, condition.prop)?)?;
+                let numeric = ValueType::try_from(entry.variant).ok()
+                    .and_then(|vt| format::numeric_value(vt, &value[1..]));
+                if let Some(value) = numeric.filter(|value| !condition.matches(*value)) {
+                    Err(eyre!("Read only: the prop is {condition}, and {} is {value}", entry.name))?
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn send_command(&mut self, bytes: &[u8]) -> Result<[u8; 64], Box<dyn Error>> {
         let mut out_bytes= [0u8; 64];
         let _ = out_bytes.as_mut_slice().write(bytes)?;
@@ -146,3 +725,23 @@ impl dyn ElytraDevice {
         Ok(in_bytes)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entry_flags_by_version() {
+        let mut v1 = [0u8; 64];
+        v1[..15].copy_from_slice(b"\x01\x03t\x10\0\0\0\0\0\0\0Name");
+        let entry = parse_entry(v1, EntryType::Prop as u8, 1).unwrap();
+        assert_eq!(ExtraFlags::ReadOnly | ExtraFlags::HasHelp, entry.flags);
+        assert_eq!((b't', 0x10, "Name"), (entry.variant, entry.constraints[0], entry.name.as_str()));
+
+        let mut v2 = [0u8; 64];
+        v2[..16].copy_from_slice(b"\x01\x03\x01t\x10\0\0\0\0\0\0\0Name");
+        let entry = parse_entry(v2, EntryType::Prop as u8, 2).unwrap();
+        assert_eq!(ExtraFlags::ReadOnly | ExtraFlags::HasHelp | ExtraFlags::RequiresUser, entry.flags);
+        assert_eq!((b't', 0x10, "Name"), (entry.variant, entry.constraints[0], entry.name.as_str()));
+    }
+}
@@ -0,0 +1,686 @@
+use std::{collections::BTreeMap, error::Error, fs, path::{Path, PathBuf}};
+
+use color_eyre::eyre::eyre;
+use elytra_conf::{
+    auth::AccessLevel,
+    command::{CommandError, CommandKey, CommandResponse},
+    config::{EntryType, QueryTargetKey, PROTO_VERSION},
+    entry::{action, options::OptionValueProvider, prop, section, Constraints, EntryDesc, FieldEntry, LAYOUT_HEADING, LAYOUT_SEPARATOR},
+    field::FieldValue,
+    values::{ColorFormat, ValueType},
+};
+use serde::Deserialize;
+
+use crate::{ElytraDevice, format::{format_hex, numeric_value, parse_entry_value}, parse_entry, serial::{trimmed, unhex}};
+
+/// Longest entry name that fits in a field response, after the flags, variant and constraints
+const MAX_NAME_LEN: usize = 64 - 11;
+
+/// Description of a mock device, read from TOML:
+///
+/// ```toml
+/// [[props]]
+/// name = "Brightness Offset"
+/// type = "integer"
+/// range = [-1500, 1500]
+/// value = 0
+///
+/// [[info]]
+/// name = "Uptime"
+/// type = "integer"
+/// simulate = "counter"
+///
+/// [[sections]]
+/// name = "Display"
+/// layout = ["heading:Brightness", "Brightness Offset", "separator", "Uptime"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default)]
+    pub props: Vec<FieldSchema>,
+    #[serde(default)]
+    pub info: Vec<FieldSchema>,
+    #[serde(default)]
+    pub sections: Vec<SectionSchema>,
+    #[serde(default)]
+    pub actions: Vec<ActionSchema>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+    pub name: String,
+    /// Value type in lowercase, e.g. `text`, `integer` or `options`
+    #[serde(rename = "type", default = "default_type")]
+    pub value_type: String,
+    pub help: Option<String>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub readonly: bool,
+    /// Access level needed to write the prop: `public`, `user` or `admin`
+    pub access: Option<String>,
+    pub range: Option<[i32; 2]>,
+    pub max_length: Option<u64>,
+    /// Options of `options` fields, or suggestions for `text` fields
+    #[serde(default)]
+    pub options: Vec<String>,
+    pub max_options: Option<u16>,
+    /// Default value of props, or the value of static info fields, as it would be entered with `elytra set`
+    pub value: Option<toml::Value>,
+    #[serde(default)]
+    pub simulate: Simulation,
+}
+
+fn default_type() -> String {
+    "text".to_owned()
+}
+
+/// How the value of an info field changes between reads
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Simulation {
+    #[default]
+    Static,
+    /// Integers that count up on every read
+    Counter,
+    /// Integers in their range, toggles, or statuses picked from the options
+    Random,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionSchema {
+    pub name: String,
+    pub help: Option<String>,
+    pub icon: Option<String>,
+    /// Names of the entries in the section, `heading:<text>` or `separator`
+    #[serde(default)]
+    pub layout: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActionSchema {
+    pub name: String,
+    pub help: Option<String>,
+    pub icon: Option<String>,
+    pub access: Option<String>,
+}
+
+/// Entries of the mock live as long as the process, so their text is leaked to get the
+/// `'static` lifetime that entry descriptions need
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_owned().into_boxed_str())
+}
+
+fn parse_value_type(s: &str) -> Result<ValueType, Box<dyn Error>> {
+    Ok(match s {
+        "text" => ValueType::Text,
+        "secret" => ValueType::Secret,
+        "integer" => ValueType::Integer,
+        "status" => ValueType::Status,
+        "bytes" => ValueType::Bytes,
+        "options" => ValueType::Options,
+        "toggle" => ValueType::Toggle,
+        "ipv4" => ValueType::Ipv4,
+        "ipv6" => ValueType::Ipv6,
+        "mac" => ValueType::Mac,
+        "port" => ValueType::Port,
+        "time" => ValueType::Time,
+        "date" => ValueType::Date,
+        "timestamp" => ValueType::Timestamp,
+        "duration" => ValueType::Duration,
+        "color" => ValueType::Color,
+        _ => Err(eyre!("Unsupported value type \"{s}\""))?,
+    })
+}
+
+fn parse_access(access: &Option<String>) -> Result<AccessLevel, Box<dyn Error>> {
+    match access {
+        Some(access) => Ok(access.parse().map_err(|_| eyre!("Not a valid access level: \"{access}\""))?),
+        None => Ok(AccessLevel::Public),
+    }
+}
+
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(eyre!("Entry names need to be 1 to {MAX_NAME_LEN} bytes: \"{name}\""))?
+    }
+    Ok(())
+}
+
+fn value_text(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Array(items) => items.iter().map(value_text).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+fn random() -> u32 {
+    let mut bytes = [0u8; 4];
+    let _ = getrandom::getrandom(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+#[derive(Debug)]
+struct MockOptions(Vec<&'static str>);
+
+impl OptionValueProvider for MockOptions {
+    fn get(&self, index: usize) -> Option<&'static str> {
+        self.0.get(index).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+fn position(mut descs: impl Iterator<Item = &'static EntryDesc>, name: &str) -> Option<usize> {
+    descs.position(|d| d.name == name)
+}
+
+struct MockField {
+    desc: &'static EntryDesc,
+    options: Vec<&'static str>,
+    simulate: Simulation,
+    default: [u8; 64],
+    /// The value in store format, see [`FieldValue::into_store_bytes`]
+    value: [u8; 64],
+}
+
+impl MockField {
+    fn new(schema: &FieldSchema, is_info: bool) -> Result<Self, Box<dyn Error>> {
+        check_name(&schema.name)?;
+        let value_type = parse_value_type(&schema.value_type)?;
+        let options: Vec<&'static str> = schema.options.iter().map(|o| leak(o)).collect();
+        let mut entry = FieldEntry {
+            readonly: Some(is_info || schema.readonly),
+            access: parse_access(&schema.access)?,
+            help: schema.help.as_deref().map(leak),
+            icon: schema.icon.as_deref().map(leak),
+            ..prop(leak(&schema.name)).with_type(value_type)
+        };
+        if let Some([start, end]) = schema.range {
+            entry = entry.with_range(start..end);
+        }
+        if let Some(max_length) = schema.max_length {
+            entry.constraints = Constraints::Length(max_length);
+        }
+        if value_type == ValueType::Color {
+            entry.constraints = Constraints::Color(ColorFormat::Rgb);
+        }
+        match (value_type, options.is_empty()) {
+            (ValueType::Options, _) => {
+                let max = schema.max_options.unwrap_or(1);
+                entry = entry.with_options(Box::leak(Box::new(MockOptions(options.clone()))))
+                    .with_max_options(max)
+                    .with_default_options(&[0]);
+                entry.multi = max > 1;
+            },
+            (ValueType::Text | ValueType::Secret, false) => {
+                entry = entry.with_suggestions(Box::leak(Box::new(MockOptions(options.clone())))).with_default_text("");
+            },
+            (ValueType::Status, _) | (_, true) => {},
+            (_, false) => Err(eyre!("Options are only supported by options, text and status fields: \"{}\"", schema.name))?,
+        }
+        let desc: &'static EntryDesc = Box::leak(Box::new(entry.as_entry()));
+
+        match (schema.simulate, value_type) {
+            (Simulation::Static, _) | (Simulation::Counter, ValueType::Integer) => {},
+            (Simulation::Random, ValueType::Integer | ValueType::Toggle | ValueType::Status) if is_info => {},
+            (simulate, _) => Err(eyre!("Can't simulate {simulate:?} values for {}: \"{}\"", schema.value_type, schema.name))?,
+        }
+
+        let mut field = Self { desc, options, simulate: schema.simulate, default: [0; 64], value: [0; 64] };
+        if let Some(value) = &schema.value {
+            field.default = field.parse(&value_text(value))
+                .map_err(|e| eyre!("Invalid value for \"{}\": {e}", schema.name))?;
+        }
+        field.value = field.default;
+        Ok(field)
+    }
+
+    /// Parses a value like `elytra set` does, options can also be given by name
+    fn parse(&self, input: &str) -> Result<[u8; 64], Box<dyn Error>> {
+        let mut fv = FieldValue::new(self.desc);
+        match self.desc.variant.bits() {
+            b if b == ValueType::Status as u8 => fv.set_status(0, input),
+            b if b == ValueType::Options as u8 => {
+                let indices = input.split(',')
+                    .map(|o| o.trim())
+                    .map(|o| o.parse::<u16>().ok()
+                        .or_else(|| self.options.iter().position(|name| *name == o).map(|i| i as u16))
+                        .ok_or(eyre!("Not an option: \"{o}\"")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                fv.set_options(&indices);
+            },
+            _ => {
+                let entry = parse_entry(CommandResponse::from(self.desc).as_bytes().try_into()?, EntryType::Prop as u8, PROTO_VERSION)?;
+                fv = FieldValue::from_message(self.desc, &parse_entry_value(&entry, input)?);
+            },
+        }
+        Ok(fv.into_store_bytes())
+    }
+
+    /// Checks a written payload against the range or maximum length of the field
+    fn check(&self, payload: &[u8]) -> Result<(), CommandError> {
+        let value_type = ValueType::try_from(self.desc.variant.bits()).map_err(|_| CommandError::InvalidField)?;
+        let valid = match &self.desc.constraints {
+            Constraints::Range(range) => numeric_value(value_type, payload)
+                .is_none_or(|value| (range.start as i64..=range.end as i64).contains(&value)),
+            Constraints::Length(max_length) => trimmed(payload).len() as u64 <= *max_length,
+            _ => true,
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(CommandError::InvalidData),
+        }
+    }
+
+    fn read(&mut self) -> CommandResponse {
+        let mut fv = FieldValue::from_store(self.desc, self.value);
+        match self.simulate {
+            Simulation::Static => {},
+            Simulation::Counter => fv.set_integer(fv.get_integer().wrapping_add(1)),
+            Simulation::Random => match &self.desc.constraints {
+                Constraints::Range(range) => fv.set_integer(range.start as i64 + (random() % range.end.abs_diff(range.start).max(1)) as i64),
+                _ if self.desc.variant.bits() == ValueType::Toggle as u8 => fv.set_enabled(random() % 2 == 1),
+                _ if !self.options.is_empty() => {
+                    let code = random() as usize % self.options.len();
+                    fv.set_status(code as u8, self.options[code]);
+                },
+                _ => fv.set_integer((random() % 100) as i64),
+            },
+        }
+        if self.simulate != Simulation::Static {
+            self.value = fv.clone().into_store_bytes();
+        }
+        fv.into()
+    }
+}
+
+/// Layout entry as sent in the layout response, with the text of headings
+type LayoutItem = ([u8; 2], Option<&'static str>);
+
+struct MockSection {
+    desc: &'static EntryDesc,
+    layout: Vec<LayoutItem>,
+}
+
+/// An in-memory device described by a [`Schema`], for developing hosts before there is firmware.
+///
+/// Written props are kept in memory, and in a JSON file if one is given with [`with_state`](Self::with_state).
+/// Any secret logs in, but writes and actions still need the access level of their entry.
+pub struct MockDevice {
+    props: Vec<MockField>,
+    info: Vec<MockField>,
+    sections: Vec<MockSection>,
+    actions: Vec<&'static EntryDesc>,
+    state: Option<PathBuf>,
+    /// Access level of the last login
+    access: AccessLevel,
+    log: Vec<([u8; 64], [u8; 64])>,
+}
+
+impl MockDevice {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| eyre!("{}: {e}", path.display()).into())
+    }
+
+    pub fn parse(schema: &str) -> Result<Self, Box<dyn Error>> {
+        Self::new(toml::from_str(schema)?)
+    }
+
+    pub fn new(schema: Schema) -> Result<Self, Box<dyn Error>> {
+        let mut device = Self {
+            props: schema.props.iter().map(|p| MockField::new(p, false)).collect::<Result<_, _>>()?,
+            info: schema.info.iter().map(|i| MockField::new(i, true)).collect::<Result<_, _>>()?,
+            sections: vec![],
+            actions: vec![],
+            state: None,
+            access: AccessLevel::Public,
+            log: vec![],
+        };
+        for action_schema in &schema.actions {
+            check_name(&action_schema.name)?;
+            let mut entry = action(leak(&action_schema.name)).with_access(parse_access(&action_schema.access)?);
+            entry.help = action_schema.help.as_deref().map(leak);
+            entry.icon = action_schema.icon.as_deref().map(leak);
+            device.actions.push(Box::leak(Box::new(entry.as_entry())));
+        }
+        for section_schema in &schema.sections {
+            check_name(&section_schema.name)?;
+            let mut entry = section(leak(&section_schema.name));
+            entry.help = section_schema.help.as_deref().map(leak);
+            entry.icon = section_schema.icon.as_deref().map(leak);
+            device.sections.push(MockSection { desc: Box::leak(Box::new(entry.as_entry())), layout: vec![] });
+        }
+        for (si, section_schema) in schema.sections.iter().enumerate() {
+            let layout = section_schema.layout.iter()
+                .map(|item| device.layout_entry(item))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| eyre!("Section \"{}\": {e}", section_schema.name))?;
+            device.sections[si].layout = layout;
+        }
+        Ok(device)
+    }
+
+    /// Resolves a layout item by entry name, looking at props, info, sections and actions in that order
+    fn layout_entry(&self, item: &str) -> Result<LayoutItem, Box<dyn Error>> {
+        if item == "separator" {
+            return Ok(([LAYOUT_SEPARATOR, 0], None))
+        }
+        if let Some(text) = item.strip_prefix("heading:") {
+            return Ok(([LAYOUT_HEADING, 0], Some(leak(text.trim()))))
+        }
+        let found = [
+            (EntryType::Prop, position(self.props.iter().map(|p| p.desc), item)),
+            (EntryType::Info, position(self.info.iter().map(|i| i.desc), item)),
+            (EntryType::Section, position(self.sections.iter().map(|s| s.desc), item)),
+            (EntryType::Action, position(self.actions.iter().copied(), item)),
+        ].into_iter().find_map(|(entry_type, index)| index.map(|i| [entry_type as u8, i as u8]));
+        Ok((found.ok_or(eyre!("No entry named \"{item}\""))?, None))
+    }
+
+    /// Keeps written props in a JSON file, loading the values already in it
+    pub fn with_state(mut self, path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        if path.exists() {
+            let values: BTreeMap<String, String> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            for (name, hex) in values {
+                // props that were removed from the schema are dropped with the next write
+                if let Some(field) = self.props.iter_mut().find(|p| p.desc.name == name) {
+                    field.value = [0; 64];
+                    unhex(&hex, &mut field.value)?;
+                }
+            }
+        }
+        self.state = Some(path);
+        Ok(self)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.state else {
+            return Ok(())
+        };
+        let values: BTreeMap<&str, String> = self.props.iter()
+            .map(|p| (p.desc.name, format_hex(trimmed(&p.value), "")))
+            .collect();
+        fs::write(path, serde_json::to_string_pretty(&values)?)?;
+        Ok(())
+    }
+
+    fn check_access(&self, required: AccessLevel) -> Result<(), CommandError> {
+        match self.access >= required {
+            true => Ok(()),
+            false => Err(CommandError::Locked),
+        }
+    }
+
+    fn write_prop(&mut self, index: u8, payload: &[u8]) -> Result<CommandResponse, CommandError> {
+        let field = self.props.get(index as usize).ok_or(CommandError::InvalidField)?;
+        if field.desc.readonly {
+            return Err(CommandError::NotSupported)
+        }
+        self.check_access(field.desc.access)?;
+        field.check(payload)?;
+        let field = &mut self.props[index as usize];
+        field.value = FieldValue::from_message(field.desc, payload).into_store_bytes();
+        self.saved()
+    }
+
+    fn saved(&self) -> Result<CommandResponse, CommandError> {
+        self.save().map_err(|e| {
+            eprintln!("Failed to save mock state: {e}");
+            CommandError::Failed
+        })?;
+        Ok(CommandResponse::ok())
+    }
+
+    fn query(&self, request: &[u8; 64]) -> Result<CommandResponse, CommandError> {
+        let [_, entry_type, index, target, lo, hi, ..] = *request;
+        let entry_type = EntryType::try_from(entry_type).map_err(|_| CommandError::InvalidEntry)?;
+        let index = index as usize;
+        let target_index = u16::from_le_bytes([lo, hi]) as usize;
+        let (desc, field, section) = match entry_type {
+            EntryType::Prop => self.props.get(index).map(|p| (p.desc, Some(p), None)).ok_or(CommandError::InvalidField)?,
+            EntryType::Info => self.info.get(index).map(|i| (i.desc, Some(i), None)).ok_or(CommandError::InvalidField)?,
+            EntryType::Section => self.sections.get(index).map(|s| (s.desc, None, Some(s))).ok_or(CommandError::InvalidSection)?,
+            EntryType::Action => self.actions.get(index).map(|a| (*a, None, None)).ok_or(CommandError::InvalidAction)?,
+        };
+        match QueryTargetKey::try_from(target).map_err(|_| CommandError::InvalidQuery)? {
+            QueryTargetKey::Field => Ok(desc.into()),
+            QueryTargetKey::Help => desc.help.ok_or(CommandError::NoContent).map(Into::into),
+            QueryTargetKey::Icon => desc.icon.ok_or(CommandError::NoContent).map(Into::into),
+            QueryTargetKey::Option => {
+                let field = field.filter(|f| !f.options.is_empty()).ok_or(CommandError::NotSupported)?;
+                field.options.get(target_index).copied().ok_or(CommandError::InvalidOption).map(Into::into)
+            },
+            QueryTargetKey::Default => field.map(|f| FieldValue::from_store(f.desc, f.default).into()).ok_or(CommandError::InvalidQuery),
+            QueryTargetKey::Layout => section.map(|s| CommandResponse::from_payload(s.layout.iter().take(31).flat_map(|(bits, _)| *bits)))
+                .ok_or(CommandError::InvalidQuery),
+            QueryTargetKey::Heading => match section.ok_or(CommandError::InvalidQuery)?.layout.get(target_index) {
+                Some((_, Some(text))) => Ok((*text).into()),
+                Some(_) => Err(CommandError::NoContent),
+                None => Err(CommandError::InvalidField),
+            },
+            QueryTargetKey::Condition | QueryTargetKey::Result => Err(CommandError::NoContent),
+            QueryTargetKey::Param | QueryTargetKey::ParamOption => Err(CommandError::InvalidQuery),
+        }
+    }
+
+    /// Answers a request like a device would
+    pub fn handle(&mut self, request: &[u8; 64]) -> CommandResponse {
+        self.respond(request).unwrap_or_else(CommandResponse::error)
+    }
+
+    fn respond(&mut self, request: &[u8; 64]) -> Result<CommandResponse, CommandError> {
+        let index = request[1];
+        match CommandKey::try_from(request[0]).map_err(|_| CommandError::InvalidCommand)? {
+            CommandKey::Meta => Ok(CommandResponse::from_payload([
+                PROTO_VERSION,
+                self.sections.len() as u8,
+                self.props.len() as u8,
+                self.info.len() as u8,
+                self.actions.len() as u8,
+            ])),
+            CommandKey::ReadProp => Ok(self.props.get_mut(index as usize).ok_or(CommandError::InvalidField)?.read()),
+            CommandKey::WriteProp => self.write_prop(index, &request[2..]),
+            CommandKey::ReadInfo => Ok(self.info.get_mut(index as usize).ok_or(CommandError::InvalidField)?.read()),
+            CommandKey::Query => self.query(request),
+            CommandKey::Action => {
+                let action = self.actions.get(index as usize).ok_or(CommandError::InvalidAction)?;
+                self.check_access(action.access)?;
+                Ok(CommandResponse::ok())
+            },
+            CommandKey::ResetProp => {
+                let field = self.props.get(index as usize).ok_or(CommandError::InvalidField)?;
+                self.check_access(field.desc.access)?;
+                let field = &mut self.props[index as usize];
+                field.value = field.default;
+                self.saved()
+            },
+            CommandKey::ResetAll => {
+                self.check_access(AccessLevel::Admin)?;
+                self.props.iter_mut().for_each(|p| p.value = p.default);
+                self.saved()
+            },
+            // any secret is accepted, so that hosts can try out access levels
+            CommandKey::Login => {
+                self.access = AccessLevel::try_from(index).map_err(|_| CommandError::InvalidData)?;
+                Ok(CommandResponse::from_payload([self.access as u8]))
+            },
+            CommandKey::Logout => {
+                self.access = AccessLevel::Public;
+                Ok(CommandResponse::ok())
+            },
+            CommandKey::Noop => Ok(CommandResponse::ok()),
+            _ => Err(CommandError::NotSupported),
+        }
+    }
+}
+
+impl ElytraDevice for MockDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        Ok(self.handle(&bytes).as_bytes().try_into()?)
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.log.push((bytes_out, bytes_in));
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        std::mem::take(&mut self.log)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use elytra_conf::entry::ExtraFlags;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [[props]]
+        name = "Brightness Offset"
+        type = "integer"
+        help = "Adjustment of the display brightness"
+        range = [-1500, 1500]
+        value = 10
+
+        [[props]]
+        name = "Hour Format"
+        type = "options"
+        options = ["24 hour", "12 hour"]
+        value = "12 hour"
+
+        [[info]]
+        name = "Uptime"
+        type = "integer"
+        simulate = "counter"
+
+        [[info]]
+        name = "Firmware"
+        value = "v1.2.3"
+
+        [[sections]]
+        name = "Display"
+        icon = "contrast"
+        layout = ["heading:Brightness", "Brightness Offset", "separator", "Hour Format", "Uptime"]
+
+        [[actions]]
+        name = "Reboot"
+        access = "admin"
+    "#;
+
+    #[test]
+    fn schema_entries() {
+        let mut mock = MockDevice::parse(SCHEMA).unwrap();
+        let device: &mut dyn ElytraDevice = &mut mock;
+        let info = device.get_info().unwrap();
+        assert_eq!((1, 2, 2, 1), (info.section_count, info.prop_count, info.info_count, info.action_count));
+
+        let brightness = device.get_entry(EntryType::Prop as u8, 0).unwrap();
+        assert_eq!("Brightness Offset", brightness.name);
+        assert!(brightness.flags.contains(ExtraFlags::HasHelp));
+        assert_eq!([(-1500i32).to_le_bytes(), 1500i32.to_le_bytes()].concat(), brightness.constraints);
+        assert!(device.get_entry(EntryType::Action as u8, 0).unwrap().flags.contains(ExtraFlags::RequiresAdmin));
+        let option = device.send_command(&[CommandKey::Query as u8, EntryType::Prop as u8, 1, QueryTargetKey::Option as u8, 1, 0]).unwrap();
+        assert_eq!(b"12 hour", trimmed(&option[1..]));
+
+        use crate::LayoutEntry::*;
+        let layout = device.get_layout(0).unwrap();
+        assert!(matches!(layout[..], [Heading(0), Prop(0), Separator, Prop(1), Info(0)]));
+        assert_eq!("Brightness", device.get_heading(0, 0).unwrap());
+    }
+
+    #[test]
+    fn values() {
+        let mut mock = MockDevice::parse(SCHEMA).unwrap();
+        let device: &mut dyn ElytraDevice = &mut mock;
+        assert_eq!(10i64.to_le_bytes(), device.get_value(EntryType::Prop, 0).unwrap()[1..9]);
+        assert_eq!([1, 0], device.get_value(EntryType::Prop, 1).unwrap()[1..3]);
+
+        // writes out of the range are rejected, like on a device
+        assert!(device.set_value(EntryType::Prop, 0, &5000i64.to_le_bytes()).is_err());
+        device.set_value(EntryType::Prop, 0, &1500i64.to_le_bytes()).unwrap();
+        assert_eq!(1500i64.to_le_bytes(), device.get_value(EntryType::Prop, 0).unwrap()[1..9]);
+        assert_eq!(10i64.to_le_bytes(), device.get_default(EntryType::Prop, 0).unwrap()[1..9]);
+        device.reset_prop(0).unwrap();
+        assert_eq!(10i64.to_le_bytes(), device.get_value(EntryType::Prop, 0).unwrap()[1..9]);
+
+        assert!(device.set_value(EntryType::Info, 1, b"v2").is_err());
+        assert_eq!(b"v1.2.3", &device.get_value(EntryType::Info, 1).unwrap()[1..7]);
+        assert_eq!(1i64.to_le_bytes(), device.get_value(EntryType::Info, 0).unwrap()[1..9]);
+        assert_eq!(2i64.to_le_bytes(), device.get_value(EntryType::Info, 0).unwrap()[1..9]);
+    }
+
+    #[test]
+    fn write_checks() {
+        let schema = r#"
+            [[props]]
+            name = "Label"
+            max_length = 4
+
+            [[props]]
+            name = "Pin"
+            access = "admin"
+
+            [[actions]]
+            name = "Reboot"
+            access = "user"
+        "#;
+        let mut mock = MockDevice::parse(schema).unwrap();
+        let mut write = |index: u8, value: &[u8]| {
+            let mut request = [0u8; 64];
+            request[..2].copy_from_slice(&[CommandKey::WriteProp as u8, index]);
+            request[2..2 + value.len()].copy_from_slice(value);
+            mock.handle(&request).as_bytes()[..2].to_vec()
+        };
+        assert_eq!([1, 0], write(0, b"abcd")[..]);
+        assert_eq!([0, CommandError::InvalidData as u8], write(0, b"abcde")[..]);
+        assert_eq!([0, CommandError::Locked as u8], write(1, b"1234")[..]);
+
+        let device: &mut dyn ElytraDevice = &mut mock;
+        assert!(device.run_action(0, &[]).is_err());
+        device.login(AccessLevel::User, "any").unwrap();
+        device.run_action(0, &[]).unwrap();
+        assert!(device.set_value(EntryType::Prop, 1, b"1234").is_err());
+        device.login(AccessLevel::Admin, "any").unwrap();
+        device.set_value(EntryType::Prop, 1, b"1234").unwrap();
+        device.logout().unwrap();
+        assert!(device.reset_prop(1).is_err());
+    }
+
+    #[test]
+    fn random_in_wide_range() {
+        let schema = "[[info]]\nname = \"Noise\"\ntype = \"integer\"\nrange = [-2147483648, 2147483647]\nsimulate = \"random\"";
+        let mut device: Box<dyn ElytraDevice> = Box::new(MockDevice::parse(schema).unwrap());
+        for _ in 0..100 {
+            let value = i64::from_le_bytes(device.get_value(EntryType::Info, 0).unwrap()[1..9].try_into().unwrap());
+            assert!((i32::MIN as i64..i32::MAX as i64).contains(&value));
+        }
+    }
+
+    #[test]
+    fn persisted_state() {
+        let path = std::env::temp_dir().join(format!("elytra-mock-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut device: Box<dyn ElytraDevice> = Box::new(MockDevice::parse(SCHEMA).unwrap().with_state(path.clone()).unwrap());
+        device.set_value(EntryType::Prop, 0, &(-20i64).to_le_bytes()).unwrap();
+
+        let mut device: Box<dyn ElytraDevice> = Box::new(MockDevice::parse(SCHEMA).unwrap().with_state(path.clone()).unwrap());
+        assert_eq!((-20i64).to_le_bytes(), device.get_value(EntryType::Prop, 0).unwrap()[1..9]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        assert!(MockDevice::parse("[[props]]\nname = \"A\"\ntype = \"float\"").is_err());
+        assert!(MockDevice::parse("[[props]]\nname = \"A\"\ntype = \"integer\"\nvalue = \"ten\"").is_err());
+        assert!(MockDevice::parse("[[props]]\nname = \"A\"\nsimulate = \"counter\"").is_err());
+        assert!(MockDevice::parse("[[sections]]\nname = \"S\"\nlayout = [\"B\"]").is_err());
+        assert!(MockDevice::parse("[[props]]\nname = \"A\"\ncolour = \"red\"").is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::error::Error;
+
+use elytra_conf::{command::{Command, CommandResponse}, config::Config, traits::{ActionIndex, InfoIndex, PropIndex, SectionIndex}};
+
+use crate::ElytraDevice;
+
+/// Device running a command handler in-process, for testing handler logic without building it for WASM.
+///
+/// Meta and query commands are answered from the config, all other commands are passed to the handler.
+pub struct NativeDevice<const L: usize, S, P, I, A, F>
+where S: SectionIndex + 'static, P: PropIndex + 'static, I: InfoIndex + 'static, A: ActionIndex + 'static {
+    config: &'static Config<L, S, P, I, A>,
+    handler: F,
+    log: Vec<([u8; 64], [u8; 64])>,
+}
+
+impl <const L: usize, S, P, I, A, F> NativeDevice<L, S, P, I, A, F>
+where
+    S: SectionIndex + 'static, P: PropIndex + 'static, I: InfoIndex + 'static, A: ActionIndex + 'static,
+    F: FnMut(Command<A, P, I, S>) -> CommandResponse,
+{
+    pub fn new(config: &'static Config<L, S, P, I, A>, handler: F) -> Self {
+        Self { config, handler, log: vec![] }
+    }
+
+    /// Handles the command like a device would, including the parsing of the request
+    pub fn handle(&mut self, bytes: &[u8; 64]) -> CommandResponse {
+        match Command::from_bytes(bytes) {
+            Ok(Command::Meta) => self.config.handle_meta(),
+            Ok(Command::Query((entry_index, target))) => self.config.handle_query(entry_index, target).into(),
+            Ok(command) => (self.handler)(command),
+            Err(e) => CommandResponse::error(e),
+        }
+    }
+}
+
+impl <const L: usize, S, P, I, A, F> ElytraDevice for NativeDevice<L, S, P, I, A, F>
+where
+    S: SectionIndex + 'static, P: PropIndex + 'static, I: InfoIndex + 'static, A: ActionIndex + 'static,
+    F: FnMut(Command<A, P, I, S>) -> CommandResponse + Send,
+    Config<L, S, P, I, A>: Sync,
+{
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        Ok(self.handle(&bytes).as_bytes().try_into()?)
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.log.push((bytes_out, bytes_in));
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        std::mem::take(&mut self.log)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use elytra_conf::{elytra, command::CommandError, config::EntryType, field::FieldValue, job::Jobs, prelude::*};
+
+    use std::sync::{Arc, Mutex};
+
+    use elytra_conf::{command::CHUNK_SIZE, dfu::{Crc32, Dfu, DfuCommand}};
+
+    use super::*;
+
+    elytra!(CONF: Conf {
+        info: InfoField {
+            Uptime: info("Uptime")
+        },
+        props: PropField {
+            Volume: integer("Volume")
+                .writable()
+                .with_range(0..100)
+        },
+        sections: Section {
+            Main: section("Main")
+        },
+        actions: Action { },
+        layout: {
+            Section::Main: [
+                Field::Prop(PropField::Volume),
+                Field::Info(InfoField::Uptime)
+            ]
+        }
+    });
+
+    fn device() -> Box<dyn ElytraDevice> {
+        let mut volume = 0;
+        Box::new(NativeDevice::new(&CONF, move |command| match command {
+            Command::ReadProp(prop) => {
+                let mut value = FieldValue::new(prop.get_entry());
+                value.set_integer(volume);
+                value.into()
+            },
+            Command::WriteProp((_, value)) => {
+                volume = value.get_integer();
+                CommandResponse::ok()
+            },
+            _ => CommandResponse::error(CommandError::NotSupported),
+        }))
+    }
+
+    #[test]
+    fn native_handler() {
+        let mut device = device();
+        let info = device.get_info().unwrap();
+        assert_eq!((1, 1, 1), (info.section_count, info.prop_count, info.info_count));
+        let entry = device.get_entry(EntryType::Prop as u8, 0).unwrap();
+        assert_eq!("Volume", entry.name);
+
+        device.set_value(EntryType::Prop, 0, &42i64.to_le_bytes()).unwrap();
+        assert_eq!(42i64.to_le_bytes(), device.get_value(EntryType::Prop, 0).unwrap()[1..9]);
+        assert_eq!([0, CommandError::NotSupported as u8], device.get_value(EntryType::Info, 0).unwrap()[..2]);
+        assert!(device.get_entry(EntryType::Prop as u8, 1).is_err());
+    }
+
+    #[test]
+    fn writable_conditions() {
+        elytra!(CONF: Conf {
+            info: InfoField { },
+            props: PropField {
+                Mode: option("Mode", &["Auto", "Manual"])
+                    .with_default_options(&[0]),
+                Level: integer("Level")
+                    .writable()
+            },
+            sections: Section {
+                Main: section("Main")
+            },
+            actions: Action { },
+            layout: {
+                Section::Main: [
+                    Field::Prop(PropField::Mode),
+                    Field::Prop(PropField::Level) => writable_when(PropField::Mode).equals(1)
+                ]
+            }
+        });
+
+        // the handler doesn't check conditions, so only the client keeps the write from happening
+        let mut stored = [[0; 64]; 2];
+        let mut device: Box<dyn ElytraDevice> = Box::new(NativeDevice::new(&CONF, move |command| match command {
+            Command::ReadProp(prop) => FieldValue::from_store(prop.get_entry(), stored[prop.as_index()]).into(),
+            Command::WriteProp((prop, value)) => {
+                stored[prop.as_index()] = value.into_store_bytes();
+                CommandResponse::ok()
+            },
+            _ => CommandResponse::error(CommandError::NotSupported),
+        }));
+        assert!(device.check_writable(1).is_err());
+        assert!(device.check_writable(0).is_ok());
+        device.set_value(EntryType::Prop, 0, &1u16.to_le_bytes()).unwrap();
+        assert!(device.check_writable(1).is_ok());
+    }
+
+    type RamTarget = elytra_conf::dfu::RamTarget<1024>;
+
+    /// A device that rejects the writes at `corrupt_offset` the first `corrupt_count` times,
+    /// like a device that got a chunk corrupted in transfer
+    fn dfu_device(dfu: Arc<Mutex<Dfu<RamTarget>>>, corrupt_offset: u32, mut corrupt_count: usize) -> Box<dyn ElytraDevice> {
+        Box::new(NativeDevice::new(&CONF, move |command| match command {
+            Command::Dfu(DfuCommand::Write { offset, .. }) if offset == corrupt_offset && corrupt_count > 0 => {
+                corrupt_count -= 1;
+                CommandResponse::error(CommandError::InvalidData)
+            },
+            Command::Dfu(command) => dfu.lock().unwrap().handle(command),
+            _ => CommandResponse::error(CommandError::NotSupported),
+        }))
+    }
+
+    #[test]
+    fn flash_image() {
+        let image: Vec<u8> = (0..700).map(|i| (i * 7) as u8).collect();
+        let dfu = Arc::new(Mutex::new(Dfu::new(RamTarget::default())));
+        let mut device = dfu_device(dfu.clone(), 2 * CHUNK_SIZE as u32, 2);
+
+        let mut progress = vec![];
+        let crc = device.flash_image(&image, 2, |_, offset| progress.push(offset)).unwrap();
+        assert_eq!(Crc32::checksum(&image), crc);
+        // The corrupted chunk is retried twice from the same offset
+        assert_eq!(3, progress.iter().filter(|o| **o == 2 * CHUNK_SIZE).count());
+        assert_eq!(Some(&image.len()), progress.last());
+        assert_eq!(image, dfu.lock().unwrap().target().partition.as_slice()[..image.len()]);
+
+        device.dfu_commit().unwrap();
+        assert_eq!(Some((image.len() as u32, crc)), dfu.lock().unwrap().target().committed);
+    }
+
+    #[test]
+    fn flash_image_gives_up() {
+        let image = [0x42u8; 300];
+        let dfu = Arc::new(Mutex::new(Dfu::new(RamTarget::default())));
+        let mut device = dfu_device(dfu.clone(), CHUNK_SIZE as u32, 3);
+
+        assert!(device.flash_image(&image, 2, |_, _| ()).is_err());
+        assert!(device.dfu_verify().is_err());
+        assert!(device.dfu_commit().is_err());
+        assert_eq!(None, dfu.lock().unwrap().target().committed);
+    }
+
+    #[test]
+    fn flash_image_detects_silent_corruption() {
+        let image = [0x42u8; 300];
+        let dfu = Arc::new(Mutex::new(Dfu::new(RamTarget::default())));
+        let target = dfu.clone();
+        let mut device: Box<dyn ElytraDevice> = Box::new(NativeDevice::new(&CONF, move |command| match command {
+            Command::Dfu(DfuCommand::Write { offset, chunk }) => {
+                let mut data = chunk.as_slice().to_vec();
+                data[0] ^= 0x01;
+                target.lock().unwrap().write(offset, &data)
+                    .map(|o| CommandResponse::from_payload(o.to_le_bytes())).into()
+            },
+            Command::Dfu(command) => target.lock().unwrap().handle(command),
+            _ => CommandResponse::error(CommandError::NotSupported),
+        }));
+
+        assert!(device.flash_image(&image, 2, |_, _| ()).is_err());
+        assert!(device.dfu_commit().is_err());
+        assert_eq!(None, dfu.lock().unwrap().target().committed);
+    }
+
+    #[test]
+    fn job_timeout() {
+        let mut jobs = Jobs::<1>::new();
+        let id = jobs.start().unwrap();
+        let mut device: Box<dyn ElytraDevice> = Box::new(NativeDevice::new(&CONF, move |command| match command {
+            Command::JobStatus(id) => jobs.status(id).into(),
+            _ => CommandResponse::error(CommandError::NotSupported),
+        }));
+        let mut polls = 0;
+        let started = std::time::Instant::now();
+        let res = device.wait_for_job(id, std::time::Duration::from_millis(300), |_| polls += 1);
+        assert!(res.is_err());
+        assert!(polls >= 2);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
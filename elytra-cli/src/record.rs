@@ -0,0 +1,351 @@
+use std::{cell::Cell, error::Error, fmt::Write as _, fs::File, io::{BufRead, BufReader, BufWriter, Write}, path::Path, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use color_eyre::eyre::eyre;
+use elytra_conf::{command::CommandKey, values::ValueType};
+
+use crate::{ElytraDevice, EntryType, format::format_hex, serial::{trimmed, unhex}};
+
+/// First line of every recording
+pub const RECORDING_HEADER: &str = "# elytra recording v1";
+
+/// A recorded request and the response (or error) it got
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    /// Time since the start of the recording
+    pub time: Duration,
+    pub request: [u8; 64],
+    pub response: Result<[u8; 64], String>,
+}
+
+/// A session with a device, saved as text so that it can be attached to bug reports:
+///
+/// ```text
+/// # elytra recording v1
+/// # transport: tcp 127.0.0.1:48000
+/// # started: 1760000000
+/// # secret props: 3
+/// 0.000120 > 6d
+/// 0.000950 < 01020406050204
+/// ```
+///
+/// Requests (`>`) and responses (`<`) are hex without the zero padding, a failed exchange has a `!` line with the error instead of a response.
+/// Values of the secret props are zeroed, see [`redact`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub transport: String,
+    /// Start of the recording, in seconds since the unix epoch
+    pub started: u64,
+    /// Indices of the props with secret values
+    pub secret_props: Vec<u8>,
+    pub exchanges: Vec<Exchange>,
+}
+
+/// Hides the secret of login commands and the values written to secret props, so that recordings can be shared
+pub fn redact(mut bytes: [u8; 64], secret_props: &[u8]) -> [u8; 64] {
+    let secret = |prop: u8| secret_props.contains(&prop);
+    let value_start = match CommandKey::try_from(bytes[0]) {
+        Ok(CommandKey::Login) => 2,
+        Ok(CommandKey::WriteProp) if secret(bytes[1]) => 2,
+        Ok(CommandKey::WriteGroupProp) if secret(bytes[3]) => 4,
+        // prop, offset and chunk length are kept
+        Ok(CommandKey::BlobWrite) if secret(bytes[1]) => 7,
+        _ => return bytes,
+    };
+    bytes[value_start..].fill(0);
+    bytes
+}
+
+/// Hides the values of secret props read by `request`
+pub fn redact_response(request: &[u8; 64], mut response: [u8; 64], secret_props: &[u8]) -> [u8; 64] {
+    let secret = |prop: u8| secret_props.contains(&prop);
+    let value_start = match CommandKey::try_from(request[0]) {
+        _ if response[0] != 1 => return response,
+        Ok(CommandKey::ReadProp) if secret(request[1]) => 1,
+        Ok(CommandKey::ReadGroupProp) if secret(request[3]) => 1,
+        // total and chunk length are kept
+        Ok(CommandKey::BlobRead) if secret(request[1]) => 6,
+        _ => return response,
+    };
+    response[value_start..].fill(0);
+    response
+}
+
+/// Indices of the props of `device` with secret values
+pub fn secret_props(device: &mut (dyn ElytraDevice + 'static)) -> Result<Vec<u8>, Box<dyn Error>> {
+    let count = device.get_info()?.prop_count as usize;
+    let props = device.get_entries(EntryType::Prop as u8, count)?;
+    Ok((0..).zip(props).filter(|(_, p)| p.variant == ValueType::Secret as u8).map(|(i, _)| i).collect())
+}
+
+fn parse_frame(hex: &str) -> Result<[u8; 64], Box<dyn Error>> {
+    let mut bytes = [0u8; 64];
+    unhex(hex, &mut bytes)?;
+    Ok(bytes)
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Self, Box<dyn Error>> {
+        let mut recording = Recording::default();
+        let mut request = None;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let err = |msg: &str| eyre!("Line {}: {msg}: \"{line}\"", number + 1);
+            if number == 0 && line != RECORDING_HEADER {
+                Err(err("Not an elytra recording"))?
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(transport) = comment.trim().strip_prefix("transport:") {
+                    recording.transport = transport.trim().to_owned();
+                } else if let Some(started) = comment.trim().strip_prefix("started:") {
+                    recording.started = started.trim().parse()?;
+                } else if let Some(props) = comment.trim().strip_prefix("secret props:") {
+                    recording.secret_props = props.split_whitespace().map(str::parse).collect::<Result<_, _>>()?;
+                }
+                continue;
+            }
+            let mut parts = line.splitn(3, ' ');
+            let (Some(time), Some(direction), rest) = (parts.next(), parts.next(), parts.next().unwrap_or_default()) else {
+                continue;
+            };
+            let time = Duration::from_secs_f64(time.parse().map_err(|_| err("Invalid time"))?);
+            match (direction, request.take()) {
+                (">", None) => request = Some(parse_frame(rest)?),
+                ("<", Some(req)) => recording.exchanges.push(Exchange { time, request: req, response: Ok(parse_frame(rest)?) }),
+                ("!", Some(req)) => recording.exchanges.push(Exchange { time, request: req, response: Err(rest.to_owned()) }),
+                _ => Err(err("Unexpected line"))?,
+            }
+        }
+        Ok(recording)
+    }
+}
+
+/// Records all exchanges with the wrapped device, writing them out as they happen
+pub struct RecordingDevice {
+    device: Box<dyn ElytraDevice>,
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    secret_props: Vec<u8>,
+}
+
+impl RecordingDevice {
+    /// Records to a file, looking up the secret props of the device first
+    pub fn create(mut device: Box<dyn ElytraDevice>, path: &Path, transport: &str) -> Result<Self, Box<dyn Error>> {
+        let secret_props = secret_props(device.as_mut())?;
+        Self::new(device, Box::new(BufWriter::new(File::create(path)?)), transport, secret_props)
+    }
+
+    pub fn new(device: Box<dyn ElytraDevice>, mut writer: Box<dyn Write + Send>, transport: &str, secret_props: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let secret = secret_props.iter().map(u8::to_string).collect::<Vec<_>>().join(" ");
+        writeln!(writer, "{RECORDING_HEADER}\n# transport: {transport}\n# started: {started}\n# secret props: {secret}")?;
+        writer.flush()?;
+        Ok(Self { device, writer, start: Instant::now(), secret_props })
+    }
+
+    fn record(&mut self, request: &[u8; 64], response: &Result<[u8; 64], Box<dyn Error>>) -> std::io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let mut lines = format!("{time:.6} > {}\n", format_hex(trimmed(&redact(*request, &self.secret_props)), ""));
+        let _ = match response {
+            Ok(response) => {
+                let response = redact_response(request, *response, &self.secret_props);
+                writeln!(lines, "{time:.6} < {}", format_hex(trimmed(&response), ""))
+            },
+            Err(e) => writeln!(lines, "{time:.6} ! {}", e.to_string().replace('\n', " ")),
+        };
+        self.writer.write_all(lines.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl ElytraDevice for RecordingDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        let response = self.device.send_command_raw(bytes);
+        self.record(&bytes, &response)?;
+        response
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.device.log_chat(bytes_out, bytes_in);
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        self.device.get_log()
+    }
+
+    fn get_device_log(&mut self) -> Vec<(log::Level, String)> {
+        self.device.get_device_log()
+    }
+}
+
+/// Answers requests from a recording, to reproduce a session without the device.
+///
+/// Requests are matched against the recorded ones in order, skipping ahead if the host sends them in a different order.
+/// Each recorded exchange is answered once, requests that aren't (or no longer are) in the recording fail.
+pub struct ReplayDevice {
+    exchanges: Vec<Option<Exchange>>,
+    cursor: usize,
+    secret_props: Vec<u8>,
+    log: Cell<Vec<([u8; 64], [u8; 64])>>,
+}
+
+impl ReplayDevice {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            exchanges: recording.exchanges.into_iter().map(Some).collect(),
+            cursor: 0,
+            secret_props: recording.secret_props,
+            log: Cell::new(vec![]),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(Recording::load(path)?))
+    }
+
+    /// Recorded exchanges that haven't been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.exchanges.iter().flatten().count()
+    }
+}
+
+impl ElytraDevice for ReplayDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        let request = redact(bytes, &self.secret_props);
+        let len = self.exchanges.len();
+        let index = (0..len).map(|i| (self.cursor + i) % len)
+            .find(|i| self.exchanges[*i].as_ref().is_some_and(|e| e.request == request))
+            .ok_or_else(|| eyre!("Request not in recording: {}", format_hex(trimmed(&request), "")))?;
+        let exchange = self.exchanges[index].take().expect("matched exchange");
+        self.cursor = index + 1;
+        Ok(exchange.response?)
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.log.get_mut().push((bytes_out, bytes_in));
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        self.log.replace(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::mock::MockDevice;
+
+    struct Counter(u8);
+
+    impl ElytraDevice for Counter {
+        fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+            if bytes[0] == b'x' {
+                Err("device went away")?
+            }
+            self.0 += 1;
+            let mut response = [0u8; 64];
+            response[..2].copy_from_slice(&[1, self.0]);
+            Ok(response)
+        }
+        fn log_chat(&mut self, _: [u8; 64], _: [u8; 64]) {}
+        fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> { vec![] }
+    }
+
+    /// Collects the written recording, so that it can be read back
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    fn frame(bytes: &[u8]) -> [u8; 64] {
+        let mut frame = [0u8; 64];
+        frame[..bytes.len()].copy_from_slice(bytes);
+        frame
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let out = Shared::default();
+        let mut device: Box<dyn ElytraDevice> = Box::new(RecordingDevice::new(Box::new(Counter(0)), Box::new(out.clone()), "test", vec![]).unwrap());
+        assert_eq!(1, device.send_command(b"m").unwrap()[1]);
+        assert_eq!(2, device.send_command(b"r\x01").unwrap()[1]);
+        device.send_command(b"k\x01\x041234").unwrap();
+        assert!(device.send_command(b"x").is_err());
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(!text.contains("31323334"), "the PIN is redacted");
+        let recording = Recording::read(text.as_bytes()).unwrap();
+        assert_eq!("test", recording.transport);
+        assert_eq!(4, recording.exchanges.len());
+
+        let mut replay = ReplayDevice::new(recording);
+        let device: &mut dyn ElytraDevice = &mut replay;
+        // requests may come in a different order
+        assert_eq!(2, device.send_command(b"r\x01").unwrap()[1]);
+        assert_eq!(1, device.send_command(b"m").unwrap()[1]);
+        assert!(device.send_command(b"m").is_err());
+        // logins match whatever the secret
+        device.send_command(b"k\x01\x040000").unwrap();
+        assert_eq!("device went away", device.send_command_raw(frame(b"x")).unwrap_err().to_string());
+        assert_eq!(0, replay.remaining());
+    }
+
+    #[test]
+    fn secret_writes() {
+        let schema = "[[props]]\nname = \"SSID\"\n\n[[props]]\nname = \"Password\"\ntype = \"secret\"";
+        let mut mock: Box<dyn ElytraDevice> = Box::new(MockDevice::parse(schema).unwrap());
+        let secret = secret_props(mock.as_mut()).unwrap();
+        assert_eq!(vec![1], secret);
+
+        let out = Shared::default();
+        let mut device: Box<dyn ElytraDevice> = Box::new(RecordingDevice::new(mock, Box::new(out.clone()), "test", secret).unwrap());
+        device.set_value(EntryType::Prop, 0, b"home").unwrap();
+        device.set_value(EntryType::Prop, 1, b"hunter2").unwrap();
+        device.get_value(EntryType::Prop, 1).unwrap();
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(text.contains(&format_hex(b"home", "")), "other props are kept");
+        assert!(!text.contains(&format_hex(b"hunter2", "")), "the password is redacted");
+        let recording = Recording::read(text.as_bytes()).unwrap();
+        assert_eq!(vec![1], recording.secret_props);
+
+        // writes match whatever the value, reads give the redacted one
+        let mut replay = ReplayDevice::new(recording);
+        let device: &mut dyn ElytraDevice = &mut replay;
+        device.set_value(EntryType::Prop, 0, b"home").unwrap();
+        device.set_value(EntryType::Prop, 1, b"letmein").unwrap();
+        assert_eq!([0; 7], device.get_value(EntryType::Prop, 1).unwrap()[1..8]);
+    }
+
+    #[test]
+    fn redacts_group_props_and_blobs() {
+        let group_write = frame(b"G\x00\x02\x01pass");
+        assert_eq!(frame(b"G\x00\x02\x01"), redact(group_write, &[1]));
+        assert_eq!(group_write, redact(group_write, &[0]));
+        assert_eq!(frame(b"B\x01\x00\x00\x00\x00\x04"), redact(frame(b"B\x01\x00\x00\x00\x00\x04pass"), &[1]));
+
+        let group_read = frame(b"g\x00\x02\x01");
+        assert_eq!(frame(b"\x01"), redact_response(&group_read, frame(b"\x01pass"), &[1]));
+        let blob_read = frame(b"b\x01\x00\x00\x00\x00");
+        let chunk = frame(b"\x01\x04\x00\x00\x00\x04pass");
+        assert_eq!(frame(b"\x01\x04\x00\x00\x00\x04"), redact_response(&blob_read, chunk, &[1]));
+        // errors are kept
+        assert_eq!(frame(b"\x00\x04field"), redact_response(&group_read, frame(b"\x00\x04field"), &[1]));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Recording::read("hello\n".as_bytes()).is_err());
+        assert!(Recording::read(format!("{RECORDING_HEADER}\n0.1 < 01\n").as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+use std::{cell::Cell, error::Error, io::{BufRead, BufReader, Read, Write}, path::Path, time::Duration};
+
+use color_eyre::eyre::eyre;
+use elytra_conf::{command::{CommandError, CommandKey}, config::QueryTargetKey};
+use serialport::SerialPort;
+
+use crate::{ElytraDevice, format::format_hex};
+
+/// Baud rate used for serial ports, USB CDC devices ignore it
+pub const BAUD_RATE: u32 = 115_200;
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the arguments or results of a command are written on a serial line
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    /// A single character, like the entry type of a query
+    Char,
+    /// An unsigned little endian number of this many bytes, as big endian hex digits
+    Number(usize),
+    /// The index of a query target, a [`Number`] left out if it and the rest of the frame are zero
+    Index,
+    /// The remaining bytes as ASCII text, see [`escape`]
+    Text,
+    /// The remaining bytes as hex digits, in order
+    Hex,
+    /// The remaining bytes as entry type characters, each followed by the index in uppercase hex digits
+    Layout,
+}
+
+use Field::*;
+
+impl Field {
+    /// Bytes of a number field
+    fn number_len(self) -> usize {
+        match self {
+            Number(len) => len,
+            _ => 2,
+        }
+    }
+}
+
+/// Fields of a request after the command character, as listed in the command table of the protocol
+fn request_fields(bytes: &[u8; 64]) -> &'static [Field] {
+    let Ok(key) = CommandKey::try_from(bytes[0]) else {
+        return &[Hex]
+    };
+    match key {
+        CommandKey::ReadProp | CommandKey::ReadInfo | CommandKey::ResetProp | CommandKey::JobStatus | CommandKey::CancelJob
+            | CommandKey::BlobDigest | CommandKey::AddInstance | CommandKey::ListInstances => &[Number(1)],
+        CommandKey::WriteProp | CommandKey::WriteInfo => &[Number(1), Text],
+        CommandKey::Query => match QueryTargetKey::try_from(bytes[3]) {
+            Ok(QueryTargetKey::ParamOption) => &[Char, Number(1), Char, Number(1), Number(2)],
+            _ => &[Char, Number(1), Char, Index],
+        },
+        CommandKey::Action => &[Number(1), Hex],
+        CommandKey::ReadGroupProp => &[Number(1), Number(1), Number(1)],
+        CommandKey::WriteGroupProp => &[Number(1), Number(1), Number(1), Text],
+        CommandKey::RemoveInstance => &[Number(1), Number(1)],
+        CommandKey::DfuBegin => &[Number(4), Number(4)],
+        CommandKey::DfuWrite => &[Number(4), Number(1), Hex],
+        CommandKey::BlobRead => &[Number(1), Number(4)],
+        CommandKey::BlobWrite => &[Number(1), Number(4), Number(1), Hex],
+        CommandKey::Login => &[Number(1), Number(1), Hex],
+        _ => &[],
+    }
+}
+
+/// Fields of a successful response to `request`, after the `=`
+fn response_fields(request: &[u8; 64]) -> &'static [Field] {
+    let Ok(key) = CommandKey::try_from(request[0]) else {
+        return &[Hex]
+    };
+    match key {
+        CommandKey::Query => match QueryTargetKey::try_from(request[3]) {
+            Ok(QueryTargetKey::Layout) => &[Layout],
+            Ok(QueryTargetKey::Field | QueryTargetKey::Param | QueryTargetKey::Result | QueryTargetKey::Condition) => &[Hex],
+            _ => &[Text],
+        },
+        CommandKey::Meta | CommandKey::ListInstances | CommandKey::BlobDigest => &[Hex],
+        CommandKey::AddInstance | CommandKey::Login => &[Number(1)],
+        CommandKey::JobStatus => &[Number(1), Number(1), Text],
+        CommandKey::DfuBegin | CommandKey::DfuWrite | CommandKey::DfuVerify => &[Number(4)],
+        CommandKey::BlobRead => &[Number(4), Number(1), Hex],
+        _ => &[Text],
+    }
+}
+
+/// Printable ASCII is kept as is, other bytes (and `\`) are escaped as `\` followed by two hex digits
+fn escape(bytes: &[u8], out: &mut String) {
+    for b in bytes {
+        match b {
+            b'\\' => out.push_str("\\5c"),
+            b' ' => out.push(' '),
+            b if b.is_ascii_graphic() => out.push(*b as char),
+            b => out.push_str(&format!("\\{b:02x}")),
+        }
+    }
+}
+
+fn encode_fields(fields: &[Field], bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    let mut rest = trimmed(bytes);
+    for field in fields {
+        match *field {
+            Char => {
+                escape(&[rest.first().copied().unwrap_or(0)], &mut out);
+                rest = rest.get(1..).unwrap_or_default();
+            },
+            Index if rest.is_empty() => {},
+            Number(_) | Index => {
+                let len = field.number_len();
+                let mut number = [0u8; 8];
+                let (taken, remaining) = rest.split_at(len.min(rest.len()));
+                number[..taken.len()].copy_from_slice(taken);
+                number[..len].iter().rev().for_each(|b| out.push_str(&format!("{b:02x}")));
+                rest = remaining;
+            },
+            Text => escape(std::mem::take(&mut rest), &mut out),
+            Hex => out.push_str(&format_hex(std::mem::take(&mut rest), "")),
+            Layout => {
+                for pair in std::mem::take(&mut rest).chunks(2) {
+                    escape(&pair[..1], &mut out);
+                    out.push_str(&format!("{:X}", pair.get(1).copied().unwrap_or(0)));
+                }
+            },
+        }
+    }
+    if !rest.is_empty() {
+        return Err(eyre!("Unexpected bytes {}", format_hex(rest, " ")))?
+    }
+    Ok(out)
+}
+
+/// Reads a character of text, unescaping it
+fn next_char(line: &str, chars: &mut std::str::Chars) -> Result<Option<u8>, Box<dyn Error>> {
+    match chars.next() {
+        None => Ok(None),
+        Some('\\') => {
+            let digits: String = chars.by_ref().take(2).collect();
+            match u8::from_str_radix(&digits, 16) {
+                Ok(b) if digits.len() == 2 => Ok(Some(b)),
+                _ => Err(eyre!("Incomplete escape in \"{line}\""))?,
+            }
+        },
+        Some(c) if c == ' ' || c.is_ascii_graphic() => Ok(Some(c as u8)),
+        Some(c) => Err(eyre!("Unescaped character {c:?} in \"{line}\""))?,
+    }
+}
+
+fn decode_fields(fields: &[Field], line: &str, into: &mut [u8]) -> Result<(), Box<dyn Error>> {
+    let mut chars = line.chars();
+    let mut out = into.iter_mut();
+    let mut push = |byte: u8| -> Result<(), Box<dyn Error>> {
+        *out.next().ok_or_else(|| eyre!("Payload too long \"{line}\""))? = byte;
+        Ok(())
+    };
+    for field in fields {
+        if chars.as_str().is_empty() {
+            break
+        }
+        match *field {
+            Char => push(next_char(line, &mut chars)?.unwrap_or(0))?,
+            Number(_) | Index => {
+                let len = field.number_len();
+                let digits = chars.as_str().get(..len * 2).ok_or_else(|| eyre!("Expected {} hex digits in \"{line}\"", len * 2))?;
+                let number = u64::from_str_radix(digits, 16)?;
+                number.to_le_bytes()[..len].iter().try_for_each(|b| push(*b))?;
+                chars = chars.as_str()[len * 2..].chars();
+            },
+            Text => {
+                while let Some(b) = next_char(line, &mut chars)? {
+                    push(b)?;
+                }
+            },
+            Hex => {
+                let rest = chars.as_str();
+                if !rest.len().is_multiple_of(2) || !rest.is_ascii() {
+                    return Err(eyre!("Invalid hex payload \"{line}\""))?
+                }
+                (0..rest.len()).step_by(2).try_for_each(|i| push(u8::from_str_radix(&rest[i..i + 2], 16)?))?;
+                chars = "".chars();
+            },
+            Layout => {
+                while let Some(entry_type) = next_char(line, &mut chars)? {
+                    let rest = chars.as_str();
+                    let digits = rest.find(|c: char| !matches!(c, '0'..='9' | 'A'..='F')).unwrap_or(rest.len());
+                    push(entry_type)?;
+                    push(u8::from_str_radix(&rest[..digits], 16).map_err(|_| eyre!("Invalid layout \"{line}\""))?)?;
+                    chars = rest[digits..].chars();
+                }
+            },
+        }
+    }
+    if !chars.as_str().is_empty() {
+        return Err(eyre!("Unexpected \"{}\" in \"{line}\"", chars.as_str()))?
+    }
+    Ok(())
+}
+
+pub(crate) fn unhex(s: &str, into: &mut [u8]) -> Result<usize, Box<dyn Error>> {
+    if !s.len().is_multiple_of(2) || s.len() / 2 > into.len() || !s.is_ascii() {
+        return Err(eyre!("Invalid hex payload \"{s}\""))?
+    }
+    for (i, byte) in into.iter_mut().take(s.len() / 2).enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(s.len() / 2)
+}
+
+/// Bytes of a frame, without the zero padding
+pub(crate) fn trimmed(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &bytes[..len]
+}
+
+/// Encodes a request frame as a serial line: the command character followed by the arguments, e.g. `r01`
+pub fn encode_request(bytes: &[u8; 64]) -> Result<String, Box<dyn Error>> {
+    if !bytes[0].is_ascii_graphic() {
+        return Err(eyre!("Invalid command byte {:02x}", bytes[0]))?
+    }
+    Ok(format!("{}{}", bytes[0] as char, encode_fields(request_fields(bytes), &bytes[1..])?))
+}
+
+pub fn decode_request(line: &str) -> Result<[u8; 64], Box<dyn Error>> {
+    let mut bytes = [0u8; 64];
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_graphic() => bytes[0] = c as u8,
+        _ => return Err(eyre!("Missing command character"))?,
+    }
+    let args = chars.as_str();
+    let mut fields = request_fields(&bytes);
+    if bytes[0] == CommandKey::Query as u8 {
+        // the fields of a query depend on its target, which is known once the start is decoded
+        let _ = decode_fields(fields, args, &mut bytes[1..]);
+        fields = request_fields(&bytes);
+        bytes[1..].fill(0);
+    }
+    decode_fields(fields, args, &mut bytes[1..])?;
+    Ok(bytes)
+}
+
+/// Encodes the response to `request` as a serial line: `=` followed by the result, e.g. `=foo`,
+/// or `!` followed by the error code in hex and message, e.g. `!4 InvalidField`
+pub fn encode_response(request: &[u8; 64], bytes: &[u8; 64]) -> Result<String, Box<dyn Error>> {
+    if bytes[0] != 0 {
+        return Ok(format!("={}", encode_fields(response_fields(request), &bytes[1..])?))
+    }
+    let message = String::from_utf8_lossy(trimmed(&bytes[2..]));
+    let message = match message.is_empty() {
+        true => format_error(bytes[1]),
+        false => message.into_owned(),
+    };
+    Ok(format!("!{:x} {}", bytes[1], message))
+}
+
+fn format_error(code: u8) -> String {
+    CommandError::try_from(code).map_or_else(|_| "Error".to_owned(), |e| e.to_string())
+}
+
+/// Decodes the response line to `request`
+pub fn decode_response(request: &[u8; 64], line: &str) -> Result<[u8; 64], Box<dyn Error>> {
+    let mut bytes = [0u8; 64];
+    if let Some(payload) = line.strip_prefix('=') {
+        bytes[0] = 1;
+        decode_fields(response_fields(request), payload, &mut bytes[1..])?;
+    } else if let Some(error) = line.strip_prefix('!') {
+        let (code, message) = error.split_once(' ').unwrap_or((error, ""));
+        bytes[1] = u8::from_str_radix(code, 16)?;
+        let message = &message.as_bytes()[..message.len().min(62)];
+        bytes[2..2 + message.len()].copy_from_slice(message);
+    } else {
+        return Err(eyre!("Invalid response line \"{line}\""))?
+    }
+    Ok(bytes)
+}
+
+/// Device on a serial port, speaking the line based serial protocol
+pub struct SerialDevice {
+    port: BufReader<Box<dyn SerialPort>>,
+    log: Cell<Vec<([u8; 64], [u8; 64])>>,
+}
+
+impl SerialDevice {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let port = serialport::new(path.to_string_lossy(), BAUD_RATE).timeout(TIMEOUT).open()?;
+        Ok(Self::from_port(port))
+    }
+
+    pub fn from_port(port: Box<dyn SerialPort>) -> Self {
+        Self { port: BufReader::new(port), log: Cell::new(vec![]) }
+    }
+}
+
+/// Reads a line without the line ending, or `None` at the end of the stream
+pub fn read_line(reader: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None)
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned()))
+}
+
+impl ElytraDevice for SerialDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+        let request = encode_request(&bytes)?;
+        let port = self.port.get_mut();
+        port.write_all(format!("{request}\n").as_bytes())?;
+        port.flush()?;
+        let line = read_line(&mut self.port)?.ok_or(eyre!("Serial port closed"))?;
+        decode_response(&bytes, &line)
+    }
+
+    fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
+        self.log.get_mut().push((bytes_out, bytes_in));
+    }
+
+    fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
+        self.log.replace(vec![])
+    }
+}
+
+/// Answers serial protocol requests from `port` with `handler`, until the port is closed.
+/// Requests the handler fails on are answered with a `Failed` error.
+pub fn serve_lines<P, F>(port: P, mut handler: F) -> Result<(), Box<dyn Error>>
+where P: Read + Write, F: FnMut([u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+    let mut port = BufReader::new(port);
+    while let Some(line) = read_line(&mut port)? {
+        if line.is_empty() {
+            continue;
+        }
+        let response = match decode_request(&line) {
+            // errors of the device are answered like errors of the command, so that the next request is still served
+            Ok(request) => handler(request).and_then(|response| encode_response(&request, &response))
+                .unwrap_or_else(|e| format!("!{:x} {}", CommandError::Failed as u8, e.to_string().replace('\n', " "))),
+            Err(e) => format!("!{:x} {e}", CommandError::InvalidCommand as u8),
+        };
+        let port = port.get_mut();
+        port.write_all(format!("{response}\n").as_bytes())?;
+        port.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(bytes: &[u8]) -> [u8; 64] {
+        let mut frame = [0u8; 64];
+        frame[..bytes.len()].copy_from_slice(bytes);
+        frame
+    }
+
+    #[test]
+    fn line_encoding() {
+        let read = frame(b"r\x01");
+        assert_eq!("r01", encode_request(&read).unwrap());
+        assert_eq!(read, decode_request("r01").unwrap());
+        assert_eq!("r00", encode_request(&frame(b"r")).unwrap());
+        assert!(decode_request("r0").is_err());
+        assert!(decode_request("r0100").is_err());
+
+        let option = frame(b"qp\x00o\x09\x00");
+        assert_eq!("qp00o0009", encode_request(&option).unwrap());
+        assert_eq!(option, decode_request("qp00o0009").unwrap());
+        assert_eq!("qs01l", encode_request(&frame(b"qs\x01l")).unwrap());
+        let param_option = frame(b"qa\x02O\x01\x03\x01");
+        assert_eq!("qa02O010103", encode_request(&param_option).unwrap());
+        assert_eq!(param_option, decode_request("qa02O010103").unwrap());
+
+        // numbers are big endian, values are text
+        let blob_write = frame(b"B\x03\x00\x01\x00\x00\x02\xca\xfe");
+        assert_eq!("B030000010002cafe", encode_request(&blob_write).unwrap());
+        assert_eq!(blob_write, decode_request("B030000010002cafe").unwrap());
+        let write = frame(b"w\x02foo b\\\n");
+        assert_eq!("w02foo b\\5c\\0a", encode_request(&write).unwrap());
+        assert_eq!(write, decode_request("w02foo b\\5c\\0a").unwrap());
+        assert!(decode_request("w02foo\\0").is_err());
+
+        assert_eq!("=foo", encode_response(&read, &frame(b"\x01foo")).unwrap());
+        assert_eq!(frame(b"\x01foo"), decode_response(&read, "=foo").unwrap());
+        assert!(decode_response(&read, &format!("={}", "a".repeat(64))).is_err());
+        let layout = frame(b"\x01i\x00i\x01p\x00p\x01p\x02a\x0b");
+        assert_eq!("=i0i1p0p1p2aB", encode_response(&frame(b"qs\x01l"), &layout).unwrap());
+        assert_eq!(layout, decode_response(&frame(b"qs\x01l"), "=i0i1p0p1p2aB").unwrap());
+        let dfu = frame(b"\x01\x00\x01\x00\x00");
+        assert_eq!("=00000100", encode_response(&frame(b"U"), &dfu).unwrap());
+        assert_eq!(dfu, decode_response(&frame(b"U"), "=00000100").unwrap());
+
+        let mut error = [0u8; 64];
+        error[1] = CommandError::InvalidField as u8;
+        assert_eq!("!4 InvalidField", encode_response(&read, &error).unwrap());
+        assert_eq!(CommandError::InvalidField as u8, decode_response(&read, "!4 Invalid Field").unwrap()[1]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serial_loopback() {
+        let (master, slave) = serialport::TTYPort::pair().unwrap();
+        std::thread::spawn(move || {
+            let _ = serve_lines(master, |mut bytes| {
+                if bytes[0] == b'x' {
+                    Err("device went away")?
+                }
+                bytes[0] = 1;
+                Ok(bytes)
+            });
+        });
+        let mut device = SerialDevice::from_port(Box::new(slave));
+        assert_eq!([1, 2, 3, 4, 0], device.send_command_raw(frame(b"g\x02\x03\x04")).unwrap()[..5]);
+        let error = device.send_command_raw(frame(b"x\x01")).unwrap();
+        assert_eq!([0, CommandError::Failed as u8], error[..2]);
+        assert_eq!(b"device went away", trimmed(&error[2..]));
+        assert_eq!([1, 5, 0, 0, 0], device.send_command_raw(frame(b"g\x05")).unwrap()[..5]);
+    }
+}
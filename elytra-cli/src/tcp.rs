@@ -1,37 +1,175 @@
-use std::{cell::Cell, io::{Read, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}};
+use std::{cell::Cell, error::Error, io::{ErrorKind, Read, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}, path::Path, sync::Arc};
+
+use elytra_conf::secure::{Handshake, Session, HANDSHAKE_LEN, KEY_LEN, SECURE_FRAME_LEN};
 
 use crate::ElytraDevice;
 
+/// Address the server listens on by default
+pub const DEFAULT_ADDR: &str = "localhost:48000";
+
+/// Parses a pre-shared key given as 64 hex digits
+pub fn parse_psk(s: &str) -> Result<[u8; KEY_LEN], String> {
+    let err = || format!("Not a valid pre-shared key, expected {} hex digits", KEY_LEN * 2);
+    if s.len() != KEY_LEN * 2 || !s.is_ascii() {
+        return Err(err())
+    }
+    let mut psk = [0u8; KEY_LEN];
+    for (i, byte) in psk.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| err())?;
+    }
+    Ok(psk)
+}
+
+/// Loads a pre-shared key from `file`, or else from the environment variable `var`,
+/// as keys given on the command line would show up in the process list
+pub fn load_psk(file: Option<&Path>, var: &str) -> Result<Option<[u8; KEY_LEN]>, Box<dyn Error>> {
+    let hex = match file {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("Can't read the key file {}: {e}", path.display()))?,
+        None => match std::env::var(var) {
+            Ok(hex) => hex,
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(Some(parse_psk(hex.trim())?))
+}
+
+fn new_handshake(psk: &[u8; KEY_LEN]) -> std::io::Result<Handshake> {
+    let mut ephemeral = [0u8; KEY_LEN];
+    getrandom::getrandom(&mut ephemeral)?;
+    Ok(Handshake::new(psk, ephemeral))
+}
+
+fn secure_error(e: elytra_conf::command::CommandError) -> std::io::Error {
+    std::io::Error::new(ErrorKind::PermissionDenied, format!("secure session: {e}"))
+}
+
+/// Reads a whole frame, or `None` if the peer closed the connection before sending one
+fn read_frame<const N: usize>(stream: &mut TcpStream) -> std::io::Result<Option<[u8; N]>> {
+    let mut frame = [0u8; N];
+    let mut read = 0;
+    while read < N {
+        match stream.read(&mut frame[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(frame))
+}
+
+/// A connection carrying any number of request/response pairs, optionally in a secure session
+pub struct TcpConnection {
+    stream: TcpStream,
+    session: Option<Session>,
+}
+
+impl TcpConnection {
+    /// Connects to a server, setting up a secure session if `psk` is given
+    pub fn connect(addrs: &[SocketAddr], psk: Option<&[u8; KEY_LEN]>) -> std::io::Result<Self> {
+        let mut stream = TcpStream::connect(addrs)?;
+        stream.set_nodelay(true)?;
+        let session = match psk {
+            Some(psk) => {
+                let mut handshake = new_handshake(psk)?;
+                stream.write_all(&handshake.request().map_err(secure_error)?)?;
+                let response = read_frame::<HANDSHAKE_LEN>(&mut stream)?.ok_or(ErrorKind::UnexpectedEof)?;
+                Some(handshake.finish(&response).map_err(secure_error)?)
+            },
+            None => None,
+        };
+        Ok(Self { stream, session })
+    }
+
+    /// Sets up the server side of an accepted connection, responding to the handshake if `psk` is given
+    pub fn accept(mut stream: TcpStream, psk: Option<&[u8; KEY_LEN]>) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let session = match psk {
+            Some(psk) => {
+                let request = read_frame::<HANDSHAKE_LEN>(&mut stream)?.ok_or(ErrorKind::UnexpectedEof)?;
+                let (response, session) = new_handshake(psk)?.respond(&request).map_err(secure_error)?;
+                stream.write_all(&response)?;
+                Some(session)
+            },
+            None => None,
+        };
+        Ok(Self { stream, session })
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn send(&mut self, bytes: &[u8; 64]) -> std::io::Result<()> {
+        match self.session.as_mut() {
+            Some(session) => self.stream.write_all(&session.encrypt(bytes).map_err(secure_error)?),
+            None => self.stream.write_all(bytes),
+        }
+    }
+
+    /// Receives the next frame, or `None` once the peer has closed the connection
+    pub fn recieve(&mut self) -> std::io::Result<Option<[u8; 64]>> {
+        match self.session.as_mut() {
+            Some(session) => match read_frame::<SECURE_FRAME_LEN>(&mut self.stream)? {
+                Some(frame) => Ok(Some(session.decrypt(&frame).map_err(secure_error)?)),
+                None => Ok(None),
+            },
+            None => read_frame(&mut self.stream),
+        }
+    }
+}
+
 pub struct TcpDevice {
     addrs: Vec<SocketAddr>,
-    log: Cell<Vec<([u8; 64], [u8; 64])>>
+    log: Cell<Vec<([u8; 64], [u8; 64])>>,
+    psk: Option<[u8; KEY_LEN]>,
+    connection: Option<TcpConnection>,
 }
 
 impl TcpDevice {
+    /// The connection is kept open for all commands, and reestablished if it drops
     pub fn new<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
-        Ok(Self{ 
-            log: Cell::new(vec![]), 
-            addrs: addr.to_socket_addrs()?.collect() 
+        Ok(Self{
+            log: Cell::new(vec![]),
+            addrs: addr.to_socket_addrs()?.collect(),
+            psk: None,
+            connection: None,
         })
     }
+
+    /// Connects with an encrypted session, authenticated by the pre-shared key `psk`
+    pub fn new_secure<A: ToSocketAddrs>(addr: A, psk: [u8; KEY_LEN]) -> std::io::Result<Self> {
+        Ok(Self { psk: Some(psk), ..Self::new(addr)? })
+    }
+
+    fn exchange(&mut self, bytes: &[u8; 64]) -> std::io::Result<[u8; 64]> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => TcpConnection::connect(&self.addrs, self.psk.as_ref())?,
+        };
+        connection.send(bytes)?;
+        let in_bytes = connection.recieve()?.ok_or(ErrorKind::UnexpectedEof)?;
+        self.connection = Some(connection);
+        Ok(in_bytes)
+    }
 }
 
 impl ElytraDevice for TcpDevice {
     fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn std::error::Error>> {
-        let mut stream = TcpStream::connect(self.addrs.as_slice())?;
-
-        stream.write_all(&bytes)?;
-
-        let mut in_bytes = [0u8; 64];
-        stream.read_exact(&mut in_bytes)?;
-
-        Ok(in_bytes)
+        let reused = self.connection.is_some();
+        match self.exchange(&bytes) {
+            // the server closed the idle connection, so the command never reached it
+            Err(e) if reused && e.kind() == ErrorKind::UnexpectedEof => Ok(self.exchange(&bytes)?),
+            result => Ok(result?),
+        }
     }
-    
+
     fn log_chat(&mut self, bytes_out: [u8; 64], bytes_in: [u8; 64]) {
         self.log.get_mut().push((bytes_out, bytes_in));
     }
-    
+
     fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
         self.log.replace(vec![])
     }
@@ -40,35 +178,187 @@ impl ElytraDevice for TcpDevice {
 
 pub struct TcpServer {
     listener: TcpListener,
-    stream: Option<TcpStream>,
-    read_buf: [u8; 64],
+    psk: Option<[u8; KEY_LEN]>,
 }
 
 impl TcpServer {
     pub fn new() -> std::io::Result<Self> {
-        let listener: TcpListener = TcpListener::bind("localhost:48000")?; 
+        Self::bind(DEFAULT_ADDR)
+    }
+
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
+        let listener: TcpListener = TcpListener::bind(addr)?;
         Ok(Self{
             listener,
-            stream: None,
-            read_buf: [0u8; 64],
+            psk: None,
         })
     }
 
-    pub fn recieve(&mut self) -> std::io::Result<&[u8]> {
-        let (mut stream, _) = self.listener.accept()?;
-        eprintln!("Got connection from {:?}", stream.peer_addr()?);
-        stream.read(self.read_buf.as_mut_slice())?;
-        self.stream = Some(stream);
-        Ok(&self.read_buf)
+    /// Requires clients to set up an encrypted session with the pre-shared key `psk`, see [`TcpDevice::new_secure`]
+    pub fn with_psk(self, psk: [u8; KEY_LEN]) -> Self {
+        Self { psk: Some(psk), ..self }
     }
 
-    pub fn respond(&mut self, bytes: &[u8]) -> std::io::Result<()> {
-        let Some(mut stream) = self.stream.as_ref() else {
-            return Err(std::io::Error::other("no current stream"));
-        };
-        stream.write(bytes)?;    
-        stream.flush()?;
-        self.stream = None;
-        Ok(())
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for the next client to connect
+    pub fn accept(&self) -> std::io::Result<TcpConnection> {
+        let (stream, _) = self.listener.accept()?;
+        TcpConnection::accept(stream, self.psk.as_ref())
+    }
+
+    /// Serves clients concurrently, each on its own thread, answering every request with `handler`.
+    /// A connection is closed when the client disconnects or `handler` fails.
+    pub fn serve<F>(&self, handler: F) -> std::io::Result<()>
+    where F: Fn([u8; 64]) -> Result<[u8; 64], Box<dyn Error>> + Send + Sync + 'static {
+        let handler = Arc::new(handler);
+        self.serve_sessions(move || {
+            let handler = handler.clone();
+            Ok(move |bytes| handler(bytes))
+        })
+    }
+
+    /// Serves clients like [`serve`](Self::serve), with a handler from `open` for each connection.
+    /// Each connection gets its own device session this way, so that one client's login or
+    /// transaction doesn't apply to the others.
+    pub fn serve_sessions<F, H>(&self, open: F) -> std::io::Result<()>
+    where
+        F: Fn() -> Result<H, Box<dyn Error>> + Send + Sync + 'static,
+        H: FnMut([u8; 64]) -> Result<[u8; 64], Box<dyn Error>>,
+    {
+        let open = Arc::new(open);
+        loop {
+            let (stream, addr) = self.listener.accept()?;
+            let (open, psk) = (open.clone(), self.psk);
+            std::thread::spawn(move || {
+                eprintln!("Got connection from {addr:?}");
+                match open().and_then(|handler| serve_connection(stream, psk.as_ref(), handler)) {
+                    Ok(()) => eprintln!("Connection from {addr:?} closed"),
+                    Err(e) => eprintln!("Connection from {addr:?} dropped: {e}"),
+                }
+            });
+        }
+    }
+}
+
+fn serve_connection<F>(stream: TcpStream, psk: Option<&[u8; KEY_LEN]>, mut handler: F) -> Result<(), Box<dyn Error>>
+where F: FnMut([u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+    let mut connection = TcpConnection::accept(stream, psk)?;
+    while let Some(bytes) = connection.recieve()? {
+        connection.send(&handler(bytes)?)?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use elytra_conf::command::{CommandError, CommandKey};
+
+    use super::*;
+
+    const PSK: [u8; KEY_LEN] = [0x42; KEY_LEN];
+
+    fn command(first: u8) -> [u8; 64] {
+        let mut command = [0u8; 64];
+        command[0] = first;
+        command
+    }
+
+    fn echo_server(psk: Option<[u8; KEY_LEN]>) -> SocketAddr {
+        let mut server = TcpServer::bind("127.0.0.1:0").unwrap();
+        if let Some(psk) = psk {
+            server = server.with_psk(psk);
+        }
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(|mut bytes| {
+            bytes.reverse();
+            Ok(bytes)
+        }));
+        addr
+    }
+
+    #[test]
+    fn psk_file() {
+        let path = std::env::temp_dir().join(format!("elytra-psk-{}", std::process::id()));
+        std::fs::write(&path, format!("{}\n", "42".repeat(KEY_LEN))).unwrap();
+        assert_eq!(Some(PSK), load_psk(Some(&path), "ELYTRA_TEST_UNSET_PSK").unwrap());
+        std::fs::write(&path, "42").unwrap();
+        assert!(load_psk(Some(&path), "ELYTRA_TEST_UNSET_PSK").is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_psk(Some(&path), "ELYTRA_TEST_UNSET_PSK").is_err());
+        assert_eq!(None, load_psk(None, "ELYTRA_TEST_UNSET_PSK").unwrap());
+    }
+
+    #[test]
+    fn secure_loopback() {
+        let mut device = TcpDevice::new_secure(echo_server(Some(PSK)), PSK).unwrap();
+        for i in 0..3u8 {
+            assert_eq!(i, device.send_command_raw(command(i)).unwrap()[63]);
+        }
+    }
+
+    #[test]
+    fn secure_wrong_psk() {
+        let mut device = TcpDevice::new_secure(echo_server(Some(PSK)), [0; KEY_LEN]).unwrap();
+        assert!(device.send_command_raw([0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn persistent_connection() {
+        let server = TcpServer::bind("127.0.0.1:0").unwrap();
+        let mut device = TcpDevice::new(server.local_addr().unwrap()).unwrap();
+        let client = std::thread::spawn(move || {
+            for i in 0..3u8 {
+                assert_eq!(i, device.send_command_raw(command(i)).unwrap()[0]);
+            }
+        });
+        // every command arrives on the same connection
+        let mut connection = server.accept().unwrap();
+        for _ in 0..3 {
+            let bytes = connection.recieve().unwrap().unwrap();
+            connection.send(&bytes).unwrap();
+        }
+        client.join().unwrap();
+        assert_eq!(None, connection.recieve().unwrap());
+    }
+
+    #[test]
+    fn concurrent_clients() {
+        let addr = echo_server(None);
+        let mut first = TcpDevice::new(addr).unwrap();
+        let mut second = TcpDevice::new(addr).unwrap();
+        assert_eq!(1, first.send_command_raw(command(1)).unwrap()[63]);
+        // the first client is still connected while the second is served
+        assert_eq!(2, second.send_command_raw(command(2)).unwrap()[63]);
+        assert_eq!(3, first.send_command_raw(command(3)).unwrap()[63]);
+    }
+
+    #[test]
+    fn session_per_client() {
+        let server = TcpServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        // writes need a login, which only lasts for the session
+        std::thread::spawn(move || server.serve_sessions(|| {
+            let mut logged_in = false;
+            Ok(move |bytes: [u8; 64]| {
+                logged_in |= bytes[0] == CommandKey::Login as u8;
+                let mut response = [0u8; 64];
+                if bytes[0] == CommandKey::WriteProp as u8 && !logged_in {
+                    response[1] = CommandError::Locked as u8;
+                } else {
+                    response[0] = 1;
+                }
+                Ok(response)
+            })
+        }));
+
+        let mut admin = TcpDevice::new(addr).unwrap();
+        let mut other = TcpDevice::new(addr).unwrap();
+        assert_eq!(1, admin.send_command_raw(command(CommandKey::Login as u8)).unwrap()[0]);
+        assert_eq!(1, admin.send_command_raw(command(CommandKey::WriteProp as u8)).unwrap()[0]);
+        let response = other.send_command_raw(command(CommandKey::WriteProp as u8)).unwrap();
+        assert_eq!([0, CommandError::Locked as u8], response[..2]);
+    }
+}
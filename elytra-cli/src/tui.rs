@@ -1,31 +1,39 @@
+use std::collections::HashMap;
 use std::thread;
 use std::{sync::mpsc::Receiver};
 
 use std::sync::mpsc::{Sender, channel};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
 
+use elytra_conf::auth::AccessLevel;
+use elytra_conf::config::EntryType;
 use elytra_conf::entry::ExtraFlags;
+use elytra_conf::values::ValueType;
 use ratatui::text::Span;
 use ratatui::prelude::*;
-use ratatui::widgets::{Clear, List, ListDirection, ListItem, Padding, Row, Table};
+use ratatui::widgets::{Clear, Gauge, List, ListDirection, ListItem, Padding, Row, Table};
 use ratatui::{
     DefaultTerminal, Frame, buffer::Buffer, layout::{Constraint, Layout, Rect}, style::Stylize, symbols::border, text::{Line, Text}, widgets::{Block, Paragraph, Widget}
 };
 
-use crate::{ElytraDevice, Entry, Info, LayoutEntry, Section};
+use crate::{ElytraDevice, Entry, FieldCondition, Info, JobStatus, LayoutEntry, LogItem, Section};
+use crate::decode::describe_exchange;
+use crate::format::{format_entry_value, numeric_value};
+use elytra_conf::condition::ConditionEffect;
+use elytra_conf::job::JobState;
 
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 enum Progress {
-    Working((String, Vec<([u8; 64], [u8; 64])>)),
-    Failed((String, Vec<([u8; 64], [u8; 64])>)),
-    Done(DeviceInfo)
+    Working((String, Vec<LogItem>)),
+    Failed((String, Vec<LogItem>)),
+    Done((Box<DeviceInfo>, Box<dyn ElytraDevice + 'static>))
 }
 
 enum AppState {
     Working(LoadingWidget),
-    Done(DeviceInfo)
+    Done(Box<DeviceInfo>)
 }
 
 pub fn run(mut device: Box<dyn ElytraDevice + 'static>) -> Result<()> {
@@ -37,13 +45,13 @@ pub fn run(mut device: Box<dyn ElytraDevice + 'static>) -> Result<()> {
 
     thread::spawn(move || {
         let final_progress = match run_worker(&mut device, tx.clone()) {
-            Ok(di) => Progress::Done(di),
-            Err(e) => Progress::Failed((format!("{:?}", e), device.get_log()))
+            Ok(di) => Progress::Done((Box::new(di), device)),
+            Err(e) => Progress::Failed((format!("{:?}", e), device.take_log()))
         };
         tx.send(final_progress).unwrap();
     });
 
-    let result = App{ rx, state: AppState::Working(LoadingWidget::new()), exit: false }.run(&mut terminal);
+    let result = App{ rx, state: AppState::Working(LoadingWidget::new()), device: None, exit: false }.run(&mut terminal);
     ratatui::restore();
     Ok(result?)
 }
@@ -53,35 +61,59 @@ fn run_worker(mut device: &mut Box<dyn ElytraDevice + 'static>, tx: Sender<Progr
     let info = device.get_info()?;
     
 
-    let mut sections = get_entries(&mut device, &tx, b's', info.section_count as usize, "sections")?;
+    let mut sections = get_entries(&mut device, &tx, EntryType::Section as u8, info.section_count as usize, "sections")?;
     get_layout(&mut device, &tx, &mut sections)?;
-    let props = get_entries(&mut device, &tx, b'c', info.prop_count as usize, "prop fields")?;
-    let infos = get_entries(&mut device, &tx, b'i', info.info_count as usize, "info fields")?;
-    let actions = get_entries(&mut device, &tx, b'a', info.action_count as usize, "actions")?;
+    let props = get_entries(&mut device, &tx, EntryType::Prop as u8, info.prop_count as usize, "prop fields")?;
+    let infos = get_entries(&mut device, &tx, EntryType::Info as u8, info.info_count as usize, "info fields")?;
+    let mut actions = get_entries(&mut device, &tx, EntryType::Action as u8, info.action_count as usize, "actions")?;
+    let _ = tx.send(Progress::Working(("  Getting action signatures".to_owned(), device.take_log())));
+    for (index, action) in actions.iter_mut().enumerate() {
+        if action.constraints[..2] != [0, 0] {
+            action.signature = Some(device.get_action_signature(action, index as u8)?);
+        }
+    }
 
 
-    tx.send(Progress::Working(("Assembling sections".to_owned(), device.get_log())))?;
+    tx.send(Progress::Working(("Assembling sections".to_owned(), device.take_log())))?;
 
     // Err(format!("Misc error: {:#?}", actions.len()))?;
 
-    let sections = sections.into_iter().map(|section_entry| {
+    let section_entries = sections.clone();
+    let mut sections: Vec<Section> = Vec::with_capacity(section_entries.len());
+    for (section_index, section_entry) in section_entries.iter().enumerate() {
         let layout = section_entry.layout.clone().unwrap_or_default();
-        let layout = layout.into_iter().map(|le| {
-            match le {
-                LayoutEntry::Prop(ci) => (le, props[ci as usize].clone()),
-                LayoutEntry::Info(ii) => (le, infos[ii as usize].clone())
-            }
-        }).collect();
+        let mut entries = Vec::with_capacity(layout.len());
+        for le in layout {
+            let entry = match le {
+                LayoutEntry::Prop(ci) => props[ci as usize].clone(),
+                LayoutEntry::Info(ii) => infos[ii as usize].clone(),
+                LayoutEntry::Section(si) => section_entries[si as usize].clone(),
+                LayoutEntry::Action(ai) => actions[ai as usize].clone(),
+                LayoutEntry::Heading(_) | LayoutEntry::Separator => device.get_layout_entry(section_index as u8, le)?,
+            };
+            entries.push((le, entry));
+        }
 
-        Section {entry: section_entry, layout}
-    }).collect();
-    
+        sections.push(Section {entry: section_entry.clone(), layout: entries, conditions: vec![]});
+    }
+
+    get_conditions(&mut device, &tx, &mut sections)?;
+
+    let (values, instances) = get_values(&mut device, &tx, &sections)?;
+    let section_order = section_order(&sections);
     
     Ok(DeviceInfo{
         info,
         sections,
+        section_order,
         actions,
-        section_index: 0
+        values,
+        instances,
+        section_index: 0,
+        job: None,
+        notice: None,
+        access: AccessLevel::Public,
+        login: None,
     })
 }
 
@@ -94,7 +126,7 @@ fn get_extras(
         apply: impl Fn(&mut Entry, String) -> (), 
         name: &str) -> Result<()> {
     let count = entries.iter().filter(|e| cond(e)).count();
-    let _ = tx.send(Progress::Working((format!("  Getting {} {}", count, name), device.get_log())));
+    let _ = tx.send(Progress::Working((format!("  Getting {} {}", count, name), device.take_log())));
     for (index, entry) in entries.iter_mut().enumerate().filter(|(_, e)| cond(e)) {
         apply(entry, device.get_extra(entry.entry_type, index as u8, extra_type)?);
     }
@@ -106,7 +138,7 @@ fn get_layout(
         device: &mut Box<dyn ElytraDevice + 'static>, 
         tx: &Sender<Progress>,
         sections: &mut Vec<Entry>) -> Result<()> {
-    let _ = tx.send(Progress::Working((format!("  Getting section layout"), device.get_log())));
+    let _ = tx.send(Progress::Working((format!("  Getting section layout"), device.take_log())));
 
     
     for (index, entry) in sections.iter_mut().enumerate() {
@@ -117,8 +149,89 @@ fn get_layout(
     Ok(())
 }
 
+fn get_conditions(
+        device: &mut Box<dyn ElytraDevice + 'static>, 
+        tx: &Sender<Progress>,
+        sections: &mut [Section]) -> Result<()> {
+    let _ = tx.send(Progress::Working(("  Getting field conditions".to_owned(), device.take_log())));
+
+    for (index, section) in sections.iter_mut().enumerate() {
+        if section.entry.flags.contains(ExtraFlags::HasConditions) {
+            section.conditions = device.get_conditions(index as u8, section.layout.len())?;
+        }
+    }
+    Ok(())
+}
+
+fn get_values(
+        device: &mut Box<dyn ElytraDevice + 'static>, 
+        tx: &Sender<Progress>,
+        sections: &[Section]) -> Result<(FieldValues, HashMap<usize, Vec<Instance>>)> {
+    let _ = tx.send(Progress::Working(("Reading field values".to_owned(), device.take_log())));
+
+    let mut values = HashMap::new();
+    let mut instances = HashMap::new();
+    for (section_index, section) in sections.iter().enumerate() {
+        if section.entry.flags.contains(ExtraFlags::IsRepeated) {
+            let _ = tx.send(Progress::Working((format!("  Reading {} instances", section.entry.name), device.take_log())));
+            let section_instances = device.list_instances(section_index as u8)?.into_iter()
+                .map(|instance| read_instance(device, section, section_index, instance))
+                .collect::<Result<Vec<_>>>()?;
+            instances.insert(section_index, section_instances);
+            continue;
+        }
+
+        for (le, entry) in &section.layout {
+            let (entry_type, index) = match le {
+                LayoutEntry::Prop(pi) => (EntryType::Prop, *pi),
+                LayoutEntry::Info(ii) => (EntryType::Info, *ii),
+                _ => continue,
+            };
+            if let Some(display) = read_field(device, entry, entry_type, index)? {
+                values.insert(*le, display);
+            }
+        }
+    }
+
+    let condition_props = sections.iter()
+        .flat_map(|s| s.conditions.iter().flatten())
+        .map(|c| LayoutEntry::Prop(c.prop))
+        .collect::<Vec<_>>();
+    for le in condition_props {
+        let LayoutEntry::Prop(pi) = le else { continue };
+        if values.contains_key(&le) { continue }
+        let entry = device.get_entry(EntryType::Prop as u8, pi)?;
+        let value = device.get_value(EntryType::Prop, pi)?;
+        if let Some(display) = FieldDisplay::from_response(&entry, &value) {
+            values.insert(le, display);
+        }
+    }
+    Ok((values, instances))
+}
+
+/// Reads the values of the props of an instance of a repeated section
+fn read_instance(device: &mut Box<dyn ElytraDevice + 'static>, section: &Section, section_index: usize, instance: u8) -> Result<Instance> {
+    let mut values = HashMap::new();
+    for (le, entry) in &section.layout {
+        let LayoutEntry::Prop(pi) = le else { continue };
+        let value = device.get_group_value(section_index as u8, instance, *pi)?;
+        if let Some(display) = FieldDisplay::from_response(entry, &value) {
+            values.insert(*le, display);
+        }
+    }
+    Ok(Instance { index: instance, values })
+}
+
+/// Reads the value of a prop or info field, along with its default
+fn read_field(device: &mut Box<dyn ElytraDevice + 'static>, entry: &Entry, entry_type: EntryType, index: u8) -> Result<Option<FieldDisplay>> {
+    let value = device.get_value(entry_type, index)?;
+    let default = device.get_default(entry_type, index).ok()
+        .and_then(|res| format_entry_value(entry, &res[1..]).ok());
+    Ok(FieldDisplay::from_response(entry, &value).map(|display| FieldDisplay { default, ..display }))
+}
+
 fn get_entries(device: &mut Box<dyn ElytraDevice + 'static>, tx: &Sender<Progress>, entry_type: u8, count: usize, n: &str) -> Result<Vec<Entry>> {
-    let _ = tx.send(Progress::Working((format!("Getting {} {}", count, n), device.get_log())));
+    let _ = tx.send(Progress::Working((format!("Getting {} {}", count, n), device.take_log())));
     // thread::sleep(std::time::Duration::from_secs(2));
     let mut entries = device.get_entries(entry_type, count as usize)?;
 
@@ -137,17 +250,145 @@ fn get_entries(device: &mut Box<dyn ElytraDevice + 'static>, tx: &Sender<Progres
     Ok(entries)
 }
 
+/// Sections in tree order with their nesting depth, nested sections follow their parent
+fn section_order(sections: &[Section]) -> Vec<(usize, usize)> {
+    fn visit(sections: &[Section], index: usize, depth: usize, order: &mut Vec<(usize, usize)>) {
+        if index >= sections.len() || order.iter().any(|(i, _)| *i == index) {
+            return
+        }
+        order.push((index, depth));
+        for (le, _) in &sections[index].layout {
+            if let LayoutEntry::Section(si) = le {
+                visit(sections, *si as usize, depth + 1, order);
+            }
+        }
+    }
+    let mut order = Vec::with_capacity(sections.len());
+    for root in crate::root_sections(sections) {
+        visit(sections, root, 0, &mut order);
+    }
+    order
+}
+
 struct DeviceInfo {
     info: Info,
     sections: Vec<Section>,
+    /// Section indices and depth, in the order they are listed
+    section_order: Vec<(usize, usize)>,
+    /// Position of the selected section in `section_order`
     section_index: usize,
+    /// The last started long-running action and its progress
+    job: Option<(String, JobStatus)>,
+    /// Outcome of the last action
+    notice: Option<String>,
+    /// Access level of the session, as far as we know: the device may have timed it out
+    access: AccessLevel,
+    /// Level and secret being typed in at the login prompt
+    login: Option<(AccessLevel, String)>,
     #[allow(unused)]
-    actions: Vec<Entry>
+    actions: Vec<Entry>,
+    values: FieldValues,
+    /// Instances of repeated sections, by section index
+    instances: HashMap<usize, Vec<Instance>>,
+}
+
+type FieldValues = HashMap<LayoutEntry, FieldDisplay>;
+
+struct Instance {
+    index: u8,
+    values: FieldValues,
+}
+
+struct FieldDisplay {
+    formatted: String,
+    /// Colour preview for colour fields
+    swatch: Option<Color>,
+    /// Value used when evaluating layout conditions
+    numeric: Option<i64>,
+    /// The formatted default value, if known
+    default: Option<String>,
+}
+
+impl FieldDisplay {
+    fn from_response(entry: &Entry, response: &[u8; 64]) -> Option<Self> {
+        if response[0] != 1 {
+            return None
+        }
+        let formatted = format_entry_value(entry, &response[1..]).ok()?;
+        let swatch = (entry.variant == ValueType::Color as u8)
+            .then(|| Color::Rgb(response[1], response[2], response[3]));
+        let numeric = ValueType::try_from(entry.variant).ok()
+            .and_then(|vt| numeric_value(vt, &response[1..]));
+        Some(Self { formatted, swatch, numeric, default: None })
+    }
+}
+
+/// Evaluates a layout condition, looking up the prop in `values` first and then in `fallback`.
+///
+/// Conditions on props without a known value are treated as matching.
+fn condition_holds(condition: &FieldCondition, values: &FieldValues, fallback: &FieldValues) -> bool {
+    let le = LayoutEntry::Prop(condition.prop);
+    values.get(&le).or_else(|| fallback.get(&le))
+        .and_then(|v| v.numeric)
+        .is_none_or(|value| condition.matches(value))
+}
+
+/// Renders the visible fields of a section layout, applying conditions against the given values
+fn layout_lines<'a>(section: &'a Section, values: &FieldValues, fallback: &FieldValues, access: AccessLevel, with_help: bool) -> Vec<Line<'a>> {
+    section.layout.iter().enumerate().flat_map(|(position, (le, e))| {
+        let action_key = section.layout[..position].iter()
+            .filter(|(le, _)| matches!(le, LayoutEntry::Action(_)))
+            .count() + 1;
+        let locked = match section.condition(position) {
+            Some(c) if !condition_holds(c, values, fallback) => match c.effect {
+                ConditionEffect::Visible => return vec![],
+                ConditionEffect::Writable => true,
+            },
+            _ => false,
+        } || e.access() > access;
+        match le {
+            LayoutEntry::Prop(_) | LayoutEntry::Info(_) => field_lines(e, values.get(le), locked, with_help),
+            LayoutEntry::Section(_) => vec![Line::from(format!("▸ {}", e.name)).fg(Color::LightCyan), Line::from("")],
+            LayoutEntry::Action(_) => vec![
+                Line::from_iter([
+                    Span::from(format!("{action_key} ")).fg(Color::DarkGray),
+                    Span::from(format!("[ {} ]", e.name)).fg(if locked { Color::DarkGray } else { Color::LightGreen }),
+                    Span::from(e.signature.as_ref().map(|s| format!(" {s}")).unwrap_or_default()).fg(Color::DarkGray),
+                ]),
+                Line::from(""),
+            ],
+            LayoutEntry::Heading(_) => vec![Line::from(e.name.as_str()).bold().underlined()],
+            LayoutEntry::Separator => vec![Line::from("─".repeat(24)).fg(Color::DarkGray), Line::from("")],
+        }
+    }).collect()
+}
+
+fn field_lines<'a>(entry: &'a Entry, value: Option<&FieldDisplay>, locked: bool, with_help: bool) -> Vec<Line<'a>> {
+    let mut lines = vec![Line::from_iter([
+        Span::from(entry.name.as_str()),
+        Span::from(value.map(|v| format!(": {}", v.formatted)).unwrap_or_default()).fg(Color::LightYellow),
+        match value.and_then(|v| v.swatch) {
+            Some(swatch) => Span::from(" ██").fg(swatch),
+            None => Span::from(""),
+        },
+        match value.and_then(|v| v.default.as_ref().filter(|d| **d != v.formatted)) {
+            Some(default) => Span::from(format!(" (default: {default})")).fg(Color::DarkGray),
+            None => Span::from(""),
+        },
+        if locked { Span::from(" (locked)").fg(Color::DarkGray) } else { Span::from("") },
+    ])];
+    if with_help {
+        lines.push(Line::from(entry.help.clone().unwrap_or_default()).fg(Color::DarkGray));
+        lines.push(Line::from(""));
+    }
+    lines
 }
 
 pub struct App {
     exit: bool,
     state: AppState,
+    /// The device, once loading has finished
+    device: Option<Box<dyn ElytraDevice + 'static>>,
     rx: Receiver<Progress>,
 }
 
@@ -161,10 +402,12 @@ impl App {
         while !self.exit {
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events()?;
+            self.poll_job();
             if let Ok(progress) = self.rx.try_recv() {
                 match progress {
-                    Progress::Done(di) => {
-                        self.state = AppState::Done(di)
+                    Progress::Done((di, device)) => {
+                        self.state = AppState::Done(di);
+                        self.device = Some(device);
                     },
                     Progress::Working((status, mut items)) => {
                         match &mut self.state {
@@ -222,14 +465,214 @@ impl App {
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        if matches!(&self.state, AppState::Done(dev_info) if dev_info.login.is_some()) {
+            return self.handle_login_key(key_event)
+        }
         match key_event.code {
             KeyCode::Char('q') => self.exit(),
             KeyCode::Up => self.update_selection(-1),
             KeyCode::Down => self.update_selection(1),
+            KeyCode::Char('c') => self.cancel_job(),
+            KeyCode::Char('r') => self.reset_section(),
+            KeyCode::Char('a') => self.add_instance(),
+            KeyCode::Char('d') => self.remove_instance(),
+            KeyCode::Char('l') => self.start_login(AccessLevel::User),
+            KeyCode::Char('L') => self.start_login(AccessLevel::Admin),
+            KeyCode::Char(c @ '1'..='9') => self.run_action(c as usize - '1' as usize),
             _ => Ok(())
         }
     }
 
+    /// Runs the nth action of the selected section, using the default parameter values
+    fn run_action(&mut self, n: usize) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        let Some((section_index, _)) = dev_info.section_order.get(dev_info.section_index) else {
+            return Ok(())
+        };
+        let Some((LayoutEntry::Action(ai), entry)) = dev_info.sections[*section_index].layout.iter()
+            .filter(|(le, _)| matches!(le, LayoutEntry::Action(_)))
+            .nth(n) else {
+            return Ok(())
+        };
+        if entry.flags.contains(ExtraFlags::IsLongRunning) {
+            dev_info.notice = None;
+            dev_info.job = match device.start_job(*ai, &[]).and_then(|id| device.get_job_status(id)) {
+                Ok(status) => Some((entry.name.clone(), status)),
+                Err(e) => {
+                    dev_info.notice = Some(format!("{} failed: {e}", entry.name));
+                    None
+                }
+            };
+            return Ok(())
+        }
+        dev_info.notice = Some(match device.run_action(*ai, &[]) {
+            Ok(res) => match entry.signature.as_ref().and_then(|s| s.result.as_ref()) {
+                Some(result) => format!("{}: {}", result.name, format_entry_value(result, &res[1..]).unwrap_or_default()),
+                None => format!("{}: OK", entry.name),
+            },
+            Err(e) => format!("{} failed: {e}", entry.name),
+        });
+        Ok(())
+    }
+
+    fn start_login(&mut self, level: AccessLevel) -> Result<()> {
+        if let AppState::Done(dev_info) = &mut self.state {
+            dev_info.login = Some((level, String::new()));
+        }
+        Ok(())
+    }
+
+    /// Edits the secret at the login prompt, logging in on enter
+    fn handle_login_key(&mut self, key_event: KeyEvent) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        let Some((level, secret)) = &mut dev_info.login else {
+            return Ok(())
+        };
+        match key_event.code {
+            KeyCode::Char(c) => secret.push(c),
+            KeyCode::Backspace => { secret.pop(); },
+            KeyCode::Esc => dev_info.login = None,
+            KeyCode::Enter => {
+                let level = *level;
+                dev_info.notice = Some(match device.login(level, secret) {
+                    Ok(granted) => {
+                        dev_info.access = granted;
+                        format!("Logged in as {granted}")
+                    },
+                    Err(e) => format!("Login failed: {e}"),
+                });
+                dev_info.login = None;
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Resets the props of the selected section to their defaults and reads them back
+    fn reset_section(&mut self) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        let Some((section_index, _)) = dev_info.section_order.get(dev_info.section_index) else {
+            return Ok(())
+        };
+        let section = &dev_info.sections[*section_index];
+        if section.entry.flags.contains(ExtraFlags::IsRepeated) {
+            dev_info.notice = Some(format!("{} can't be reset", section.entry.name));
+            return Ok(())
+        }
+        let props: Vec<_> = section.layout.iter()
+            .filter_map(|(le, entry)| match le {
+                LayoutEntry::Prop(pi) => Some((*le, *pi, entry)),
+                _ => None,
+            })
+            .collect();
+        // blobs can't be staged, so they are reset once the other props were
+        let (blobs, props): (Vec<_>, Vec<_>) = props.into_iter()
+            .partition(|(_, _, entry)| entry.variant == ValueType::Blob as u8);
+        let res = device.transaction(|device| props.iter().try_for_each(|(_, pi, _)| device.reset_prop(*pi)))
+            .and_then(|_| blobs.iter().try_for_each(|(_, pi, _)| device.reset_prop(*pi)));
+        if let Err(e) = res {
+            dev_info.notice = Some(format!("Resetting {} failed: {e}", section.entry.name));
+            return Ok(())
+        }
+        for (le, pi, entry) in props.iter().chain(&blobs) {
+            match read_field(device, entry, EntryType::Prop, *pi) {
+                Ok(Some(display)) => { dev_info.values.insert(*le, display); },
+                Ok(None) => { dev_info.values.remove(le); },
+                Err(e) => {
+                    dev_info.notice = Some(format!("Reading {} failed: {e}", entry.name));
+                    return Ok(())
+                },
+            }
+        }
+        dev_info.notice = Some(format!("Reset {} field(s) of {}", props.len() + blobs.len(), section.entry.name));
+        Ok(())
+    }
+
+    /// Adds an instance to the selected repeated section and reads its values
+    fn add_instance(&mut self) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        let Some((section_index, _)) = dev_info.section_order.get(dev_info.section_index).copied() else {
+            return Ok(())
+        };
+        let section = &dev_info.sections[section_index];
+        if !section.entry.flags.contains(ExtraFlags::IsRepeated) {
+            return Ok(())
+        }
+        let res = device.add_instance(section_index as u8)
+            .and_then(|instance| read_instance(device, section, section_index, instance));
+        dev_info.notice = Some(match res {
+            Ok(instance) => {
+                let notice = format!("Added {} #{}", section.entry.name, instance.index + 1);
+                let instances = dev_info.instances.entry(section_index).or_default();
+                instances.push(instance);
+                instances.sort_by_key(|i| i.index);
+                notice
+            },
+            Err(e) => format!("Adding to {} failed: {e}", section.entry.name),
+        });
+        Ok(())
+    }
+
+    /// Removes the last instance of the selected repeated section
+    fn remove_instance(&mut self) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        let Some((section_index, _)) = dev_info.section_order.get(dev_info.section_index).copied() else {
+            return Ok(())
+        };
+        let name = &dev_info.sections[section_index].entry.name;
+        let Some(instances) = dev_info.instances.get_mut(&section_index) else {
+            return Ok(())
+        };
+        let Some(last) = instances.last().map(|i| i.index) else {
+            dev_info.notice = Some(format!("{name} has no instances"));
+            return Ok(())
+        };
+        dev_info.notice = Some(match device.remove_instance(section_index as u8, last) {
+            Ok(()) => {
+                instances.pop();
+                format!("Removed {name} #{}", last + 1)
+            },
+            Err(e) => format!("Removing from {name} failed: {e}"),
+        });
+        Ok(())
+    }
+
+    fn cancel_job(&mut self) -> Result<()> {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return Ok(())
+        };
+        if let Some((name, status)) = &dev_info.job
+            && !status.state.is_finished()
+            && let Err(e) = device.cancel_job(status.id) {
+            dev_info.notice = Some(format!("Cancelling {name} failed: {e}"));
+        }
+        Ok(())
+    }
+
+    fn poll_job(&mut self) {
+        let (AppState::Done(dev_info), Some(device)) = (&mut self.state, &mut self.device) else {
+            return
+        };
+        let Some((_, status)) = &mut dev_info.job else { return };
+        if status.state.is_finished() {
+            return
+        }
+        match device.get_job_status(status.id) {
+            Ok(new_status) => *status = new_status,
+            Err(e) => dev_info.notice = Some(format!("Job status failed: {e}")),
+        }
+    }
+
     fn exit(&mut self) -> Result<()> {
         self.exit = true;
         Ok(())
@@ -238,7 +681,7 @@ impl App {
     fn update_selection(&mut self, arg: i32) -> Result<()> {
         if let AppState::Done(dev_info) = &mut self.state {
             if arg > 0 {
-                dev_info.section_index = (dev_info.section_index + 1).min(dev_info.sections.len() - 1);
+                dev_info.section_index = (dev_info.section_index + 1).min(dev_info.section_order.len().saturating_sub(1));
             } else {
                 dev_info.section_index = dev_info.section_index.saturating_sub(1);
             }
@@ -271,9 +714,11 @@ impl Widget for &App {
 impl Widget for &DeviceInfo {
     fn render(self, area: Rect, buf: &mut Buffer) {
         
+        let has_status = self.job.is_some() || self.notice.is_some() || self.login.is_some();
         let vertical = Layout::vertical([
             Constraint::Length(6), 
-            Constraint::Fill(1)
+            Constraint::Fill(1),
+            Constraint::Length(if has_status { 3 } else { 0 }),
             // Constraint::Percentage(50), 
         ]).spacing(0)
         .vertical_margin(1)
@@ -281,17 +726,44 @@ impl Widget for &DeviceInfo {
         
         let rows = vertical.split(area);
 
+        if let Some((level, secret)) = &self.login {
+            Paragraph::new(format!("PIN: {}", "*".repeat(secret.chars().count())))
+                .block(Block::bordered().title(format!(" Login as {level} (enter to submit, esc to cancel) ")))
+                .render(rows[2], buf);
+        } else if let Some((name, status)) = &self.job {
+            let color = match status.state {
+                JobState::Running | JobState::Done => Color::Green,
+                JobState::Failed | JobState::Cancelled => Color::Red,
+            };
+            Gauge::default()
+                .block(Block::bordered().title(format!(" {name} ({:?}) ", status.state)))
+                .gauge_style(color)
+                .percent(status.progress.min(100) as u16)
+                .label(format!("{}% {}", status.progress, status.message))
+                .render(rows[2], buf);
+        } else if let Some(notice) = &self.notice {
+            Paragraph::new(notice.as_str())
+                .block(Block::bordered().title(" Action "))
+                .render(rows[2], buf);
+        }
+
         Paragraph::new(Text::from_iter([
             Line::from_iter([ 
                 Span::from("Version:"), 
                 Span::from(format!("{}", self.info.proto_version))
+            ]),
+            Line::from_iter([
+                Span::from("Access: "),
+                Span::from(self.access.to_string()),
             ])
         ]))
         .block(Block::bordered().title(" Info ").padding(Padding::uniform(1)))
         
         .render(rows[0], buf);
 
-        let max_section_name = self.sections.iter().map(|s| s.entry.name.len()).max().unwrap_or(20);
+        let max_section_name = self.section_order.iter()
+            .map(|(index, depth)| self.sections[*index].entry.name.len() + depth * 2)
+            .max().unwrap_or(20);
 
         let horz = Layout::horizontal([Constraint::Length(max_section_name as u16 + 4), Constraint::Fill(1)])
             .spacing(1);
@@ -303,8 +775,9 @@ impl Widget for &DeviceInfo {
         //         .block(Block::bordered().title(" Sections "))
         //         .select(0);
         let tabs = Paragraph::new(
-           Text::from_iter(self.sections.iter().enumerate().map(|(index, section)| {
-            Line::from(format!(" {:max_section_name$} ", section.entry.name)).style(if index == self.section_index {
+           Text::from_iter(self.section_order.iter().enumerate().map(|(position, (index, depth))| {
+            let name = format!("{}{}", "  ".repeat(*depth), self.sections[*index].entry.name);
+            Line::from(format!(" {:max_section_name$} ", name)).style(if position == self.section_index {
                 Style::new().bg(Color::White).fg(Color::Black)
             } else {
                 Style::new()
@@ -313,23 +786,23 @@ impl Widget for &DeviceInfo {
         .block(Block::bordered().title(" Sections ").padding(Padding::symmetric(0, 0)).title_alignment(Alignment::Center));
         tabs.render(horz[0], buf);
 
-        if let Some(section) = self.sections.get(self.section_index) {
-            let section_text = Text::from_iter(section.layout.iter().flat_map(|(_, e)|
-                [
-                    Line::from_iter([ 
-                        Span::from(format!("{}", e.name)), 
-                    ]),
-                    // Line::from("                 ").underlined(),
-                    Line::from_iter([ 
-                        Span::from(format!("{}", e.help.clone().unwrap_or_default())).fg(Color::DarkGray)
-                    ]),
-                    Line::from(""),
-                ]
-            ));
+        let selected = self.section_order.get(self.section_index).map(|(index, _)| *index);
+        if let Some((section_index, section)) = selected.and_then(|i| self.sections.get(i).map(|s| (i, s))) {
+            let section_text = match self.instances.get(&section_index) {
+                Some(instances) if instances.is_empty() => Text::from(Line::from("No instances").fg(Color::DarkGray)),
+                Some(instances) => Text::from_iter(instances.iter().flat_map(|instance| {
+                    let header = Line::from(format!("#{}", instance.index + 1)).bold();
+                    std::iter::once(header)
+                        .chain(layout_lines(section, &instance.values, &self.values, self.access, false))
+                        .chain(std::iter::once(Line::from("")))
+                })),
+                None => Text::from_iter(layout_lines(section, &self.values, &self.values, self.access, true)),
+            };
             let para = Paragraph::new(section_text)
                 .left_aligned()
                 .block(Block::bordered().padding(Padding::symmetric(2, 1))
-                    .title(Line::from(format!(" {} ", section.entry.name))))
+                    .title(Line::from(format!(" {} ", section.entry.name)))
+                    .title_bottom(Line::from(" 1-9 run action · c cancel job · r reset to defaults · a/d add/remove instance · l/L login · q quit ").fg(Color::DarkGray).centered()))
                 ;
             Widget::render(Clear, horz[1], buf);
             para.render(horz[1], buf);
@@ -384,7 +857,7 @@ impl Widget for &DeviceInfo {
 }
 
 struct LoadingWidget {
-    log: Vec<([u8; 64], [u8; 64])>,
+    log: Vec<LogItem>,
     statuses: Vec<String>,
     failure: Option<String>
 }
@@ -438,13 +911,30 @@ impl Widget for &LoadingWidget {
             Constraint::Length(32),
         ];
 
-        let table = Table::new(self.log.iter().rev().flat_map(|(bout, bin)| {
-            let bout = fmt_hex_bytes(bout);
-            let bin = fmt_hex_bytes(bin);
-            [
-                Row::new([ Text::from(">>"), bout.0, bout.1 ]).height(2),
-                Row::new([ Text::from("<<"), bin.0, bin.1 ]).height(2),
-            ]
+        let table = Table::new(self.log.iter().rev().flat_map(|item| match item {
+            LogItem::Exchange(bout, bin) => {
+                let decoded = describe_exchange(bout, bin);
+                let bout = fmt_hex_bytes(bout);
+                let bin = fmt_hex_bytes(bin);
+                vec![
+                    Row::new([ Text::from(">>"), bout.0, bout.1 ]).height(2),
+                    Row::new([ Text::from("<<"), bin.0, bin.1 ]).height(2),
+                    Row::new([ Text::from(""), Text::from(decoded).italic().cyan(), Text::from("") ]).bottom_margin(1),
+                ]
+            },
+            LogItem::Device(level, message) => {
+                let color = match level {
+                    log::Level::Error => Color::LightRed,
+                    log::Level::Warn => Color::LightYellow,
+                    log::Level::Info => Color::LightGreen,
+                    log::Level::Debug | log::Level::Trace => Color::DarkGray,
+                };
+                vec![Row::new([
+                    Text::from("#"),
+                    Text::from(Line::from_iter([Span::from(format!("{level:<5} ")).fg(color), Span::from(message.as_str())])),
+                    Text::from(""),
+                ]).bottom_margin(1)]
+            },
         }), widths)
         .block(Block::bordered().title(" Device Communication ").padding(Padding::proportional(1)))
         .header(Row::new(vec!["Dir", "Bytes (hex)", "ASCII"])
@@ -1,5 +1,6 @@
-use std::{cell::Cell, error::Error, fs::File, io::{Read, Write}, path::Path};
-use log::debug;
+use std::{cell::Cell, collections::BTreeMap, error::Error, fs::{self, File}, io::{Read, Write}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 
 use color_eyre::eyre::eyre;
 
@@ -9,15 +10,137 @@ use wasmtime::*;
 #[cfg(feature = "wasmi")]
 use wasmi::*;
 
-use crate::ElytraDevice;
+use crate::{ElytraDevice, format::format_hex, serial::{trimmed, unhex}};
 
+/// Device running a module built with `elytra_wasm!`.
+///
+/// Prop values and blobs written by the module through its store imports are kept in memory,
+/// and in a JSON file if one is given with [`with_store`](Self::with_store). Messages
+/// logged by the module are returned by [`get_device_log`](ElytraDevice::get_device_log).
+///
+/// Each command may only run for a limited amount of fuel, see [`with_fuel`](Self::with_fuel).
+/// If the module traps or runs out of fuel, it is instantiated again, keeping the stored values.
 pub struct WasmDevice {
-    instance: Instance,
+    module: Module,
+    linker: Linker<HostState>,
     store: Store<HostState>,
+    send_fn: TypedFunc<Message, u32>,
+    recieve_fn: TypedFunc<u32, u64>,
+    fuel: u64,
     log: Cell<Vec<([u8; 64], [u8; 64])>>
 }
 
-type HostState = u32;
+/// Fuel given to the module for handling a single command, roughly the number of instructions
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+/// Maximum size of the linear memory of the module
+pub const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+#[derive(Default, Clone)]
+struct HostState {
+    /// Stored prop values by slot, see `elytra_wasm::WasmStore`
+    values: BTreeMap<u32, [u8; 64]>,
+    /// Stored blobs by slot
+    blobs: BTreeMap<u32, Vec<u8>>,
+    store_path: Option<PathBuf>,
+    /// Messages logged by the module, see `elytra_wasm::logger`
+    messages: Vec<(log::Level, String)>,
+    limits: StoreLimits,
+}
+
+impl HostState {
+    fn read(&self, slot: u32, index: u32) -> u64 {
+        let start = index as usize * 8;
+        self.values.get(&slot)
+            .and_then(|value| value.get(start..start + 8))
+            .map_or(0, |bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn write(&mut self, slot: u32, value: [u8; 64]) -> Result<(), Box<dyn Error>> {
+        let mut values = self.values.clone();
+        if value == [0; 64] {
+            values.remove(&slot);
+        } else {
+            values.insert(slot, value);
+        }
+        // the module is told the write failed if it can't be saved, so it is only kept once saved
+        self.save(&values, &self.blobs)?;
+        self.values = values;
+        Ok(())
+    }
+
+    fn blob(&self, slot: u32) -> &[u8] {
+        self.blobs.get(&slot).map_or(&[], Vec::as_slice)
+    }
+
+    fn write_blob(&mut self, slot: u32, offset: u32, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut blob = self.blob(slot).to_vec();
+        let offset = offset as usize;
+        if offset > blob.len() {
+            return Err(eyre!("Blob write at {offset} is past the end of slot {slot}"))?
+        }
+        blob.truncate(offset);
+        blob.extend_from_slice(data);
+        let end = offset + data.len();
+        if let Some(rest) = self.blob(slot).get(end..) {
+            blob.extend_from_slice(rest);
+        }
+        self.replace_blob(slot, blob)
+    }
+
+    fn truncate_blob(&mut self, slot: u32, len: u32) -> Result<(), Box<dyn Error>> {
+        let blob = self.blob(slot);
+        let len = (len as usize).min(blob.len());
+        self.replace_blob(slot, blob[..len].to_vec())
+    }
+
+    fn replace_blob(&mut self, slot: u32, blob: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let mut blobs = self.blobs.clone();
+        if blob.is_empty() {
+            blobs.remove(&slot);
+        } else {
+            blobs.insert(slot, blob);
+        }
+        self.save(&self.values, &blobs)?;
+        self.blobs = blobs;
+        Ok(())
+    }
+
+    fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let file: StoreFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        for (slot, hex) in file.values {
+            let mut value = [0; 64];
+            unhex(&hex, &mut value)?;
+            self.values.insert(slot, value);
+        }
+        for (slot, hex) in file.blobs {
+            let mut blob = vec![0; hex.len() / 2];
+            unhex(&hex, &mut blob)?;
+            self.blobs.insert(slot, blob);
+        }
+        Ok(())
+    }
+
+    fn save(&self, values: &BTreeMap<u32, [u8; 64]>, blobs: &BTreeMap<u32, Vec<u8>>) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.store_path else {
+            return Ok(())
+        };
+        let file = StoreFile {
+            values: values.iter().map(|(slot, value)| (*slot, format_hex(trimmed(value), ""))).collect(),
+            blobs: blobs.iter().map(|(slot, blob)| (*slot, format_hex(blob, ""))).collect(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+/// Contents of the store file, the values and blobs as hex by slot
+#[derive(Default, Serialize, Deserialize)]
+struct StoreFile {
+    #[serde(default)]
+    values: BTreeMap<u32, String>,
+    #[serde(default)]
+    blobs: BTreeMap<u32, String>,
+}
 
 type Message = (u64, u64, u64, u64, u64, u64, u64, u64);
 
@@ -31,57 +154,197 @@ fn pack64(value: [u8; 64]) -> Message {
 
 impl WasmDevice {
     pub fn new(file_path: &Path) -> Result<Self, Box<dyn Error>> {
-
-        let engine = Engine::default();
-        // Now we can compile the above Wasm module with the given Wasm source.
-
         let mut file = File::open(file_path)?;
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+
+        #[cfg(feature = "wasmtime")]
+        let engine = Engine::new(&config)?;
+
+        #[cfg(feature = "wasmi")]
+        let engine = Engine::new(&config);
+
         let module = Module::new(&engine, bytes)?;
 
         for e in module.exports() {
             debug!("WASM Export {}: {:#?}", e.name(), e.ty());
         }
+        for name in ["send", "recieve"] {
+            if module.get_export(name).is_none() {
+                return Err(eyre!("The module has no \"{name}\" export, is it built with elytra_wasm!?"))?
+            }
+        }
 
-        let mut store = Store::new(&engine, 0);
-        let linker = <Linker<HostState>>::new(&engine);
-
-
-        #[cfg(feature = "wasmtime")]
-        let instance = linker.instantiate(&mut store, &module)?;
-
-        #[cfg(feature = "wasmi")]
-        let instance = linker.instantiate_and_start(&mut store, &module)?;
+        let mut linker = <Linker<HostState>>::new(&engine);
+        linker.func_wrap("elytra", "store_read", |caller: Caller<'_, HostState>, slot: u32, index: u32| -> u64 {
+            caller.data().read(slot, index)
+        })?;
+        linker.func_wrap("elytra", "store_write", |mut caller: Caller<'_, HostState>, slot: u32,
+            a: u64, b: u64, c: u64, d: u64, e: u64, f: u64, g: u64, h: u64| -> u32 {
+            let mut value = [0u8; 64];
+            for (chunk, packed) in value.chunks_mut(8).zip([a, b, c, d, e, f, g, h]) {
+                chunk.copy_from_slice(&packed.to_be_bytes());
+            }
+            match caller.data_mut().write(slot, value) {
+                Ok(()) => 1,
+                Err(e) => {
+                    warn!("Failed to save the value of slot {slot}: {e}");
+                    0
+                }
+            }
+        })?;
+        linker.func_wrap("elytra", "blob_len", |caller: Caller<'_, HostState>, slot: u32| -> u32 {
+            caller.data().blob(slot).len() as u32
+        })?;
+        linker.func_wrap("elytra", "blob_read", |mut caller: Caller<'_, HostState>, slot: u32, offset: u32, ptr: u32, len: u32| -> u32 {
+            let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                return 0
+            };
+            let (memory, state) = memory.data_and_store_mut(&mut caller);
+            let data = state.blob(slot).get(offset as usize..).unwrap_or_default();
+            let len = data.len().min(len as usize);
+            match memory.get_mut(ptr as usize..ptr as usize + len) {
+                Some(buf) => {
+                    buf.copy_from_slice(&data[..len]);
+                    len as u32
+                },
+                None => 0,
+            }
+        })?;
+        linker.func_wrap("elytra", "blob_write", |mut caller: Caller<'_, HostState>, slot: u32, offset: u32, ptr: u32, len: u32| -> u32 {
+            let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                return 0
+            };
+            let (memory, state) = memory.data_and_store_mut(&mut caller);
+            let Some(data) = memory.get(ptr as usize..ptr as usize + len as usize) else {
+                return 0
+            };
+            match state.write_blob(slot, offset, data) {
+                Ok(()) => 1,
+                Err(e) => {
+                    warn!("Failed to save the blob of slot {slot}: {e}");
+                    0
+                }
+            }
+        })?;
+        linker.func_wrap("elytra", "blob_truncate", |mut caller: Caller<'_, HostState>, slot: u32, len: u32| -> u32 {
+            match caller.data_mut().truncate_blob(slot, len) {
+                Ok(()) => 1,
+                Err(e) => {
+                    warn!("Failed to save the blob of slot {slot}: {e}");
+                    0
+                }
+            }
+        })?;
+        linker.func_wrap("elytra", "log_message", |mut caller: Caller<'_, HostState>, level: u32, ptr: u32, len: u32| {
+            let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                return
+            };
+            let (start, end) = (ptr as usize, ptr as usize + len as usize);
+            let message = memory.data(&caller).get(start..end)
+                .map_or_else(|| "<invalid log message>".to_owned(), |bytes| String::from_utf8_lossy(bytes).into_owned());
+            let level = log::Level::iter().nth(level.saturating_sub(1) as usize).unwrap_or(log::Level::Trace);
+            caller.data_mut().messages.push((level, message));
+        })?;
+        linker.func_wrap("elytra", "now_millis", || -> u64 {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+        })?;
 
+        let state = HostState {
+            limits: StoreLimitsBuilder::new().memory_size(DEFAULT_MEMORY_LIMIT).build(),
+            ..Default::default()
+        };
+        let (store, send_fn, recieve_fn) = instantiate(&linker, &module, state, DEFAULT_FUEL)?;
         Ok(Self {
-            instance, store, log: Cell::new(vec![])
+            module, linker, store, send_fn, recieve_fn, fuel: DEFAULT_FUEL, log: Cell::new(vec![])
         })
     }
-}
 
-impl ElytraDevice for WasmDevice {
-    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn std::error::Error>> {
-        let msg_in = pack64(bytes);
-        let send_fn = self.instance.get_typed_func::<Message, u32>(&mut self.store, "send")
-            .unwrap();
-        let recieve_fn = self.instance.get_typed_func::<u32, u64>(&mut self.store, "recieve")
-            .unwrap();
-        
+    /// Sets the fuel the module gets for handling each command
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
 
-        let res_pack_count: u32 = send_fn.call(&mut self.store, msg_in)?;
+    /// Sets the maximum size in bytes the memory of the module may grow to
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.store.data_mut().limits = StoreLimitsBuilder::new().memory_size(bytes).build();
+        self
+    }
 
-        if res_pack_count == 0 {
-            return Err(eyre!("Error response from WASM device"))?
+    /// Keeps the stored prop values and blobs in a JSON file, loading the ones already in it
+    pub fn with_store(mut self, path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let state = self.store.data_mut();
+        if path.exists() {
+            state.load(&path).map_err(|e| eyre!("{}: {e}", path.display()))?;
         }
+        state.store_path = Some(path);
+        Ok(self)
+    }
+
+    fn call(&mut self, msg_in: Message) -> Result<(u32, [u8; 64]), Box<dyn Error>> {
+        let res_pack_count: u32 = self.send_fn.call(&mut self.store, msg_in)?;
 
         let mut out_data = [0u8; 64];
         let mut cursor = out_data.as_mut_slice();
-        for i in 0..res_pack_count {
-            let packed = recieve_fn.call(&mut self.store, i)?;
+        for i in 0..res_pack_count.min(8) {
+            let packed = self.recieve_fn.call(&mut self.store, i)?;
             let _ = cursor.write(&packed.to_be_bytes())?;
         }
-        
+        Ok((res_pack_count, out_data))
+    }
+
+    /// Instantiates the module again in a new store, as its state can't be trusted after a trap.
+    /// The host state is copied, so that the stored values are kept if that fails
+    fn restart(&mut self) -> Result<(), Box<dyn Error>> {
+        let state = self.store.data().clone();
+        (self.store, self.send_fn, self.recieve_fn) = instantiate(&self.linker, &self.module, state, self.fuel)?;
+        Ok(())
+    }
+}
+
+/// A new store with the module instantiated in it, and the exports used to send commands
+type Instantiated = (Store<HostState>, TypedFunc<Message, u32>, TypedFunc<u32, u64>);
+
+fn instantiate(linker: &Linker<HostState>, module: &Module, state: HostState, fuel: u64) -> Result<Instantiated, Box<dyn Error>> {
+    let mut store = Store::new(module.engine(), state);
+    store.limiter(|state| &mut state.limits);
+    // the start function, if any, runs on the same fuel as commands
+    store.set_fuel(fuel)?;
+
+    #[cfg(feature = "wasmtime")]
+    let instance = linker.instantiate(&mut store, module)?;
+
+    #[cfg(feature = "wasmi")]
+    let instance = linker.instantiate_and_start(&mut store, module)?;
+
+    let send_fn = instance.get_typed_func::<Message, u32>(&mut store, "send")
+        .map_err(|e| eyre!("The \"send\" export has the wrong signature: {e}"))?;
+    let recieve_fn = instance.get_typed_func::<u32, u64>(&mut store, "recieve")
+        .map_err(|e| eyre!("The \"recieve\" export has the wrong signature: {e}"))?;
+    Ok((store, send_fn, recieve_fn))
+}
+
+impl ElytraDevice for WasmDevice {
+    fn send_command_raw(&mut self, bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn std::error::Error>> {
+        self.store.set_fuel(self.fuel)?;
+        let (res_pack_count, out_data) = match self.call(pack64(bytes)) {
+            Ok(response) => response,
+            Err(e) => {
+                self.restart()?;
+                return Err(eyre!("WASM device trapped and was restarted: {e}"))?
+            }
+        };
+
+        if res_pack_count == 0 {
+            return Err(eyre!("Error response from WASM device"))?
+        }
         Ok(out_data)
     }
 
@@ -92,4 +355,175 @@ impl ElytraDevice for WasmDevice {
     fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> {
         self.log.replace(vec![])
     }
-}
\ No newline at end of file
+
+    fn get_device_log(&mut self) -> Vec<(log::Level, String)> {
+        std::mem::take(&mut self.store.data_mut().messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn persisted_store() {
+        let path = std::env::temp_dir().join(format!("elytra-wasm-store-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut value = [0u8; 64];
+        value[..4].copy_from_slice(b"ntp!");
+        value[63] = 0xff;
+
+        let mut state = HostState { store_path: Some(path.clone()), ..Default::default() };
+        state.write(3, value).unwrap();
+        state.write(4, [0; 64]).unwrap();
+        state.write_blob(6, 0, b"certificate").unwrap();
+        state.write_blob(6, 4, b"!").unwrap();
+        state.truncate_blob(6, 8).unwrap();
+        assert!(state.write_blob(6, 9, b"gap").is_err());
+
+        let mut state = HostState::default();
+        state.load(&path).unwrap();
+        assert_eq!(vec![3], state.values.keys().copied().collect::<Vec<_>>());
+        assert_eq!(u64::from_be_bytes(*b"ntp!\0\0\0\0"), state.read(3, 0));
+        assert_eq!(0xff, state.read(3, 7));
+        assert_eq!(0, state.read(3, 8));
+        assert_eq!(0, state.read(5, 0));
+        assert_eq!(b"cert!fic", state.blob(6));
+        assert!(state.blob(7).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_save_keeps_memory() {
+        let path = std::env::temp_dir().join(format!("elytra-wasm-missing-{}", std::process::id())).join("store.json");
+        let mut state = HostState { store_path: Some(path), ..Default::default() };
+        assert!(state.write(1, [1; 64]).is_err());
+        assert!(state.write_blob(2, 0, b"blob").is_err());
+        assert_eq!(0, state.read(1, 0));
+        assert!(state.blob(2).is_empty());
+    }
+
+    /// Echoes the request through the store, logs a message and puts the time in the last 8 bytes
+    const ECHO_WAT: &str = r#"(module
+        (import "elytra" "store_read" (func $store_read (param i32 i32) (result i64)))
+        (import "elytra" "store_write" (func $store_write (param i32 i64 i64 i64 i64 i64 i64 i64 i64) (result i32)))
+        (import "elytra" "log_message" (func $log_message (param i32 i32 i32)))
+        (import "elytra" "now_millis" (func $now_millis (result i64)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "handled")
+        (func (export "send") (param i64 i64 i64 i64 i64 i64 i64 i64) (result i32)
+            (call $log_message (i32.const 4) (i32.const 0) (i32.const 7))
+            (drop (call $store_write (i32.const 1) (local.get 0) (local.get 1) (local.get 2) (local.get 3)
+                (local.get 4) (local.get 5) (local.get 6) (local.get 7)))
+            (i32.const 8))
+        (func (export "recieve") (param i32) (result i64)
+            (if (result i64) (i32.eq (local.get 0) (i32.const 7))
+                (then (call $now_millis))
+                (else (call $store_read (i32.const 1) (local.get 0)))))
+    )"#;
+
+    #[test]
+    fn host_imports() {
+        let mut device = WasmDevice::from_bytes(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut request = [0u8; 64];
+        request[..4].copy_from_slice(b"w\x01hi");
+        let response = device.send_command_raw(request).unwrap();
+        assert_eq!(request[..56], response[..56]);
+        let millis = u64::from_be_bytes(response[56..].try_into().unwrap());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        assert!(now - millis < 10_000);
+        assert_eq!(vec![(log::Level::Debug, "handled".to_owned())], device.get_device_log());
+        assert!(device.get_device_log().is_empty());
+    }
+
+    /// Writes "blob" to blob slot 2 and responds with what is read back from offset 1
+    const BLOB_WAT: &str = r#"(module
+        (import "elytra" "blob_write" (func $blob_write (param i32 i32 i32 i32) (result i32)))
+        (import "elytra" "blob_read" (func $blob_read (param i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "blob")
+        (func (export "send") (param i64 i64 i64 i64 i64 i64 i64 i64) (result i32)
+            (drop (call $blob_write (i32.const 2) (i32.const 0) (i32.const 0) (i32.const 4)))
+            (drop (call $blob_read (i32.const 2) (i32.const 1) (i32.const 16) (i32.const 8)))
+            (i32.const 1))
+        (func (export "recieve") (param i32) (result i64) (i64.load (i32.const 16)))
+    )"#;
+
+    #[test]
+    fn blob_imports() {
+        let mut device = WasmDevice::from_bytes(&wat::parse_str(BLOB_WAT).unwrap()).unwrap();
+        let response = device.send_command_raw([0; 64]).unwrap();
+        assert_eq!(b"lob\0", &u64::from_be_bytes(response[..8].try_into().unwrap()).to_le_bytes()[..4]);
+        assert_eq!(b"blob", device.store.data().blob(2));
+    }
+
+    /// Counts the commands since it was instantiated, unless the first byte asks it to
+    /// spin forever (ff), trap (fe) or grow its memory by 64 MiB (fd)
+    const FAULTY_WAT: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $count (mut i64) (i64.const 0))
+        (global $out (mut i64) (i64.const 0))
+        (func (export "send") (param i64 i64 i64 i64 i64 i64 i64 i64) (result i32)
+            (local $first i64)
+            (local.set $first (i64.shr_u (local.get 0) (i64.const 56)))
+            (global.set $count (i64.add (global.get $count) (i64.const 1)))
+            (global.set $out (global.get $count))
+            (if (i64.eq (local.get $first) (i64.const 0xff)) (then (loop $spin (br $spin))))
+            (if (i64.eq (local.get $first) (i64.const 0xfe)) (then unreachable))
+            (if (i64.eq (local.get $first) (i64.const 0xfd))
+                (then (global.set $out (i64.extend_i32_s (memory.grow (i32.const 1024))))))
+            (i32.const 1))
+        (func (export "recieve") (param i32) (result i64) (global.get $out))
+    )"#;
+
+    fn send_first(device: &mut WasmDevice, first: u8) -> Result<i64, Box<dyn Error>> {
+        let mut request = [0u8; 64];
+        request[0] = first;
+        let response = device.send_command_raw(request)?;
+        Ok(i64::from_be_bytes(response[..8].try_into().unwrap()))
+    }
+
+    #[test]
+    fn faulty_modules() {
+        let missing = WasmDevice::from_bytes(&wat::parse_str(r#"(module (func (export "send")))"#).unwrap());
+        assert!(missing.err().unwrap().to_string().contains("\"recieve\" export"));
+        let wrong = WasmDevice::from_bytes(&wat::parse_str(
+            r#"(module (func (export "send")) (func (export "recieve")))"#
+        ).unwrap());
+        assert!(wrong.err().unwrap().to_string().contains("wrong signature"));
+
+        let mut device = WasmDevice::from_bytes(&wat::parse_str(FAULTY_WAT).unwrap()).unwrap().with_fuel(100_000);
+        assert_eq!(1, send_first(&mut device, 0).unwrap());
+        assert_eq!(2, send_first(&mut device, 0).unwrap());
+        assert!(send_first(&mut device, 0xff).unwrap_err().to_string().contains("restarted"));
+        assert_eq!(1, send_first(&mut device, 0).unwrap());
+        assert!(send_first(&mut device, 0xfe).is_err());
+        assert_eq!(1, send_first(&mut device, 0).unwrap());
+        assert_eq!(-1, send_first(&mut device, 0xfd).unwrap());
+
+        let mut device = device.with_fuel(DEFAULT_FUEL).with_memory_limit(128 * 1024 * 1024);
+        assert_eq!(1, send_first(&mut device, 0xfd).unwrap());
+    }
+
+    /// Stores each request and traps, its start function takes more fuel than a command gets
+    const SLOW_START_WAT: &str = r#"(module
+        (import "elytra" "store_write" (func $store_write (param i32 i64 i64 i64 i64 i64 i64 i64 i64) (result i32)))
+        (func $start (local $i i32)
+            (loop $busy
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br_if $busy (i32.lt_u (local.get $i) (i32.const 10000)))))
+        (start $start)
+        (func (export "send") (param i64 i64 i64 i64 i64 i64 i64 i64) (result i32)
+            (drop (call $store_write (i32.const 1) (local.get 0) (local.get 1) (local.get 2) (local.get 3)
+                (local.get 4) (local.get 5) (local.get 6) (local.get 7)))
+            unreachable)
+        (func (export "recieve") (param i32) (result i64) (i64.const 0))
+    )"#;
+
+    #[test]
+    fn failed_restart_keeps_values() {
+        let mut device = WasmDevice::from_bytes(&wat::parse_str(SLOW_START_WAT).unwrap()).unwrap().with_fuel(1000);
+        assert!(send_first(&mut device, b'w').is_err());
+        assert_eq!(u64::from(b'w') << 56, device.store.data().read(1, 0));
+    }
+}
@@ -0,0 +1,208 @@
+use std::{error::Error, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}, sync::Arc};
+
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message, WebSocket,
+};
+
+use crate::ElytraDevice;
+
+/// Address the WebSocket endpoint listens on by default
+pub const DEFAULT_WS_ADDR: &str = "localhost:48001";
+
+/// Opens the device session of a new socket
+pub type DeviceOpener = dyn Fn() -> Result<Box<dyn ElytraDevice>, Box<dyn Error>> + Send + Sync;
+
+/// Exposes devices to browsers, every 64 byte binary message is forwarded as a command and
+/// answered with the response.
+///
+/// Each socket gets its own device session, opened when it connects and closed with it. As any
+/// web page could connect to a local helper, only pages from the allowed origins are accepted,
+/// and clients that don't send an origin are refused. Sockets aren't encrypted, so a device that
+/// requires a pre-shared key over TCP should only be served on a loopback address.
+pub struct WsServer {
+    listener: TcpListener,
+    origins: Vec<String>,
+}
+
+impl WsServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr)?, origins: vec![] })
+    }
+
+    /// Allows pages from `origins` (e.g. `https://example.com`), instead of only pages served from localhost
+    pub fn with_origins(self, origins: Vec<String>) -> Self {
+        Self { origins, ..self }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves sockets concurrently, each on its own thread with a device session from `open`
+    pub fn serve<F>(&self, open: F) -> std::io::Result<()>
+    where F: Fn() -> Result<Box<dyn ElytraDevice>, Box<dyn Error>> + Send + Sync + 'static {
+        let open: Arc<DeviceOpener> = Arc::new(open);
+        let origins: Arc<[String]> = self.origins.clone().into();
+        loop {
+            let (stream, addr) = self.listener.accept()?;
+            let (open, origins) = (open.clone(), origins.clone());
+            std::thread::spawn(move || {
+                eprintln!("Got WebSocket connection from {addr:?}");
+                match serve_socket(stream, &origins, &*open) {
+                    Ok(()) => eprintln!("WebSocket from {addr:?} closed"),
+                    Err(e) => eprintln!("WebSocket from {addr:?} dropped: {e}"),
+                }
+            });
+        }
+    }
+}
+
+/// Whether a page from `origin` may connect
+fn origin_allowed(origins: &[String], origin: Option<&str>) -> bool {
+    let Some(origin) = origin else {
+        return false
+    };
+    let origin = origin.trim_end_matches('/');
+    if !origins.is_empty() {
+        return origins.iter().any(|o| o.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+    let host = origin.split_once("://").map_or(origin, |(_, rest)| rest);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+fn serve_socket(stream: TcpStream, origins: &[String], open: &DeviceOpener) -> Result<(), Box<dyn Error>> {
+    let mut socket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        let origin = request.headers().get("origin").and_then(|o| o.to_str().ok());
+        if origin_allowed(origins, origin) {
+            Ok(response)
+        } else {
+            let mut error = ErrorResponse::new(Some(format!("Origin {} is not allowed", origin.unwrap_or_default())));
+            *error.status_mut() = StatusCode::FORBIDDEN;
+            Err(error)
+        }
+    }).map_err(|e| e.to_string())?;
+
+    let mut device = match open() {
+        Ok(device) => device,
+        Err(e) => {
+            close(&mut socket, CloseCode::Error, &format!("Failed to open device: {e}"));
+            return Err(e)
+        },
+    };
+
+    loop {
+        let message = match socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        match message {
+            Message::Binary(bytes) => {
+                let Ok(frame) = <[u8; 64]>::try_from(bytes.as_ref()) else {
+                    close(&mut socket, CloseCode::Invalid, "Messages must be 64 bytes");
+                    return Err(format!("invalid message length {}", bytes.len()).into())
+                };
+                match device.send_command_raw(frame) {
+                    Ok(response) => socket.send(Message::binary(response.to_vec()))?,
+                    Err(e) => {
+                        close(&mut socket, CloseCode::Error, &format!("Device error: {e}"));
+                        return Err(e)
+                    },
+                }
+            },
+            Message::Text(_) => {
+                close(&mut socket, CloseCode::Unsupported, "Only binary messages are supported");
+                return Err("unexpected text message".into())
+            },
+            Message::Close(_) => {},
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {},
+        }
+    }
+}
+
+/// Closes the socket with a reason for the browser, errors are ignored as the socket is going away
+fn close(socket: &mut WebSocket<TcpStream>, code: CloseCode, reason: &str) {
+    let _ = socket.close(Some(CloseFrame { code, reason: reason.into() }));
+    while socket.read().is_ok() {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use tungstenite::client::IntoClientRequest;
+
+    struct Echo;
+
+    impl ElytraDevice for Echo {
+        fn send_command_raw(&mut self, mut bytes: [u8; 64]) -> Result<[u8; 64], Box<dyn Error>> {
+            bytes.reverse();
+            Ok(bytes)
+        }
+        fn log_chat(&mut self, _: [u8; 64], _: [u8; 64]) {}
+        fn get_log(&mut self) -> Vec<([u8; 64], [u8; 64])> { vec![] }
+    }
+
+    fn connect(addr: SocketAddr, origin: &str) -> Result<WebSocket<TcpStream>, Box<dyn Error>> {
+        let mut request = format!("ws://{addr}").into_client_request()?;
+        request.headers_mut().insert("origin", origin.parse()?);
+        let (socket, _) = tungstenite::client(request, TcpStream::connect(addr)?).map_err(|e| e.to_string())?;
+        Ok(socket)
+    }
+
+    #[test]
+    fn forwards_frames_with_a_session_per_socket() {
+        static OPENED: AtomicUsize = AtomicUsize::new(0);
+        let server = WsServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(|| {
+            OPENED.fetch_add(1, Ordering::Relaxed);
+            Ok(Box::new(Echo))
+        }));
+
+        let mut first = connect(addr, "http://localhost:5173").unwrap();
+        let mut second = connect(addr, "http://127.0.0.1:5173").unwrap();
+        for (i, socket) in [&mut first, &mut second].into_iter().enumerate() {
+            let mut command = [0u8; 64];
+            command[0] = i as u8 + 1;
+            socket.send(Message::binary(command.to_vec())).unwrap();
+            assert_eq!(i as u8 + 1, socket.read().unwrap().into_data()[63]);
+        }
+        assert_eq!(2, OPENED.load(Ordering::Relaxed));
+
+        // messages that aren't frames close the socket
+        first.send(Message::binary(vec![0u8; 3])).unwrap();
+        assert!(matches!(first.read(), Ok(Message::Close(Some(frame))) if frame.code == CloseCode::Invalid));
+    }
+
+    #[test]
+    fn rejects_foreign_origins() {
+        let server = WsServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(|| Ok(Box::new(Echo))));
+        assert!(connect(addr, "https://evil.example").is_err());
+        assert!(tungstenite::client(format!("ws://{addr}"), TcpStream::connect(addr).unwrap()).is_err());
+
+        let server = WsServer::bind("127.0.0.1:0").unwrap().with_origins(vec!["https://elytra.example".into()]);
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(|| Ok(Box::new(Echo))));
+        assert!(connect(addr, "https://elytra.example").is_ok());
+        assert!(connect(addr, "http://localhost:5173").is_err());
+    }
+
+    #[test]
+    fn origin_hosts() {
+        assert!(!origin_allowed(&[], None));
+        assert!(origin_allowed(&[], Some("http://[::1]:8080")));
+        assert!(!origin_allowed(&[], Some("http://localhost.evil.example")));
+        assert!(!origin_allowed(&[], Some("null")));
+    }
+}
@@ -0,0 +1,235 @@
+use core::prelude::rust_2024::*;
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    command::{Chunk, Command, CommandError, CommandResponse},
+    traits::{ActionIndex, InfoIndex, PropIndex, SectionIndex},
+};
+
+/// Number of failed logins after which [`Auth`] refuses logins until the session timeout has passed
+pub const MAX_LOGIN_ATTEMPTS: u8 = 5;
+
+/// Access level needed to write an entry or run an action, see [`FieldEntry::with_access`](crate::entry::FieldEntry::with_access)
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default, TryFromPrimitive, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessLevel {
+    #[default]
+    Public = 0,
+    User = 1,
+    Admin = 2,
+}
+
+/// Checks the PIN or passphrase that a host logs in with
+pub trait Verifier {
+    fn verify(&mut self, level: AccessLevel, secret: &[u8]) -> bool;
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuthCommand {
+    /// Log in at an access level, responds with the granted level
+    Login { level: AccessLevel, secret: Chunk },
+    Logout,
+}
+
+/// Tracks the access level of the connected host.
+///
+/// Time is passed in by the caller as `now`, in whatever unit `timeout` is given in, so that
+/// devices can use any clock they have available. A session expires when no command has been
+/// authorized for `timeout`, after which the host needs to log in again.
+pub struct Auth<V: Verifier> {
+    verifier: V,
+    timeout: u64,
+    level: AccessLevel,
+    last_seen: u64,
+    failures: u8,
+}
+
+impl <V: Verifier> Auth<V> {
+    pub const fn new(verifier: V, timeout: u64) -> Self {
+        Self { verifier, timeout, level: AccessLevel::Public, last_seen: 0, failures: 0 }
+    }
+
+    pub const fn level(&self) -> AccessLevel {
+        self.level
+    }
+
+    pub fn handle(&mut self, command: AuthCommand, now: u64) -> CommandResponse {
+        match command {
+            AuthCommand::Login { level, secret } => self.login(level, secret.as_slice(), now)
+                .map(|_| CommandResponse::from_payload([self.level as u8]))
+                .into(),
+            AuthCommand::Logout => {
+                self.logout();
+                CommandResponse::ok()
+            },
+        }
+    }
+
+    /// Logs in at `level`, logging out if it is [`AccessLevel::Public`]
+    pub fn login(&mut self, level: AccessLevel, secret: &[u8], now: u64) -> Result<(), CommandError> {
+        if level == AccessLevel::Public {
+            self.logout();
+            return Ok(())
+        }
+        if self.failures >= MAX_LOGIN_ATTEMPTS {
+            if now.saturating_sub(self.last_seen) < self.timeout {
+                return Err(CommandError::InvalidCredentials)
+            }
+            self.failures = 0;
+        }
+        self.last_seen = now;
+        if !self.verifier.verify(level, secret) {
+            self.failures += 1;
+            return Err(CommandError::InvalidCredentials)
+        }
+        self.failures = 0;
+        self.level = level;
+        Ok(())
+    }
+
+    pub fn logout(&mut self) {
+        self.level = AccessLevel::Public;
+    }
+
+    /// Checks that the session grants `required`, refreshing the session timeout if it does
+    pub fn check(&mut self, required: AccessLevel, now: u64) -> Result<(), CommandError> {
+        if required == AccessLevel::Public {
+            return Ok(())
+        }
+        if self.level != AccessLevel::Public && now.saturating_sub(self.last_seen) >= self.timeout {
+            self.logout();
+            return Err(CommandError::SessionExpired)
+        }
+        if self.level < required {
+            return Err(CommandError::Locked)
+        }
+        self.last_seen = now;
+        Ok(())
+    }
+
+    /// Checks that the session grants the [`required_access`](Command::required_access) of a command
+    pub fn authorize<A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex>(
+        &mut self,
+        command: &Command<A, P, I, S>,
+        now: u64
+    ) -> Result<(), CommandError> {
+        self.check(command.required_access(), now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{blob::BlobCommand, entry::ExtraFlags};
+
+    struct Pins;
+
+    impl Verifier for Pins {
+        fn verify(&mut self, level: AccessLevel, secret: &[u8]) -> bool {
+            match level {
+                AccessLevel::Public => true,
+                AccessLevel::User => secret == b"1234",
+                AccessLevel::Admin => secret == b"hunter2",
+            }
+        }
+    }
+
+    crate::props!(P {
+        Name: crate::entry::prop("Name"),
+        Password: crate::entry::secret("Password").with_access(AccessLevel::User),
+        Certificate: crate::entry::blob("Certificate", 1024).with_access(AccessLevel::User)
+    });
+    crate::actions!(A {
+        Reboot: crate::entry::action("Reboot").with_access(AccessLevel::Admin)
+    });
+    crate::infos!(I);
+    crate::sections!(S {
+        Alarms: crate::entry::section("Alarms").repeated(4).with_access(AccessLevel::Admin)
+    });
+
+    fn command(bytes: &[u8]) -> Command<A, P, I, S> {
+        Command::from_bytes(bytes).ok().unwrap()
+    }
+
+    #[test]
+    fn access_levels_and_timeout() {
+        let mut auth = Auth::new(Pins, 100);
+        assert_eq!(Ok(()), auth.authorize(&command(b"w\x00\x01a"), 0));
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"w\x01\x01a"), 0));
+
+        assert_eq!(Err(CommandError::InvalidCredentials), auth.login(AccessLevel::User, b"0000", 0));
+        auth.login(AccessLevel::User, b"1234", 10).unwrap();
+        assert_eq!(Ok(()), auth.authorize(&command(b"w\x01\x01a"), 50));
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"a\x00"), 60));
+
+        // the session is kept alive by authorized commands
+        assert_eq!(Ok(()), auth.authorize(&command(b"z\x01"), 140));
+        assert_eq!(Err(CommandError::SessionExpired), auth.authorize(&command(b"z\x01"), 240));
+        assert_eq!(AccessLevel::Public, auth.level());
+
+        auth.login(AccessLevel::Admin, b"hunter2", 300).unwrap();
+        assert_eq!(Ok(()), auth.authorize(&command(b"a\x00"), 310));
+        auth.logout();
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"a\x00"), 320));
+    }
+
+    #[test]
+    fn instance_access() {
+        let mut auth = Auth::new(Pins, 100);
+        assert_eq!(AccessLevel::Admin, command(b"+\x00").required_access());
+        assert_eq!(AccessLevel::Admin, command(b"-\x00\x01").required_access());
+        assert_eq!(AccessLevel::Public, command(b"L\x00").required_access());
+        auth.login(AccessLevel::User, b"1234", 0).unwrap();
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"+\x00"), 10));
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"-\x00\x01"), 10));
+        auth.login(AccessLevel::Admin, b"hunter2", 20).unwrap();
+        assert_eq!(Ok(()), auth.authorize(&command(b"+\x00"), 30));
+        assert_eq!(Ok(()), auth.authorize(&command(b"-\x00\x01"), 30));
+    }
+
+    #[test]
+    fn blob_access() {
+        let mut auth = Auth::new(Pins, 100);
+        let read = command(b"b\x02\x00\x00\x00\x00");
+        assert!(matches!(read, Command::Blob(BlobCommand::Read { .. })));
+        assert_eq!(AccessLevel::User, read.required_access());
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&read, 0));
+        auth.login(AccessLevel::User, b"1234", 0).unwrap();
+        assert_eq!(Ok(()), auth.authorize(&read, 10));
+    }
+
+    #[test]
+    fn transaction_access() {
+        let mut auth = Auth::new(Pins, 100);
+        // a public session can stage public props, the staged writes are checked on their own
+        assert_eq!(AccessLevel::Public, command(b"t").required_access());
+        assert_eq!(AccessLevel::Public, command(b"T").required_access());
+        assert_eq!(AccessLevel::Public, command(b"X").required_access());
+        assert_eq!(Ok(()), auth.authorize(&command(b"t"), 0));
+        assert_eq!(Ok(()), auth.authorize(&command(b"w\x00\x01a"), 0));
+        assert_eq!(Err(CommandError::Locked), auth.authorize(&command(b"w\x01\x01a"), 0));
+        assert_eq!(Ok(()), auth.authorize(&command(b"T"), 0));
+    }
+
+    #[test]
+    fn access_flags() {
+        let response = crate::command::CommandResponse::from(P::Password.get_entry());
+        assert_eq!(ExtraFlags::RequiresUser.bits().to_le_bytes(), response.as_bytes()[1..3]);
+        assert!(A::Reboot.get_entry().flags().contains(ExtraFlags::RequiresAdmin));
+        assert!(!P::Name.get_entry().flags().intersects(ExtraFlags::RequiresUser | ExtraFlags::RequiresAdmin));
+    }
+
+    #[test]
+    fn repeated_failures_lock_out_login() {
+        let mut auth = Auth::new(Pins, 100);
+        for _ in 0..MAX_LOGIN_ATTEMPTS {
+            assert_eq!(Err(CommandError::InvalidCredentials), auth.login(AccessLevel::User, b"0000", 0));
+        }
+        assert_eq!(Err(CommandError::InvalidCredentials), auth.login(AccessLevel::User, b"1234", 50));
+        auth.login(AccessLevel::User, b"1234", 150).unwrap();
+        assert_eq!(AccessLevel::User, auth.level());
+    }
+}
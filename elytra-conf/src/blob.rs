@@ -0,0 +1,239 @@
+use core::prelude::rust_2024::*;
+
+use crate::{
+    command::{CHUNK_SIZE, Chunk, CommandError, CommandResponse},
+    entry::{Constraints, EntryVariant},
+    store::PropStore,
+    traits::PropIndex,
+    values::ValueType,
+};
+
+/// Length of the SHA-256 digest of a blob
+pub const BLOB_DIGEST_LEN: usize = 32;
+
+/// Chunked access to [`blob`](crate::entry::blob) props.
+///
+/// Reads respond with the total length of the blob, followed by a length byte and up to
+/// [`CHUNK_SIZE`] bytes of data. Writes respond with the new length of the blob and need to be
+/// sent in order: writing at offset 0 replaces the blob, other writes append to it.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BlobCommand<P: PropIndex> {
+    Read { prop: P, offset: u32 },
+    Write { prop: P, offset: u32, chunk: Chunk },
+    /// The SHA-256 digest of the blob, if enabled using [`with_digest`](crate::entry::FieldEntry::with_digest)
+    Digest(P),
+}
+
+impl <P: PropIndex> BlobCommand<P> {
+    pub fn prop(&self) -> P {
+        match self {
+            Self::Read { prop, .. } | Self::Write { prop, .. } | Self::Digest(prop) => *prop,
+        }
+    }
+
+    /// Handles the command, using the prop index as the blob slot of `store`
+    pub fn handle(self, store: &mut impl PropStore) -> CommandResponse {
+        let slot = self.prop().as_index();
+        self.handle_slot(store, slot)
+    }
+
+    pub fn handle_slot(self, store: &mut impl PropStore, slot: usize) -> CommandResponse {
+        let desc = self.prop().get_entry();
+        let Constraints::Blob { max_size, digest } = desc.constraints else {
+            return CommandResponse::error(CommandError::InvalidField)
+        };
+        if desc.variant != EntryVariant::Field(ValueType::Blob) {
+            return CommandResponse::error(CommandError::InvalidField)
+        }
+        let result = match self {
+            Self::Read { offset, .. } => read(store, slot, offset),
+            Self::Write { .. } if desc.readonly => Err(CommandError::NotSupported),
+            Self::Write { offset, chunk, .. } => write(store, slot, offset, chunk.as_slice(), max_size),
+            Self::Digest(_) if !digest => Err(CommandError::NotSupported),
+            Self::Digest(_) => blob_digest(store, slot).map(CommandResponse::from_payload),
+        };
+        result.into()
+    }
+}
+
+fn read(store: &mut impl PropStore, slot: usize, offset: u32) -> Result<CommandResponse, CommandError> {
+    let len = store.blob_len(slot)?;
+    if offset > len {
+        return Err(CommandError::InvalidData)
+    }
+    let mut buf = [0u8; CHUNK_SIZE];
+    let read = store.read_blob(slot, offset, &mut buf)?;
+    let mut res = CommandResponse::from_payload(len.to_le_bytes());
+    res.push(read as u8);
+    res.extend(buf[..read].iter().copied());
+    Ok(res)
+}
+
+fn write(store: &mut impl PropStore, slot: usize, offset: u32, data: &[u8], max_size: u32) -> Result<CommandResponse, CommandError> {
+    // checked before truncating, so a write that is too large leaves the blob as it was
+    if offset as usize + data.len() > max_size as usize {
+        return Err(CommandError::InvalidData)
+    }
+    let len = if offset == 0 {
+        store.truncate_blob(slot, 0)?;
+        0
+    } else {
+        store.blob_len(slot)?
+    };
+    if offset != len {
+        return Err(CommandError::InvalidData)
+    }
+    store.write_blob(slot, offset, data)?;
+    Ok(CommandResponse::from_payload((len + data.len() as u32).to_le_bytes()))
+}
+
+#[cfg(feature = "digest")]
+fn blob_digest(store: &mut impl PropStore, slot: usize) -> Result<[u8; BLOB_DIGEST_LEN], CommandError> {
+    use sha2::{Digest, Sha256};
+    let len = store.blob_len(slot)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64];
+    let mut offset = 0;
+    while offset < len {
+        let read = store.read_blob(slot, offset, &mut buf)?;
+        if read == 0 {
+            return Err(CommandError::Failed)
+        }
+        hasher.update(&buf[..read]);
+        offset += read as u32;
+    }
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(not(feature = "digest"))]
+fn blob_digest(_store: &mut impl PropStore, _slot: usize) -> Result<[u8; BLOB_DIGEST_LEN], CommandError> {
+    Err(CommandError::NotSupported)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{command::Command, config::MESSAGE_LENGTH};
+
+    /// Holds a single blob, in slot 0
+    struct TestStore {
+        blob: [u8; 128],
+        len: usize,
+    }
+
+    impl PropStore for TestStore {
+        fn read(&mut self, _slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+            Ok([0; MESSAGE_LENGTH])
+        }
+        fn write(&mut self, _slot: usize, _bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError> {
+            Ok(())
+        }
+        fn blob_len(&mut self, _slot: usize) -> Result<u32, CommandError> {
+            Ok(self.len as u32)
+        }
+        fn read_blob(&mut self, _slot: usize, offset: u32, buf: &mut [u8]) -> Result<usize, CommandError> {
+            let data = &self.blob[offset as usize..self.len];
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+        fn write_blob(&mut self, _slot: usize, offset: u32, data: &[u8]) -> Result<(), CommandError> {
+            let offset = offset as usize;
+            self.blob[offset..offset + data.len()].copy_from_slice(data);
+            self.len = self.len.max(offset + data.len());
+            Ok(())
+        }
+        fn truncate_blob(&mut self, _slot: usize, len: u32) -> Result<(), CommandError> {
+            self.len = self.len.min(len as usize);
+            Ok(())
+        }
+    }
+
+    crate::props!(P {
+        Cert: cert(),
+        Name: crate::entry::prop("Name"),
+        Key: crate::entry::blob("Key", 16)
+    });
+
+    const fn cert() -> crate::entry::PropEntry {
+        let cert = crate::entry::blob("Certificate", 100);
+        #[cfg(feature = "digest")]
+        let cert = cert.with_digest();
+        cert
+    }
+
+    crate::actions!(A);
+    crate::infos!(I);
+    crate::sections!(S);
+
+    fn send(store: &mut TestStore, bytes: &[u8]) -> [u8; 64] {
+        let mut message = [0u8; 64];
+        message[..bytes.len()].copy_from_slice(bytes);
+        let response = match Command::<A, P, I, S>::from_bytes(&message) {
+            Ok(Command::Blob(command)) => command.handle(store),
+            Ok(_) => panic!("not a blob command"),
+            Err(e) => CommandResponse::error(e),
+        };
+        response.as_bytes().try_into().unwrap()
+    }
+
+    fn write(store: &mut TestStore, prop: u8, offset: u32, data: &[u8]) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[0] = b'B';
+        bytes[1] = prop;
+        bytes[2..6].copy_from_slice(&offset.to_le_bytes());
+        bytes[6] = data.len() as u8;
+        bytes[7..7 + data.len()].copy_from_slice(data);
+        send(store, &bytes)
+    }
+
+    fn read(store: &mut TestStore, offset: u32) -> [u8; 64] {
+        let mut bytes = [b'b', 0, 0, 0, 0, 0];
+        bytes[2..6].copy_from_slice(&offset.to_le_bytes());
+        send(store, &bytes)
+    }
+
+    #[test]
+    fn blob_chunked_transfer() {
+        let data: [u8; 90] = core::array::from_fn(|i| i as u8);
+        let mut store = TestStore { blob: [0; 128], len: 0 };
+
+        assert_eq!([1, 56, 0, 0, 0], write(&mut store, 0, 0, &data[..56])[..5]);
+        // writes need to continue at the end of the blob
+        assert_eq!(0, write(&mut store, 0, 10, &data[56..])[0]);
+        assert_eq!([1, 90, 0, 0, 0], write(&mut store, 0, 56, &data[56..])[..5]);
+        // but not grow past the max size
+        assert_eq!(0, write(&mut store, 0, 90, &[0; 20])[0]);
+        // and only to blob props
+        assert_eq!(0, write(&mut store, 1, 0, &[0; 20])[0]);
+
+        let first = read(&mut store, 0);
+        assert_eq!([1, 90, 0, 0, 0, 56], first[..6]);
+        assert_eq!(data[..56], first[6..62]);
+        let second = read(&mut store, 56);
+        assert_eq!([1, 90, 0, 0, 0, 34], second[..6]);
+        assert_eq!(data[56..], second[6..40]);
+        assert_eq!([1, 90, 0, 0, 0, 0], read(&mut store, 90)[..6]);
+
+        // writing at offset 0 replaces the blob
+        assert_eq!([1, 4, 0, 0, 0], write(&mut store, 0, 0, b"cert")[..5]);
+        assert_eq!([1, 4, 0, 0, 0, 4, b'c'], read(&mut store, 0)[..7]);
+
+        // a replacement that is too large keeps the previous contents
+        assert_eq!(0, write(&mut store, 2, 0, &[0; 20])[0]);
+        assert_eq!([1, 4, 0, 0, 0, 4, b'c'], read(&mut store, 0)[..7]);
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn blob_digest() {
+        let mut store = TestStore { blob: [0; 128], len: 0 };
+        write(&mut store, 0, 0, b"abc");
+        let res = send(&mut store, &[b'#', 0]);
+        assert_eq!(1, res[0]);
+        assert_eq!([0xba, 0x78, 0x16, 0xbf], res[1..5]);
+        assert_eq!([0xf2, 0x00, 0x15, 0xad], res[29..33]);
+        assert_eq!(0, send(&mut store, &[b'#', 1])[0]);
+    }
+}
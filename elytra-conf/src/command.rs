@@ -3,10 +3,14 @@ use num_enum::TryFromPrimitive;
 use core::{panic, slice};
 use core::prelude::rust_2024::{*};
 
+use crate::auth::{AccessLevel, AuthCommand};
+use crate::blob::BlobCommand;
+use crate::dfu::DfuCommand;
 use crate::config::{EntryIndex, EntryType, QueryTarget, QueryTargetKey};
 use crate::{ActionIndex, InfoIndex, PropIndex, SectionIndex};
 use crate::{
     config::{MESSAGE_LENGTH, PAYLOAD_SIZE},
+    entry::EntryDesc,
     field::FieldValue
 };
 
@@ -94,7 +98,7 @@ impl From<Result<CommandResponse, CommandError>> for CommandResponse {
 }
 
 #[repr(u8)]
-#[derive(TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
 pub enum CommandKey {
     ReadProp = 'r' as u8,
     WriteProp = 'w' as u8,
@@ -103,6 +107,27 @@ pub enum CommandKey {
     Query = 'q' as u8,
     Action = 'a' as u8,
     Meta = 'm' as u8,
+    ReadGroupProp = 'g' as u8,
+    WriteGroupProp = 'G' as u8,
+    AddInstance = '+' as u8,
+    RemoveInstance = '-' as u8,
+    ListInstances = 'L' as u8,
+    JobStatus = 'j' as u8,
+    CancelJob = 'x' as u8,
+    DfuBegin = 'U' as u8,
+    DfuWrite = 'D' as u8,
+    DfuVerify = 'V' as u8,
+    DfuCommit = 'C' as u8,
+    BlobRead = 'b' as u8,
+    BlobWrite = 'B' as u8,
+    BlobDigest = '#' as u8,
+    BeginTransaction = 't' as u8,
+    CommitTransaction = 'T' as u8,
+    AbortTransaction = 'X' as u8,
+    ResetProp = 'z' as u8,
+    ResetAll = 'Z' as u8,
+    Login = 'k' as u8,
+    Logout = 'K' as u8,
     Noop = 0,
 }
 
@@ -116,11 +141,118 @@ pub enum Command<A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> {
     ReadInfo(I),
     WriteInfo((I, FieldValue)),
     Query((EntryIndex<A, P, I, S>, QueryTarget)),
-    Action(A),
+    Action((A, ActionArgs)),
     Meta,
+    /// Read a prop of a repeated section instance: (section, instance, prop)
+    ReadGroupProp((S, u8, P)),
+    /// Write a prop of a repeated section instance: (section, instance, prop, value)
+    WriteGroupProp((S, u8, P, FieldValue)),
+    AddInstance(S),
+    RemoveInstance((S, u8)),
+    ListInstances(S),
+    /// Query the state of a long-running action by job id
+    JobStatus(u8),
+    CancelJob(u8),
+    /// Firmware transfer, see [`Dfu`](crate::dfu::Dfu)
+    Dfu(DfuCommand),
+    /// Chunked access to blob props, see [`BlobCommand`]
+    Blob(BlobCommand<P>),
+    /// Stage the following prop writes until the transaction is committed, see [`Transaction`](crate::transaction::Transaction)
+    BeginTransaction,
+    /// Validate and apply the staged writes
+    CommitTransaction,
+    /// Discard the staged writes
+    AbortTransaction,
+    /// Reset a prop to its default value
+    ResetProp(P),
+    /// Reset all props to their default values
+    ResetAll,
+    /// Session access level, see [`Auth`](crate::auth::Auth)
+    Auth(AuthCommand),
     Noop,
 }
 
+/// Max number of data bytes in a single chunked write, see [`Chunk`]
+pub const CHUNK_SIZE: usize = 56;
+
+/// A part of a large transfer (firmware images, blobs), sent as a length byte followed by the data
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Chunk {
+    len: u8,
+    data: [u8; CHUNK_SIZE],
+}
+
+impl Chunk {
+    pub fn from_message(bytes: &[u8]) -> Result<Self, CommandError> {
+        let (&len, data) = bytes.split_first().ok_or(CommandError::MissingArgument)?;
+        if len as usize > CHUNK_SIZE || data.len() < len as usize {
+            return Err(CommandError::InvalidData)
+        }
+        let mut chunk = Self { len, data: [0; CHUNK_SIZE] };
+        chunk.data[..len as usize].copy_from_slice(&data[..len as usize]);
+        Ok(chunk)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+/// Parameter values of an action command.
+/// 
+/// The payload starts with the number of arguments, followed by each argument as a length byte and
+/// the value payload. Parameters without an argument use their default value.
+pub struct ActionArgs {
+    desc: &'static EntryDesc,
+    data: [u8; PAYLOAD_SIZE],
+}
+
+impl ActionArgs {
+    pub fn from_message(desc: &'static EntryDesc, bytes: &[u8]) -> Result<Self, CommandError> {
+        let mut data = [0u8; PAYLOAD_SIZE];
+        let len = bytes.len().min(PAYLOAD_SIZE);
+        data[..len].copy_from_slice(&bytes[..len]);
+        let args = Self { desc, data };
+
+        if args.count() > desc.signature.params.len() {
+            return Err(CommandError::InvalidData)
+        }
+        let mut offset = 1;
+        for _ in 0..args.count() {
+            offset += 1 + *args.data.get(offset).ok_or(CommandError::InvalidData)? as usize;
+        }
+        if offset > PAYLOAD_SIZE {
+            return Err(CommandError::InvalidData)
+        }
+        Ok(args)
+    }
+
+    /// The number of arguments that were sent
+    pub fn count(&self) -> usize {
+        self.data[0] as usize
+    }
+
+    /// Returns the value of parameter `index`, or `None` if the action has no such parameter
+    pub fn get(&self, index: usize) -> Option<FieldValue> {
+        let desc = self.desc.signature.params.get(index)?;
+        if index >= self.count() {
+            return Some(FieldValue::default_value(desc))
+        }
+        let mut offset = 1;
+        for _ in 0..index {
+            offset += 1 + self.data[offset] as usize;
+        }
+        let len = self.data[offset] as usize;
+        Some(FieldValue::from_message(desc, &self.data[offset + 1..offset + 1 + len]))
+    }
+
+    /// An empty value for the result of the action, if it has one
+    pub fn result(&self) -> Option<FieldValue> {
+        self.desc.signature.result.map(FieldValue::new)
+    }
+}
+
 impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P, I, S> {
     pub fn from_bytes<'a>(bytes: &[u8]) -> Result<Command<A, P, I, S>, CommandError> {
         let mut bytes = bytes.into_iter();
@@ -130,7 +262,9 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
 
         match key {
             CommandKey::Action =>{
-                Ok(Command::Action(Self::get_action_index(&mut bytes)?))
+                let action = Self::get_action_index(&mut bytes)?;
+                let args = ActionArgs::from_message(action.get_entry(), bytes.as_slice())?;
+                Ok(Command::Action((action, args)))
             },
             CommandKey::ReadProp => {
                 Ok(Command::ReadProp(Self::get_prop_index(&mut bytes)?))
@@ -165,6 +299,10 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
                     QueryTargetKey::Field => Ok(QueryTarget::Field),
                     QueryTargetKey::Help => Ok(QueryTarget::Help),
                     QueryTargetKey::Icon => Ok(QueryTarget::Icon),
+                    QueryTargetKey::Default => match entry_index {
+                        EntryIndex::Prop(_) | EntryIndex::Info(_) => Ok(QueryTarget::Default),
+                        _ => Err(CommandError::InvalidQuery)
+                    },
                     QueryTargetKey::Option => {
                         // let entry = entry_index.get_entry();
                         // let Constraints::Values(ValueConstraints{value_provider, ..}) = &entry.constraints else {
@@ -177,6 +315,29 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
                         let option_index: u16 = u16::from_le_bytes(index_bytes);
                         Ok(QueryTarget::Option(option_index))
                     },
+                    QueryTargetKey::Condition => match entry_index {
+                        EntryIndex::Section(_si) => Ok(QueryTarget::Condition(Self::get_position(&mut bytes)?)),
+                        _ => Err(CommandError::InvalidQuery)
+                    },
+                    QueryTargetKey::Param => match entry_index {
+                        EntryIndex::Action(_ai) => Ok(QueryTarget::Param(Self::get_position(&mut bytes)?)),
+                        _ => Err(CommandError::InvalidQuery)
+                    },
+                    QueryTargetKey::ParamOption => match entry_index {
+                        EntryIndex::Action(_ai) => {
+                            let param = *bytes.next().ok_or(CommandError::MissingArgument)?;
+                            Ok(QueryTarget::ParamOption((param, Self::get_position(&mut bytes)?)))
+                        },
+                        _ => Err(CommandError::InvalidQuery)
+                    },
+                    QueryTargetKey::Result => match entry_index {
+                        EntryIndex::Action(_ai) => Ok(QueryTarget::Result),
+                        _ => Err(CommandError::InvalidQuery)
+                    },
+                    QueryTargetKey::Heading => match entry_index {
+                        EntryIndex::Section(_si) => Ok(QueryTarget::Heading(Self::get_position(&mut bytes)?)),
+                        _ => Err(CommandError::InvalidQuery)
+                    },
                     QueryTargetKey::Layout =>  match entry_index {
                         EntryIndex::Section(_si) => Ok(QueryTarget::Layout),
                         _ => Err(CommandError::InvalidQuery)
@@ -184,8 +345,93 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
                 }?;
                 Ok(Command::Query((entry_index, target)))
             },
+            CommandKey::JobStatus => Ok(Command::JobStatus(*bytes.next().ok_or(CommandError::MissingArgument)?)),
+            CommandKey::CancelJob => Ok(Command::CancelJob(*bytes.next().ok_or(CommandError::MissingArgument)?)),
+            CommandKey::DfuBegin => Ok(Command::Dfu(DfuCommand::Begin {
+                size: Self::get_u32(&mut bytes)?,
+                crc: Self::get_u32(&mut bytes)?,
+            })),
+            CommandKey::DfuWrite => Ok(Command::Dfu(DfuCommand::Write {
+                offset: Self::get_u32(&mut bytes)?,
+                chunk: Chunk::from_message(bytes.as_slice())?,
+            })),
+            CommandKey::DfuVerify => Ok(Command::Dfu(DfuCommand::Verify)),
+            CommandKey::DfuCommit => Ok(Command::Dfu(DfuCommand::Commit)),
+            CommandKey::BlobRead => Ok(Command::Blob(BlobCommand::Read {
+                prop: Self::get_prop_index(&mut bytes)?,
+                offset: Self::get_u32(&mut bytes)?,
+            })),
+            CommandKey::BlobWrite => Ok(Command::Blob(BlobCommand::Write {
+                prop: Self::get_prop_index(&mut bytes)?,
+                offset: Self::get_u32(&mut bytes)?,
+                chunk: Chunk::from_message(bytes.as_slice())?,
+            })),
+            CommandKey::BlobDigest => Ok(Command::Blob(BlobCommand::Digest(Self::get_prop_index(&mut bytes)?))),
+            CommandKey::BeginTransaction => Ok(Command::BeginTransaction),
+            CommandKey::CommitTransaction => Ok(Command::CommitTransaction),
+            CommandKey::AbortTransaction => Ok(Command::AbortTransaction),
+            CommandKey::ResetProp => Ok(Command::ResetProp(Self::get_prop_index(&mut bytes)?)),
+            CommandKey::ResetAll => Ok(Command::ResetAll),
+            CommandKey::Login => Ok(Command::Auth(AuthCommand::Login {
+                level: AccessLevel::try_from_primitive(*bytes.next().ok_or(CommandError::MissingArgument)?)
+                    .or(Err(CommandError::InvalidData))?,
+                secret: Chunk::from_message(bytes.as_slice())?,
+            })),
+            CommandKey::Logout => Ok(Command::Auth(AuthCommand::Logout)),
             CommandKey::Noop => Ok(Command::Noop),
             CommandKey::Meta => Ok(Command::Meta),
+            CommandKey::ReadGroupProp => {
+                let (section, instance) = Self::get_group_instance(&mut bytes)?;
+                Ok(Command::ReadGroupProp((section, instance, Self::get_prop_index(&mut bytes)?)))
+            },
+            CommandKey::WriteGroupProp => {
+                let (section, instance) = Self::get_group_instance(&mut bytes)?;
+                let prop_field = Self::get_prop_index(&mut bytes)?;
+                let payload = Self::get_payload(&mut bytes)?;
+                let field_value = FieldValue::from_message(P::get_entry(prop_field), payload);
+                Ok(Command::WriteGroupProp((section, instance, prop_field, field_value)))
+            },
+            CommandKey::AddInstance => Ok(Command::AddInstance(Self::get_group_index(&mut bytes)?)),
+            CommandKey::RemoveInstance => Ok(Command::RemoveInstance(Self::get_group_instance(&mut bytes)?)),
+            CommandKey::ListInstances => Ok(Command::ListInstances(Self::get_group_index(&mut bytes)?)),
+        }
+    }
+
+    /// The access level needed to run the command.
+    ///
+    /// Writes, resets, blob access and actions use the access level of their entry, and changing the
+    /// instances of a repeated section that of the section. Transactions only need the access of the
+    /// writes staged in them. Firmware updates and resetting all props need [`AccessLevel::Admin`].
+    /// Reading props and info fields is always allowed, except for blobs.
+    pub fn required_access(&self) -> AccessLevel {
+        match self {
+            Command::WriteProp((prop, _))
+            | Command::ResetProp(prop) => prop.get_entry().access,
+            Command::WriteGroupProp((section, _, prop, _)) => section.get_entry().access.max(prop.get_entry().access),
+            Command::Blob(blob) => blob.prop().get_entry().access,
+            Command::AddInstance(section)
+            | Command::RemoveInstance((section, _)) => section.get_entry().access,
+            Command::WriteInfo((info, _)) => info.get_entry().access,
+            Command::Action((action, _)) => action.get_entry().access,
+            Command::Dfu(_) | Command::ResetAll => AccessLevel::Admin,
+            _ => AccessLevel::Public,
+        }
+    }
+
+    fn get_group_index(bytes: &mut slice::Iter<'_, u8>) -> Result<S, CommandError> {
+        let section = Self::get_section_index(bytes)?;
+        if section.get_entry().repeat_count().is_none() {
+            return Err(CommandError::InvalidSection)
+        }
+        Ok(section)
+    }
+
+    fn get_group_instance(bytes: &mut slice::Iter<'_, u8>) -> Result<(S, u8), CommandError> {
+        let section = Self::get_group_index(bytes)?;
+        let instance = *bytes.next().ok_or(CommandError::MissingArgument)?;
+        match section.get_entry().repeat_count() {
+            Some(count) if instance < count => Ok((section, instance)),
+            _ => Err(CommandError::InvalidInstance)
         }
     }
 
@@ -234,6 +480,24 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
             Section => Ok(EntryIndex::Section(Self::get_section_index(bytes)?)),
         }
     }
+
+    /// Reads a u32 (LE), for the offsets and sizes of firmware and blob transfers
+    fn get_u32(bytes: &mut slice::Iter<'_, u8>) -> Result<u32, CommandError> {
+        let mut value = [0u8; 4];
+        for b in value.iter_mut() {
+            *b = *bytes.next().ok_or(CommandError::MissingArgument)?;
+        }
+        Ok(u32::from_le_bytes(value))
+    }
+
+    /// Reads a layout position (u16 LE) for section queries
+    fn get_position(bytes: &mut slice::Iter<'_, u8>) -> Result<u16, CommandError> {
+        let Some(index_bytes) = bytes.next().map(|b| bytes.next().map(|b2| [*b, *b2])).flatten() else {
+            return Err(CommandError::MissingArgument)
+        };
+        Ok(u16::from_le_bytes(index_bytes))
+    }
+
     fn get_query_prop(bytes: &mut slice::Iter<'_, u8>) -> Result<QueryTargetKey, CommandError> {
         let byte = *bytes.next().ok_or(CommandError::MissingArgument)?;
         QueryTargetKey::try_from_primitive(byte).or(Err(CommandError::InvalidQuery))
@@ -244,7 +508,7 @@ impl <A: ActionIndex, P: PropIndex, I: InfoIndex, S: SectionIndex> Command<A, P,
 
 
 #[repr(u8)]
-#[derive(Debug, strum::Display, Clone, Copy)]
+#[derive(Debug, strum::Display, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 pub enum CommandError {
     InvalidCommand = 1,
     MissingArgument = 2,
@@ -258,4 +522,12 @@ pub enum CommandError {
     NotSupported = 10,
     Failed = 11,
     NoContent = 12,
+    InvalidInstance = 13,
+    InvalidJob = 14,
+    /// The entry needs a higher access level than the session has, or a condition makes it read only
+    Locked = 15,
+    /// The session timed out, and the host needs to log in again
+    SessionExpired = 16,
+    /// Wrong PIN or passphrase, or too many failed attempts
+    InvalidCredentials = 17,
 }
\ No newline at end of file
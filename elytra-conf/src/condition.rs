@@ -0,0 +1,104 @@
+use core::prelude::rust_2024::*;
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    command::CommandResponse,
+    entry::EntryVariant,
+    field::FieldValue,
+    traits::PropIndex,
+    values::ValueType,
+};
+
+/// What a [`Condition`] controls for the layout entry it is attached to
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConditionEffect {
+    /// The field is hidden unless the condition matches
+    Visible = b'v',
+    /// The field is read only unless the condition matches
+    Writable = b'w',
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConditionOp {
+    Equals = b'=',
+    NotEquals = b'!',
+}
+
+/// A dependency of a layout entry on the value of another prop.
+///
+/// The prop value is compared numerically: toggles are `0`/`1`, options use the index of
+/// the first selected option and integers use their value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition<P: PropIndex> {
+    pub effect: ConditionEffect,
+    pub prop: P,
+    pub op: ConditionOp,
+    pub value: i64,
+}
+
+pub struct ConditionBuilder<P: PropIndex> {
+    effect: ConditionEffect,
+    prop: P,
+}
+
+/// Only show the layout entry when `prop` matches the condition
+#[allow(unused)]
+pub const fn show_when<P: PropIndex>(prop: P) -> ConditionBuilder<P> {
+    ConditionBuilder { effect: ConditionEffect::Visible, prop }
+}
+
+/// Only allow writing to the layout entry when `prop` matches the condition
+#[allow(unused)]
+pub const fn writable_when<P: PropIndex>(prop: P) -> ConditionBuilder<P> {
+    ConditionBuilder { effect: ConditionEffect::Writable, prop }
+}
+
+impl <P: PropIndex> ConditionBuilder<P> {
+    pub const fn equals(self, value: i64) -> Condition<P> {
+        Condition { effect: self.effect, prop: self.prop, op: ConditionOp::Equals, value }
+    }
+    pub const fn not_equals(self, value: i64) -> Condition<P> {
+        Condition { effect: self.effect, prop: self.prop, op: ConditionOp::NotEquals, value }
+    }
+    pub const fn is_enabled(self) -> Condition<P> {
+        self.equals(1)
+    }
+    pub const fn is_disabled(self) -> Condition<P> {
+        self.equals(0)
+    }
+}
+
+impl <P: PropIndex> Condition<P> {
+    pub fn matches_value(&self, value: i64) -> bool {
+        match self.op {
+            ConditionOp::Equals => value == self.value,
+            ConditionOp::NotEquals => value != self.value,
+        }
+    }
+
+    /// Evaluates the condition against the current value of the prop it depends on
+    pub fn matches(&self, field_value: &FieldValue) -> bool {
+        let value = match self.prop.get_entry().variant {
+            EntryVariant::Field(ValueType::Toggle) => field_value.get_enabled() as i64,
+            EntryVariant::Field(ValueType::Options) => field_value.get_options().as_slice().first().map_or(-1, |o| *o as i64),
+            EntryVariant::Field(ValueType::Integer) => field_value.get_integer(),
+            _ => return false,
+        };
+        self.matches_value(value)
+    }
+}
+
+impl <P: PropIndex> From<&Condition<P>> for CommandResponse {
+    fn from(value: &Condition<P>) -> Self {
+        let mut res = CommandResponse::new();
+        res.push(value.effect as u8);
+        res.push(value.op as u8);
+        res.push(value.prop.as_index() as u8);
+        res.extend(value.value.to_le_bytes());
+        res
+    }
+}
@@ -3,13 +3,19 @@ use num_enum::TryFromPrimitive;
 
 use crate::{
    command::{CommandError, CommandResponse}, 
-   entry::{Constraints, EntryDesc, Field}, 
-   traits::{ActionIndex, PropIndex, InfoIndex, SectionIndex}
+   condition::{Condition, ConditionEffect},
+   entry::{Constraints, EntryDesc, EntryVariant, ExtraFlags, Field}, 
+   field::FieldValue,
+   store::PropStore,
+   traits::{ActionIndex, PropIndex, InfoIndex, SectionIndex},
+   values::ValueType
 };
 use core::marker::PhantomData;
 
 pub const MESSAGE_LENGTH: usize = 64;
 pub const PAYLOAD_SIZE: usize = MESSAGE_LENGTH - 1;
+/// Version of the protocol, as reported by the meta command
+pub const PROTO_VERSION: u8 = 2;
 
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, TryFromPrimitive, strum::EnumString)]
@@ -29,7 +35,13 @@ pub enum QueryTargetKey {
     Icon = b'i',
     Help = b'h',
     Layout = b'l',
-    Option = b'o'
+    Option = b'o',
+    Condition = b'c',
+    Heading = b't',
+    Param = b'a',
+    ParamOption = b'O',
+    Result = b'r',
+    Default = b'd',
 }
 
 #[derive(Debug)]
@@ -39,8 +51,23 @@ pub enum QueryTarget {
     Help,
     Layout,
     Option(u16),
+    /// The condition of the layout entry at the given position of a section
+    Condition(u16),
+    /// The text of the heading at the given position of a section
+    Heading(u16),
+    /// The description of an action parameter
+    Param(u16),
+    /// An option of an action parameter: (param, option)
+    ParamOption((u8, u16)),
+    /// The description of the action result
+    Result,
+    /// The default value of a prop or info field, encoded like a read response
+    Default,
 }
 
+/// A [`Condition`] attached to a field in the layout of a section
+pub type LayoutCondition<S, P, I, A> = (S, Field<P, I, S, A>, Condition<P>);
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum EntryIndex<AI: ActionIndex, PI: PropIndex, II: InfoIndex, SI: SectionIndex> {
@@ -63,12 +90,13 @@ impl <AI: ActionIndex, PI: PropIndex, II: InfoIndex, SI: SectionIndex> EntryInde
 
 pub struct Config<
     const L: usize,
-    SI: SectionIndex, 
-    PI: PropIndex, 
-    II: InfoIndex, 
-    AI: ActionIndex
+    SI: SectionIndex + 'static, 
+    PI: PropIndex + 'static, 
+    II: InfoIndex + 'static, 
+    AI: ActionIndex + 'static
 > {
-    pub layout: [(SI, Field<PI, II>); L],
+    pub layout: [(SI, Field<PI, II, SI, AI>); L],
+    pub conditions: &'static [LayoutCondition<SI, PI, II, AI>],
     _field_index: PhantomData<PI>,
     _status_index: PhantomData<II>,
     _action_index: PhantomData<AI>
@@ -76,20 +104,23 @@ pub struct Config<
 
 
 
-impl <'s: 'static, const L: usize, S: SectionIndex, P: PropIndex, I: InfoIndex, A: ActionIndex>  Config<L, S, P, I, A> {
-
-    const PROTO_VERSION: u8 = 1;
+impl <'s: 'static, const L: usize, S: SectionIndex + 'static, P: PropIndex + 'static, I: InfoIndex + 'static, A: ActionIndex + 'static>  Config<L, S, P, I, A> {
 
     pub const fn new(
-            layout: [(S, Field<P, I>); L]) -> Self {
+            layout: [(S, Field<P, I, S, A>); L]) -> Self {
         Self {
             layout,
+            conditions: &[],
             _field_index: PhantomData,
             _status_index: PhantomData,
             _action_index: PhantomData
         }
     }
 
+    pub const fn with_conditions(self, conditions: &'static [LayoutCondition<S, P, I, A>]) -> Self {
+        Self { conditions, ..self }
+    }
+
     // async fn _parse_command<'a, CH: CommandHandler<PI, II, AI>>(&'s self, mut bytes: slice::Iter<'a, u8>, handler: &mut CH) -> Result<CommandResponse, CommandError> {
         
     //     let command = bytes.next()
@@ -180,7 +211,7 @@ impl <'s: 'static, const L: usize, S: SectionIndex, P: PropIndex, I: InfoIndex,
     pub fn handle_meta(&'s self) -> CommandResponse {
         let mut res = CommandResponse::new();
         // Protocol version (1 byte)
-        res.push(Self::PROTO_VERSION);
+        res.push(PROTO_VERSION);
 
         // Field section count (1 byte)
         res.push(S::count() as u8);
@@ -201,23 +232,73 @@ impl <'s: 'static, const L: usize, S: SectionIndex, P: PropIndex, I: InfoIndex,
         let entry = entry_index.get_entry();
         use QueryTarget::{*};
         match target {
-            Field => Ok(entry.into()),
+            Field => match entry_index {
+                EntryIndex::Section(si) if self.has_conditions(si) => 
+                    Ok(entry.response_with_flags(entry.flags() | ExtraFlags::HasConditions)),
+                _ => Ok(entry.into()),
+            },
             Help => entry.help.ok_or(CommandError::NoContent).map(Into::into),
             Icon => entry.icon.ok_or(CommandError::NoContent).map(Into::into),
-            Option(option_index) => {
-                let Constraints::Values(constr) = &entry.constraints else {
-                    return Err(CommandError::NotSupported)
-                };
-                constr.value_provider.get(option_index as usize)
-                    .ok_or(CommandError::InvalidOption).map(|s| (*s).into())
+            Option(option_index) => Self::option_response(entry, option_index),
+            Param(param) => entry.signature.params.get(param as usize)
+                .ok_or(CommandError::InvalidQuery).map(Into::into),
+            ParamOption((param, option_index)) => {
+                let param = entry.signature.params.get(param as usize).ok_or(CommandError::InvalidQuery)?;
+                Self::option_response(param, option_index)
+            },
+            Result => entry.signature.result.ok_or(CommandError::NoContent).map(Into::into),
+            Default => match entry_index {
+                EntryIndex::Prop(_) | EntryIndex::Info(_) => Ok(FieldValue::default_value(entry).into()),
+                _ => Err(CommandError::InvalidQuery)
             },
             Layout => match entry_index {
                         EntryIndex::Section(si) => Ok( self.section_layout(si)),
                         _ => Err(CommandError::InvalidQuery)
+            },
+            Condition(position) => match entry_index {
+                EntryIndex::Section(si) => self.layout_condition(si, position as usize)
+                    .ok_or(CommandError::NoContent).map(Into::into),
+                _ => Err(CommandError::InvalidQuery)
+            },
+            Heading(position) => match entry_index {
+                EntryIndex::Section(si) => match self.section_fields(si).nth(position as usize) {
+                    Some(crate::entry::Field::Heading(text)) => Ok(text.into()),
+                    Some(_) => Err(CommandError::NoContent),
+                    None => Err(CommandError::InvalidField),
+                },
+                _ => Err(CommandError::InvalidQuery)
             }
         }
     }
 
+    fn option_response(entry: &EntryDesc, option_index: u16) -> Result<CommandResponse, CommandError> {
+        let Constraints::Values(constr) = &entry.constraints else {
+            return Err(CommandError::NotSupported)
+        };
+        constr.value_provider.get(option_index as usize)
+            .ok_or(CommandError::InvalidOption).map(|s| (*s).into())
+    }
+
+    /// Resets a prop to its default value
+    pub fn reset_prop(&'s self, store: &mut impl PropStore, prop: P) -> Result<(), CommandError> {
+        store.reset(prop.as_index())?;
+        if prop.get_entry().variant == EntryVariant::Field(ValueType::Blob) {
+            store.truncate_blob(prop.as_index(), 0)?;
+        }
+        Ok(())
+    }
+
+    /// Resets all props to their default values, including those of repeated section instances
+    pub fn reset_all(&'s self, store: &mut impl PropStore) -> Result<(), CommandError> {
+        for prop in (0..P::count()).filter_map(|i| P::from_byte(i as u8)) {
+            self.reset_prop(store, prop)?;
+        }
+        for slot in P::count()..self.storage_slots() {
+            store.reset(slot)?;
+        }
+        Ok(())
+    }
+
     pub fn prop_field(&'s self, index: P) -> &'s EntryDesc {
         index.get_entry()
     }
@@ -234,6 +315,93 @@ impl <'s: 'static, const L: usize, S: SectionIndex, P: PropIndex, I: InfoIndex,
         index.get_entry()
     }
 
+    /// The props that make up each instance of a repeated section
+    pub fn group_props(&'s self, section: S) -> impl Iterator<Item = P> + 's {
+        self.section_fields(section).filter_map(|field| match field {
+            Field::Prop(pi) => Some(pi),
+            _ => None,
+        })
+    }
+
+    /// Returns the storage slot for a prop of a repeated section instance.
+    ///
+    /// The first instance uses the slot of the prop itself (`prop.as_index()`), while the
+    /// remaining instances are allocated slots after the regular props, in section order.
+    pub fn group_slot(&'s self, section: S, instance: u8, prop: P) -> Result<usize, CommandError> {
+        let count = section.get_entry().repeat_count().ok_or(CommandError::InvalidSection)?;
+        if instance >= count {
+            return Err(CommandError::InvalidInstance)
+        }
+        let position = self.group_props(section).position(|p| p == prop)
+            .ok_or(CommandError::InvalidField)?;
+        if instance == 0 {
+            return Ok(prop.as_index())
+        }
+        let base = P::count() + (0..section.as_index())
+            .filter_map(|i| S::from_byte(i as u8))
+            .map(|si| self.extra_group_slots(si))
+            .sum::<usize>();
+        let fields = self.group_props(section).count();
+        Ok(base + (instance as usize - 1) * fields + position)
+    }
+
+    /// The total number of storage slots needed for all props, including repeated section instances
+    pub fn storage_slots(&'s self) -> usize {
+        P::count() + (0..S::count())
+            .filter_map(|i| S::from_byte(i as u8))
+            .map(|si| self.extra_group_slots(si))
+            .sum::<usize>()
+    }
+
+    fn extra_group_slots(&'s self, section: S) -> usize {
+        match section.get_entry().repeat_count() {
+            Some(count) => (count as usize - 1) * self.group_props(section).count(),
+            None => 0,
+        }
+    }
+
+    /// The layout of `section`, in order
+    pub fn section_fields(&'s self, section: S) -> impl Iterator<Item = Field<P, I, S, A>> + 's {
+        self.layout.iter()
+            .filter(move |(si, _)| *si == section)
+            .map(|(_, field)| *field)
+    }
+
+    /// The sections nested in `section`
+    pub fn subsections(&'s self, section: S) -> impl Iterator<Item = S> + 's {
+        self.section_fields(section).filter_map(|field| match field {
+            Field::Section(si) => Some(si),
+            _ => None,
+        })
+    }
+
+    /// Returns the condition for the field at `position` in the layout of `section`, if any
+    pub fn layout_condition(&'s self, section: S, position: usize) -> Option<&'s Condition<P>> {
+        let field = self.section_fields(section).nth(position)?;
+        self.field_condition(section, field)
+    }
+
+    pub fn field_condition(&'s self, section: S, field: Field<P, I, S, A>) -> Option<&'s Condition<P>> {
+        self.conditions.iter()
+            .find(|(si, f, _)| *si == section && *f == field)
+            .map(|(_, _, condition)| condition)
+    }
+
+    /// Checks the `writable_when` conditions of `prop` in every section it appears in,
+    /// reading the current values of the props they depend on with `read`
+    pub fn check_writable(&'s self, prop: P, mut read: impl FnMut(P) -> Result<FieldValue, CommandError>) -> Result<(), CommandError> {
+        for (_, field, condition) in self.conditions.iter() {
+            if *field == Field::Prop(prop) && condition.effect == ConditionEffect::Writable && !condition.matches(&read(condition.prop)?) {
+                return Err(CommandError::Locked)
+            }
+        }
+        Ok(())
+    }
+
+    fn has_conditions(&'s self, section: S) -> bool {
+        self.conditions.iter().any(|(si, _, _)| *si == section)
+    }
+
     pub fn section_layout(&'s self, section: S) -> CommandResponse {
         let mut res = CommandResponse::new();
         self.layout.iter()
@@ -0,0 +1,430 @@
+use core::prelude::rust_2024::*;
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+use crate::command::{CHUNK_SIZE, Chunk, CommandError, CommandResponse};
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE) used to verify firmware images
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 = CRC32_TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub const fn finish(&self) -> u32 {
+        !self.0
+    }
+
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = Self::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+/// Commands of the firmware transfer sub-protocol
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DfuCommand {
+    /// Start (or resume) a transfer of an image with the given size and CRC-32
+    Begin { size: u32, crc: u32 },
+    Write { offset: u32, chunk: Chunk },
+    /// Check the CRC-32 of the written image
+    Verify,
+    /// Hand a verified image over to the bootloader
+    Commit,
+}
+
+/// The partition a firmware image is written to, and how to activate it
+pub trait FirmwareTarget {
+    type Partition: NorFlash;
+
+    fn partition(&mut self) -> &mut Self::Partition;
+
+    /// Called with a verified image, e.g. to mark the partition as bootable
+    fn commit(&mut self, size: u32, crc: u32) -> Result<(), CommandError>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Transfer {
+    size: u32,
+    crc: u32,
+    /// Number of bytes written so far
+    offset: u32,
+    verified: bool,
+}
+
+/// Handles [`DfuCommand`]s, writing the image to a [`FirmwareTarget`].
+///
+/// Chunks need to be written in order. Sending `Begin` with the same size and CRC as the
+/// transfer in progress resumes it, responding with the offset to continue from.
+pub struct Dfu<T: FirmwareTarget> {
+    target: T,
+    transfer: Option<Transfer>,
+}
+
+fn storage_error<E: NorFlashError>(_: E) -> CommandError {
+    CommandError::Failed
+}
+
+impl <T: FirmwareTarget> Dfu<T> {
+    pub const fn new(target: T) -> Self {
+        Self { target, transfer: None }
+    }
+
+    pub fn target(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    pub fn handle(&mut self, command: DfuCommand) -> CommandResponse {
+        let result = match command {
+            DfuCommand::Begin { size, crc } => self.begin(size, crc).map(|o| CommandResponse::from_payload(o.to_le_bytes())),
+            DfuCommand::Write { offset, chunk } => self.write(offset, chunk.as_slice()).map(|o| CommandResponse::from_payload(o.to_le_bytes())),
+            DfuCommand::Verify => self.verify().map(|crc| CommandResponse::from_payload(crc.to_le_bytes())),
+            DfuCommand::Commit => self.commit().map(|_| CommandResponse::ok()),
+        };
+        result.into()
+    }
+
+    /// Starts or resumes a transfer, returning the offset to write from
+    pub fn begin(&mut self, size: u32, crc: u32) -> Result<u32, CommandError> {
+        if let Some(transfer) = self.transfer
+            && transfer.size == size && transfer.crc == crc {
+            return Ok(transfer.offset)
+        }
+        if !CHUNK_SIZE.is_multiple_of(T::Partition::WRITE_SIZE) {
+            return Err(CommandError::NotSupported)
+        }
+        let partition = self.target.partition();
+        if size as usize > partition.capacity() {
+            return Err(CommandError::InvalidData)
+        }
+        let erase_end = (size as usize).next_multiple_of(T::Partition::ERASE_SIZE);
+        self.transfer = None;
+        partition.erase(0, erase_end as u32).map_err(storage_error)?;
+        self.transfer = Some(Transfer { size, crc, offset: 0, verified: false });
+        Ok(0)
+    }
+
+    /// Writes a chunk at `offset`, returning the offset of the next chunk
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<u32, CommandError> {
+        let transfer = self.transfer.as_mut().ok_or(CommandError::Failed)?;
+        if offset != transfer.offset || offset as usize + data.len() > transfer.size as usize {
+            return Err(CommandError::InvalidData)
+        }
+        // Pad the last chunk to the write size of the flash, using the erased value
+        let mut buf = [0xFFu8; CHUNK_SIZE];
+        buf[..data.len()].copy_from_slice(data);
+        let len = data.len().next_multiple_of(T::Partition::WRITE_SIZE);
+        let padded = buf.get(..len).ok_or(CommandError::NotSupported)?;
+        self.target.partition().write(offset, padded).map_err(storage_error)?;
+        transfer.offset += data.len() as u32;
+        transfer.verified = false;
+        Ok(transfer.offset)
+    }
+
+    /// Reads back the written image, returning its CRC-32 if it matches the expected one
+    pub fn verify(&mut self) -> Result<u32, CommandError> {
+        let transfer = self.transfer.as_mut().ok_or(CommandError::Failed)?;
+        if transfer.offset != transfer.size {
+            return Err(CommandError::Failed)
+        }
+        let partition = self.target.partition();
+        let mut crc = Crc32::new();
+        let mut buf = [0u8; 64];
+        // Read whole read units that fit the buffer, as the last read is padded up to the read size
+        let max_len = buf.len() - buf.len() % T::Partition::READ_SIZE;
+        if max_len == 0 {
+            return Err(CommandError::NotSupported)
+        }
+        let mut offset = 0;
+        while offset < transfer.size {
+            let len = (transfer.size - offset).min(max_len as u32) as usize;
+            let read_len = len.next_multiple_of(T::Partition::READ_SIZE);
+            partition.read(offset, &mut buf[..read_len]).map_err(storage_error)?;
+            crc.update(&buf[..len]);
+            offset += len as u32;
+        }
+        if crc.finish() != transfer.crc {
+            return Err(CommandError::InvalidData)
+        }
+        transfer.verified = true;
+        Ok(crc.finish())
+    }
+
+    pub fn commit(&mut self) -> Result<(), CommandError> {
+        match self.transfer {
+            Some(Transfer { size, crc, verified: true, .. }) => {
+                self.target.commit(size, crc)?;
+                self.transfer = None;
+                Ok(())
+            },
+            _ => Err(CommandError::Failed),
+        }
+    }
+}
+
+/// A partition backed by RAM, for mock devices and tests
+pub struct RamPartition<const N: usize> {
+    data: [u8; N],
+}
+
+impl <const N: usize> Default for RamPartition<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <const N: usize> RamPartition<N> {
+    pub const fn new() -> Self {
+        Self { data: [0xFF; N] }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn range(offset: u32, len: usize) -> Result<core::ops::Range<usize>, NorFlashErrorKind> {
+        let start = offset as usize;
+        match start.checked_add(len) {
+            Some(end) if end <= N => Ok(start..end),
+            _ => Err(NorFlashErrorKind::OutOfBounds),
+        }
+    }
+}
+
+impl <const N: usize> ErrorType for RamPartition<N> {
+    type Error = NorFlashErrorKind;
+}
+
+impl <const N: usize> ReadNorFlash for RamPartition<N> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        bytes.copy_from_slice(&self.data[Self::range(offset, bytes.len())?]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl <const N: usize> NorFlash for RamPartition<N> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 256;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if !(from as usize).is_multiple_of(Self::ERASE_SIZE) || !(to as usize).is_multiple_of(Self::ERASE_SIZE) {
+            return Err(NorFlashErrorKind::NotAligned)
+        }
+        let range = Self::range(from, to.saturating_sub(from) as usize)?;
+        self.data[range].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        if !(offset as usize).is_multiple_of(Self::WRITE_SIZE) || !bytes.len().is_multiple_of(Self::WRITE_SIZE) {
+            return Err(NorFlashErrorKind::NotAligned)
+        }
+        let range = Self::range(offset, bytes.len())?;
+        // Like NOR flash, writing can only clear bits
+        for (cell, byte) in self.data[range].iter_mut().zip(bytes) {
+            *cell &= *byte;
+        }
+        Ok(())
+    }
+}
+
+/// A firmware target backed by a [`RamPartition`], which keeps the size and CRC of the last commit
+#[derive(Default)]
+pub struct RamTarget<const N: usize> {
+    pub partition: RamPartition<N>,
+    pub committed: Option<(u32, u32)>,
+}
+
+impl <const N: usize> FirmwareTarget for RamTarget<N> {
+    type Partition = RamPartition<N>;
+
+    fn partition(&mut self) -> &mut Self::Partition {
+        &mut self.partition
+    }
+
+    fn commit(&mut self, size: u32, crc: u32) -> Result<(), CommandError> {
+        self.committed = Some((size, crc));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Command;
+
+    type RamTarget = super::RamTarget<1024>;
+
+    crate::actions!(A);
+    crate::props!(P);
+    crate::infos!(I);
+    crate::sections!(S);
+
+    fn send(dfu: &mut Dfu<RamTarget>, bytes: &[u8]) -> [u8; 64] {
+        let mut message = [0u8; 64];
+        message[..bytes.len()].copy_from_slice(bytes);
+        let response = match Command::<A, P, I, S>::from_bytes(&message) {
+            Ok(Command::Dfu(command)) => dfu.handle(command),
+            Ok(_) => panic!("not a dfu command"),
+            Err(e) => CommandResponse::error(e),
+        };
+        response.as_bytes().try_into().unwrap()
+    }
+
+    fn offset(response: [u8; 64]) -> u32 {
+        assert_eq!(1, response[0], "error response: {:?}", response[1]);
+        u32::from_le_bytes(response[1..5].try_into().unwrap())
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(0xCBF4_3926, Crc32::checksum(b"123456789"));
+    }
+
+    #[test]
+    fn dfu_transfer_with_resume() {
+        let image: [u8; 300] = core::array::from_fn(|i| (i * 7) as u8);
+        let crc = Crc32::checksum(&image);
+        let mut dfu = Dfu::new(RamTarget::default());
+
+        let begin = |dfu: &mut Dfu<RamTarget>| {
+            let mut bytes = [b'U', 0, 0, 0, 0, 0, 0, 0, 0];
+            bytes[1..5].copy_from_slice(&(image.len() as u32).to_le_bytes());
+            bytes[5..9].copy_from_slice(&crc.to_le_bytes());
+            offset(send(dfu, &bytes))
+        };
+        let write = |dfu: &mut Dfu<RamTarget>, at: u32| {
+            let chunk = &image[at as usize..(at as usize + CHUNK_SIZE).min(image.len())];
+            let mut bytes = [0u8; 64];
+            bytes[0] = b'D';
+            bytes[1..5].copy_from_slice(&at.to_le_bytes());
+            bytes[5] = chunk.len() as u8;
+            bytes[6..6 + chunk.len()].copy_from_slice(chunk);
+            send(dfu, &bytes)
+        };
+
+        assert_eq!(0, begin(&mut dfu));
+        let mut at = offset(write(&mut dfu, 0));
+        at = offset(write(&mut dfu, at));
+        // out of order writes are rejected
+        assert_eq!(0, write(&mut dfu, 0)[0]);
+        // verifying an incomplete image fails
+        assert_eq!(0, send(&mut dfu, b"V")[0]);
+
+        // beginning the same transfer again resumes it
+        assert_eq!(at, begin(&mut dfu));
+        while at < image.len() as u32 {
+            at = offset(write(&mut dfu, at));
+        }
+
+        assert_eq!(crc, offset(send(&mut dfu, b"V")));
+        assert_eq!(1, send(&mut dfu, b"C")[0]);
+        assert_eq!(Some((image.len() as u32, crc)), dfu.target().committed);
+        assert_eq!(&image, &dfu.target().partition.as_slice()[..image.len()]);
+    }
+
+    #[test]
+    fn dfu_rejects_corrupt_image() {
+        let image = [0x55u8; 100];
+        let mut dfu = Dfu::new(RamTarget::default());
+        assert_eq!(Ok(0), dfu.begin(image.len() as u32, Crc32::checksum(&image) ^ 1));
+        assert_eq!(Ok(56), dfu.write(0, &image[..56]));
+        assert_eq!(Ok(100), dfu.write(56, &image[56..]));
+        assert_eq!(Err(CommandError::InvalidData), dfu.verify());
+        assert_eq!(Err(CommandError::Failed), dfu.commit());
+        assert_eq!(Err(CommandError::InvalidData), dfu.begin(2048, 0));
+    }
+
+    /// A partition that is read in units that don't divide the verify buffer
+    #[derive(Default)]
+    struct CoarsePartition(RamPartition<1024>);
+
+    impl ErrorType for CoarsePartition {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for CoarsePartition {
+        const READ_SIZE: usize = 48;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            self.0.read(offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+    }
+
+    impl NorFlash for CoarsePartition {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 256;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.0.erase(from, to)
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.0.write(offset, bytes)
+        }
+    }
+
+    impl FirmwareTarget for CoarsePartition {
+        type Partition = Self;
+
+        fn partition(&mut self) -> &mut Self::Partition {
+            self
+        }
+
+        fn commit(&mut self, _size: u32, _crc: u32) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dfu_verify_with_coarse_reads() {
+        let image: [u8; 100] = core::array::from_fn(|i| i as u8);
+        let mut dfu = Dfu::new(CoarsePartition::default());
+        assert_eq!(Ok(0), dfu.begin(image.len() as u32, Crc32::checksum(&image)));
+        assert_eq!(Ok(56), dfu.write(0, &image[..56]));
+        assert_eq!(Ok(100), dfu.write(56, &image[56..]));
+        assert_eq!(Ok(Crc32::checksum(&image)), dfu.verify());
+    }
+}
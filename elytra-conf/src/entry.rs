@@ -3,7 +3,8 @@ use core::{ops::Range};
 use bitflags::bitflags;
 use elytra_bytepack::{Buf, pack};
 use crate::{
-    values::DefaultValue,
+    auth::AccessLevel,
+    values::{ColorFormat, DefaultValue},
     command::CommandResponse, 
     entry::options::{OptionValueProvider}, 
     config::MESSAGE_LENGTH
@@ -24,12 +25,17 @@ use super::{
 
 bitflags! {
     #[derive(Debug, Eq, PartialEq, Clone, Copy)]
-    pub struct ExtraFlags: u8 {
+    pub struct ExtraFlags: u16 {
         const ReadOnly = 1 << 0;
         const HasHelp = 1 << 1;
         const HasIcon = 1 << 2;
         const HasOptions = 1 << 3;
         const IsMulti = 1 << 4;
+        const IsRepeated = 1 << 5;
+        const HasConditions = 1 << 6;
+        const IsLongRunning = 1 << 7;
+        const RequiresUser = 1 << 8;
+        const RequiresAdmin = 1 << 9;
     }
 }
 
@@ -87,7 +93,10 @@ pub enum Constraints {
     None,
     Range(Range<i32>),
     Length(u64),
-    Values(ValueConstraints)
+    Values(ValueConstraints),
+    Color(ColorFormat),
+    /// Max size in bytes and whether the SHA-256 digest can be queried
+    Blob { max_size: u32, digest: bool },
 }
 
 #[cfg(feature = "defmt")]
@@ -98,6 +107,8 @@ impl defmt::Format for Constraints {
             Constraints::Range(range) => defmt::write!(fmt, "RangeConstraint({}, {})", range.start, range.end),
             Constraints::Length(len) => defmt::write!(fmt, "LengthConstraints({})", len),
             Constraints::Values(ovp) => defmt::write!(fmt, "ValuesConstraints({}, {}, {})", ovp.value_provider.len(), ovp.min, ovp.max_or_suggested),
+            Constraints::Color(format) => defmt::write!(fmt, "ColorConstraints({})", format),
+            Constraints::Blob { max_size, digest } => defmt::write!(fmt, "BlobConstraints({}, {})", max_size, digest),
         }
     }
 }
@@ -121,6 +132,13 @@ impl Constraints {
                     constr.max_or_suggested.to_le_bytes()
                 )
             },
+            Constraints::Color(format) => [*format as u8, 0, 0, 0, 0, 0, 0, 0],
+            Constraints::Blob { max_size, digest } => {
+                pack!(
+                    max_size.to_le_bytes(),
+                    [*digest as u8, 0, 0, 0]
+                )
+            },
         }
     }
 
@@ -157,6 +175,25 @@ pub struct EntryDesc {
     pub icon: Option<&'static str>,
     pub default: DefaultValue,
     pub multi: bool,
+    pub signature: ActionSignature,
+    /// Access level needed to write the entry or run the action
+    pub access: AccessLevel,
+}
+
+/// Typed parameters and result of an action
+#[derive(Debug)]
+pub struct ActionSignature {
+    pub params: &'static [EntryDesc],
+    pub result: Option<&'static EntryDesc>,
+    /// The action responds with a job id instead of completing immediately, see [`Jobs`](crate::job::Jobs)
+    pub long_running: bool,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ActionSignature {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "ActionSignature({}, {}, {})", self.params.len(), self.result.is_some(), self.long_running)
+    }
 }
 
 impl EntryDesc {
@@ -179,14 +216,33 @@ impl EntryDesc {
                 (ValueType::Text, DefaultValue::Text(_)) => {},
                 (ValueType::Secret, DefaultValue::Text(_)) => {},
                 (ValueType::Options, DefaultValue::Options(_)) => {},
+                (ValueType::Options, DefaultValue::Enum(_)) => {},
                 (ValueType::Toggle, DefaultValue::Enabled(_)) => {},
+                (ValueType::Ipv4, DefaultValue::Ipv4(_)) => {},
+                (ValueType::Ipv6, DefaultValue::Ipv6(_)) => {},
+                (ValueType::Mac, DefaultValue::Mac(_)) => {},
+                (ValueType::Port, DefaultValue::Port(_)) => {},
+                (ValueType::Time, DefaultValue::Time(_)) => {},
+                (ValueType::Date, DefaultValue::Date(_)) => {},
+                (ValueType::Timestamp, DefaultValue::Timestamp(_)) => {},
+                (ValueType::Duration, DefaultValue::Duration(_)) => {},
+                (ValueType::Color, DefaultValue::Color(_)) => {},
                 (_, DefaultValue::Empty) => {},
                 (ValueType::Status, _) => panic!("Status value type cannot have a default value"),
                 (_, DefaultValue::Integer(_)) => panic!("Integer is not a valid default value for this field"),
                 (_, DefaultValue::Bytes(_)) => panic!("Bytes is not a valid default value for this field"),
                 (_, DefaultValue::Text(_)) => panic!("Text is not a valid default value for this field"),
-                (_, DefaultValue::Options(_)) => panic!("Options is not a valid default value for this field"),
+                (_, DefaultValue::Options(_) | DefaultValue::Enum(_)) => panic!("Options is not a valid default value for this field"),
                 (_, DefaultValue::Enabled(_)) => panic!("Enabled is not a valid default value for this field"),
+                (_, DefaultValue::Ipv4(_)) => panic!("Ipv4 is not a valid default value for this field"),
+                (_, DefaultValue::Ipv6(_)) => panic!("Ipv6 is not a valid default value for this field"),
+                (_, DefaultValue::Mac(_)) => panic!("Mac is not a valid default value for this field"),
+                (_, DefaultValue::Port(_)) => panic!("Port is not a valid default value for this field"),
+                (_, DefaultValue::Time(_)) => panic!("Time is not a valid default value for this field"),
+                (_, DefaultValue::Date(_)) => panic!("Date is not a valid default value for this field"),
+                (_, DefaultValue::Timestamp(_)) => panic!("Timestamp is not a valid default value for this field"),
+                (_, DefaultValue::Duration(_)) => panic!("Duration is not a valid default value for this field"),
+                (_, DefaultValue::Color(_)) => panic!("Color is not a valid default value for this field"),
             }
         }
         match (&constraints, &default) {
@@ -203,6 +259,23 @@ impl EntryDesc {
             icon,
             default,
             multi,
+            signature: ActionSignature { params: &[], result: None, long_running: false },
+            access: AccessLevel::Public,
+        }
+    }
+
+    pub const fn with_access(self, access: AccessLevel) -> Self {
+        Self {
+            access,
+            ..self
+        }
+    }
+
+    pub const fn with_signature(self, signature: ActionSignature) -> Self {
+        if signature.params.len() > Self::MAX_ACTION_PARAMS { panic!("too many action parameters") }
+        Self {
+            signature,
+            ..self
         }
     }
 
@@ -213,11 +286,33 @@ impl EntryDesc {
         flags.set(ExtraFlags::HasIcon, self.icon.is_some());
         flags.set(ExtraFlags::HasOptions, self.constraints.is_values());
         flags.set(ExtraFlags::IsMulti, self.multi);
+        flags.set(ExtraFlags::IsRepeated, self.repeat_count().is_some());
+        flags.set(ExtraFlags::IsLongRunning, self.signature.long_running);
+        flags.set(ExtraFlags::RequiresUser, self.access == AccessLevel::User);
+        flags.set(ExtraFlags::RequiresAdmin, self.access == AccessLevel::Admin);
         flags
     }
 
+    /// The max number of instances if this entry is a repeated section
+    pub const fn repeat_count(&self) -> Option<u8> {
+        match (&self.variant, &self.constraints) {
+            (EntryVariant::Section, Constraints::Length(count)) => Some(*count as u8),
+            _ => None
+        }
+    }
+
+    /// Actions use the constraint bytes for their signature: parameter count and whether it has a result
+    fn constraint_bits(&self) -> [u8; 8] {
+        match self.variant {
+            EntryVariant::Action(_) => [self.signature.params.len() as u8, self.signature.result.is_some() as u8, 0, 0, 0, 0, 0, 0],
+            _ => self.constraints.bits(),
+        }
+    }
+
+    const MAX_ACTION_PARAMS: usize = 16;
+
     const MAX_ENTRY_NAME_LEN: usize = MESSAGE_LENGTH - (
-        1 // flags
+        2 // flags
         +
         1 // variant
         +
@@ -225,14 +320,20 @@ impl EntryDesc {
     );
 }
 
-impl From<&EntryDesc> for CommandResponse {
-    fn from(value: &EntryDesc) -> Self {
+impl EntryDesc {
+    pub fn response_with_flags(&self, flags: ExtraFlags) -> CommandResponse {
         let mut res = CommandResponse::new();
-        res.push(value.flags().bits()); // readonly (1 byte)
-        res.push(value.variant.bits());  // type (1 byte)
-        res.extend(value.constraints.bits()); // 8 byte
+        res.extend(flags.bits().to_le_bytes()); // flags (2 bytes)
+        res.push(self.variant.bits());  // type (1 byte)
+        res.extend(self.constraint_bits()); // 8 byte
         // use the rest of the message buffer for field name
-        res.extend(value.name.bytes());
+        res.extend(self.name.bytes());
         res
     }
+}
+
+impl From<&EntryDesc> for CommandResponse {
+    fn from(value: &EntryDesc) -> Self {
+        value.response_with_flags(value.flags())
+    }
 }
\ No newline at end of file
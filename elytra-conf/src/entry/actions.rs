@@ -1,4 +1,4 @@
-use crate::{entry::{Constraints, EntryDesc, EntryVariant}, values::DefaultValue};
+use crate::{auth::AccessLevel, entry::{ActionSignature, Constraints, EntryDesc, EntryVariant}, values::DefaultValue};
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -23,6 +23,10 @@ pub struct ActionEntry {
     pub variant: ActionVariant,
     pub help: Option<&'static str>,
     pub icon: Option<&'static str>,
+    pub params: &'static [EntryDesc],
+    pub result: Option<&'static EntryDesc>,
+    pub long_running: bool,
+    pub access: AccessLevel,
 }
 
 #[allow(unused)]
@@ -31,7 +35,11 @@ pub const fn action(name: &'static str) -> ActionEntry {
         name,
         variant: ActionVariant::Normal,
         help: None,
-        icon: None
+        icon: None,
+        params: &[],
+        result: None,
+        long_running: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -47,7 +55,39 @@ impl ActionEntry {
             self.icon,
             DefaultValue::Empty,
             false,
-        )
+        ).with_signature(ActionSignature {
+            params: self.params,
+            result: self.result,
+            long_running: self.long_running,
+        }).with_access(self.access)
+    }
+    /// Typed parameters of the action, see [`FieldEntry::as_param`](crate::entry::FieldEntry::as_param)
+    pub const fn with_params(self, params: &'static [EntryDesc]) -> Self {
+        Self {
+            params,
+            ..self
+        }
+    }
+    /// Marks the action as long-running: it responds with a job id that the host polls for progress
+    pub const fn long_running(self) -> Self {
+        Self {
+            long_running: true,
+            ..self
+        }
+    }
+    /// Requires a host to be logged in at `access` to run the action, see [`Auth`](crate::auth::Auth)
+    pub const fn with_access(self, access: AccessLevel) -> Self {
+        Self {
+            access,
+            ..self
+        }
+    }
+    /// Typed result of the action, see [`FieldEntry::as_result`](crate::entry::FieldEntry::as_result)
+    pub const fn with_result(self, result: &'static EntryDesc) -> Self {
+        Self {
+            result: Some(result),
+            ..self
+        }
     }
     pub const fn with_icon(self, icon: &'static str) -> Self {
         Self {
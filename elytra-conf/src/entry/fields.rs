@@ -1,25 +1,41 @@
-use core::ops::Range;
+use core::{net::{Ipv4Addr, Ipv6Addr}, ops::Range, time::Duration};
 
 use crate::{
-    traits::{PropIndex, InfoIndex},
+    auth::AccessLevel,
+    traits::{ActionIndex, PropIndex, InfoIndex, SectionIndex},
     entry::{Constraints, EntryDesc, EntryVariant, ValueConstraints}, 
-    prelude::OptionValueProvider, 
+    prelude::{OptionEnum, OptionValueProvider}, 
     config::EntryType, 
-    values::{DefaultValue, ValueType}
+    values::{Color, ColorFormat, Date, DefaultValue, TimeOfDay, ValueType}
 };
 
+/// Layout type byte for headings, the text is read with a `Heading` query
+pub const LAYOUT_HEADING: u8 = b't';
+/// Layout type byte for separators
+pub const LAYOUT_SEPARATOR: u8 = b'-';
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Field<PI: PropIndex, II: InfoIndex> {
+pub enum Field<PI: PropIndex, II: InfoIndex, SI: SectionIndex, AI: ActionIndex> {
     Prop(PI),
-    Info(II)
+    Info(II),
+    /// A nested section, shown as a child of the section it is placed in
+    Section(SI),
+    /// An action placed inline next to the fields it affects
+    Action(AI),
+    Heading(&'static str),
+    Separator,
 }
 
-impl <PI: PropIndex, II: InfoIndex> Field<PI, II> {
+impl <PI: PropIndex, II: InfoIndex, SI: SectionIndex, AI: ActionIndex> Field<PI, II, SI, AI> {
     pub fn bits(&self) -> [u8; 2] {
         match self {
             Self::Prop(pi) => [EntryType::Prop as u8, pi.as_index() as u8],
             Self::Info(ii) => [EntryType::Info as u8, ii.as_index() as u8],
+            Self::Section(si) => [EntryType::Section as u8, si.as_index() as u8],
+            Self::Action(ai) => [EntryType::Action as u8, ai.as_index() as u8],
+            Self::Heading(_) => [LAYOUT_HEADING, 0],
+            Self::Separator => [LAYOUT_SEPARATOR, 0],
         }
     }
 }
@@ -36,6 +52,7 @@ pub struct FieldEntry {
     pub icon: Option<&'static str>,
     pub default: DefaultValue,
     pub multi: bool,
+    pub access: AccessLevel,
 }
 
 #[allow(unused)]
@@ -53,7 +70,15 @@ impl FieldEntry {
             self.icon,
             self.default,
             self.multi,
-        )
+        ).with_access(self.access)
+    }
+    /// Converts the field to a writable action parameter
+    pub const fn as_param(self) -> EntryDesc {
+        Self { readonly: Some(false), ..self }.as_entry()
+    }
+    /// Converts the field to a read only action result
+    pub const fn as_result(self) -> EntryDesc {
+        Self { readonly: Some(true), ..self }.as_entry()
     }
     pub const fn with_icon(self, icon: &'static str) -> Self {
         Self {
@@ -79,6 +104,13 @@ impl FieldEntry {
             ..self
         }
     }
+    /// Requires a host to be logged in at `access` to write the field, see [`Auth`](crate::auth::Auth)
+    pub const fn with_access(self, access: AccessLevel) -> Self {
+        Self {
+            access,
+            ..self
+        }
+    }
     pub const fn writable(self) -> Self {
         Self {
             readonly: Some(false),
@@ -139,6 +171,14 @@ impl FieldEntry {
         }
     }
 
+    /// Typed version of [`Self::with_default_options`] for fields backed by an [`OptionEnum`]
+    pub const fn with_default_enum<T: OptionEnum, const N: usize>(self, value: &'static [T; N]) -> Self {
+        Self {
+            default: DefaultValue::Enum(value),
+            ..self
+        }
+    }
+
     pub const fn with_default_enabled(self, value: bool) -> Self {
         Self {
             default: DefaultValue::Enabled(value),
@@ -146,6 +186,101 @@ impl FieldEntry {
         }
     }
 
+    pub const fn with_default_ipv4(self, value: Ipv4Addr) -> Self {
+        Self {
+            default: DefaultValue::Ipv4(value.octets()),
+            ..self
+        }
+    }
+
+    pub const fn with_default_ipv6(self, value: Ipv6Addr) -> Self {
+        Self {
+            default: DefaultValue::Ipv6(value.octets()),
+            ..self
+        }
+    }
+
+    pub const fn with_default_mac(self, value: [u8; 6]) -> Self {
+        Self {
+            default: DefaultValue::Mac(value),
+            ..self
+        }
+    }
+
+    pub const fn with_default_port(self, value: u16) -> Self {
+        Self {
+            default: DefaultValue::Port(value),
+            ..self
+        }
+    }
+
+    pub const fn with_default_time(self, value: TimeOfDay) -> Self {
+        Self {
+            default: DefaultValue::Time(value),
+            ..self
+        }
+    }
+
+    pub const fn with_default_date(self, value: Date) -> Self {
+        Self {
+            default: DefaultValue::Date(value),
+            ..self
+        }
+    }
+
+    pub const fn with_default_timestamp(self, value: i64) -> Self {
+        Self {
+            default: DefaultValue::Timestamp(value),
+            ..self
+        }
+    }
+
+    pub const fn with_default_duration(self, value: Duration) -> Self {
+        Self {
+            default: DefaultValue::Duration(value.as_secs() as u32),
+            ..self
+        }
+    }
+
+    pub const fn with_default_color(self, value: Color) -> Self {
+        Self {
+            default: DefaultValue::Color(value),
+            ..self
+        }
+    }
+
+    /// Adds an alpha channel to a colour field
+    pub const fn with_alpha(self) -> Self {
+        self.with_color_format(ColorFormat::Rgba)
+    }
+
+    /// Adds a white channel to a colour field
+    pub const fn with_white(self) -> Self {
+        self.with_color_format(ColorFormat::Rgbw)
+    }
+
+    const fn with_color_format(self, format: ColorFormat) -> Self {
+        let Constraints::Color(_) = self.constraints else {
+            panic!("field is not a color field");
+        };
+        Self {
+            constraints: Constraints::Color(format),
+            ..self
+        }
+    }
+
+    /// Allows querying the SHA-256 digest of a blob field
+    #[cfg(feature = "digest")]
+    pub const fn with_digest(self) -> Self {
+        let Constraints::Blob { max_size, .. } = self.constraints else {
+            panic!("field is not a blob field");
+        };
+        Self {
+            constraints: Constraints::Blob { max_size, digest: true },
+            ..self
+        }
+    }
+
     pub const fn with_max_options(self, max: u16) -> Self {
         let Constraints::Values(values) = self.constraints else {
             panic!("field does not have values constraint");
@@ -174,6 +309,7 @@ pub const fn bytes(name: &'static str, size: u8) -> FieldEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -188,6 +324,7 @@ pub const fn secret(name: &'static str) -> PropEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -202,6 +339,7 @@ pub const fn status(name: &'static str) -> InfoEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -216,6 +354,7 @@ pub const fn integer(name: &'static str) -> InfoEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -230,6 +369,7 @@ pub const fn toggle(name: &'static str) -> PropEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -247,9 +387,166 @@ pub const fn option(name: &'static str, value_provider: &'static dyn OptionValue
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn ipv4(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Ipv4,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn ipv6(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Ipv6,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
     }
 }
 
+#[allow(unused)]
+pub const fn mac(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Mac,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn port(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Port,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn time(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Time,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn date(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Date,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn timestamp(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Timestamp,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn duration(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Duration,
+        constraints: Constraints::None,
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn color(name: &'static str) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Color,
+        constraints: Constraints::Color(ColorFormat::Rgb),
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+/// A large value of up to `max_size` bytes, like a certificate or a calibration table
+#[allow(unused)]
+pub const fn blob(name: &'static str, max_size: u32) -> PropEntry {
+    PropEntry {
+        name,
+        value_type: ValueType::Blob,
+        constraints: Constraints::Blob { max_size, digest: false },
+        readonly: Some(false),
+        help: None,
+        icon: None,
+        default: DefaultValue::Empty,
+        multi: false,
+        access: AccessLevel::Public,
+    }
+}
+
+#[allow(unused)]
+pub const fn option_enum<T: OptionEnum>(name: &'static str) -> PropEntry {
+    option(name, T::PROVIDER)
+}
+
 pub type InfoEntry = FieldEntry;
 #[allow(unused)]
 pub const fn info(name: &'static str) -> InfoEntry {
@@ -262,6 +559,7 @@ pub const fn info(name: &'static str) -> InfoEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
 
@@ -277,5 +575,6 @@ pub const fn prop(name: &'static str) -> PropEntry {
         icon: None,
         default: DefaultValue::Empty,
         multi: false,
+        access: AccessLevel::Public,
     }
 }
\ No newline at end of file
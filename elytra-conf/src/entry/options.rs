@@ -6,6 +6,30 @@ pub trait OptionValueProvider: Debug + Send + Sync {
     fn len(&self) -> usize;
 }
 
+/// A Rust enum that backs the values of an option field, usually implemented using
+/// `#[derive(OptionEnum)]` from `elytra-macros`.
+pub trait OptionEnum: OptionValueProvider + Copy + 'static {
+    /// The provider of option names used for the field constraints
+    const PROVIDER: &'static dyn OptionValueProvider;
+
+    fn from_option(index: u16) -> Option<Self>;
+    fn to_option(self) -> u16;
+}
+
+/// Typed defaults of an option field, converted to option indices when the default is read
+pub trait OptionDefaults: Debug + Send + Sync {
+    /// Writes the option indices to `buf`, returning how many were written
+    fn write_indices(&self, buf: &mut [u16]) -> usize;
+}
+
+impl <T: OptionEnum, const N: usize> OptionDefaults for [T; N] {
+    fn write_indices(&self, buf: &mut [u16]) -> usize {
+        buf.iter_mut().zip(self.iter().map(|v| v.to_option()))
+            .map(|(slot, index)| *slot = index)
+            .count()
+    }
+}
+
 impl OptionValueProvider for [&'static str] {
     fn get(&self, index: usize) -> Option<&'static str> {
         if index < self.len() {
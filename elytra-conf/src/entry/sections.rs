@@ -1,4 +1,4 @@
-use crate::{entry::{Constraints, EntryDesc, EntryVariant}, values::DefaultValue};
+use crate::{auth::AccessLevel, entry::{Constraints, EntryDesc, EntryVariant}, group::GroupInstances, values::DefaultValue};
 
 
 #[derive(Debug, Eq, PartialEq)]
@@ -7,21 +7,29 @@ pub struct SectionEntry {
     pub name: &'static str,
     pub icon: Option<&'static str>,
     pub help: Option<&'static str>,
+    /// The max number of instances for repeated sections (groups)
+    pub repeat: Option<u8>,
+    /// Access level needed to add or remove instances and write their props
+    pub access: AccessLevel,
 }
 
 #[allow(unused)]
 impl SectionEntry {
     pub const fn as_entry(self) -> EntryDesc {
+        let constraints = match self.repeat {
+            Some(count) => Constraints::Length(count as u64),
+            None => Constraints::None,
+        };
         EntryDesc::new(
             self.name, 
             EntryVariant::Section, 
             true,
-            Constraints::None,
+            constraints,
             self.help, 
             self.icon,
             DefaultValue::Empty,
             false
-        )
+        ).with_access(self.access)
     }
     pub const fn with_icon(self, icon: &'static str) -> Self {
         Self {
@@ -35,9 +43,25 @@ impl SectionEntry {
             ..self
         }
     }
+    pub const fn with_access(self, access: AccessLevel) -> Self {
+        Self {
+            access,
+            ..self
+        }
+    }
+    /// Makes the section a group of props that can be instantiated up to `count` times
+    pub const fn repeated(self, count: u8) -> Self {
+        if count == 0 || count as u32 > GroupInstances::MAX_INSTANCES { 
+            panic!("repeated sections must have between 1 and 64 instances") 
+        }
+        Self {
+            repeat: Some(count),
+            ..self
+        }
+    }
 }
 
 #[allow(unused)]
 pub const fn section(name: &'static str) -> SectionEntry {
-    SectionEntry { name, icon: None, help: None }
+    SectionEntry { name, icon: None, help: None, repeat: None, access: AccessLevel::Public }
 }
\ No newline at end of file
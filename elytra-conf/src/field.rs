@@ -1,10 +1,10 @@
-use core::{prelude::rust_2024::*};
+use core::{net::{Ipv4Addr, Ipv6Addr}, prelude::rust_2024::*, time::Duration};
 #[cfg(feature = "alloc")]
 use alloc::borrow::ToOwned;
 use log::warn;
 use elytra_bytepack::Cursor;
 use crate::{
-    command::CommandResponse, config::MESSAGE_LENGTH, entry::{Constraints, EntryDesc, EntryVariant}, values::{DefaultValue, ValueType}
+    command::CommandResponse, config::MESSAGE_LENGTH, entry::{Constraints, EntryDesc, EntryVariant, options::OptionEnum}, values::{Color, ColorFormat, Date, DefaultValue, TimeOfDay, ValueType}
 };
 
 pub struct Options {
@@ -12,12 +12,12 @@ pub struct Options {
     len: u8
 }
 impl Options {
-    fn as_slice(&self) -> &[u16] {
+    pub fn as_slice(&self) -> &[u16] {
         &self.buf[0..self.len as usize]
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FieldValue {
     desc: &'static EntryDesc,
     data: [u8; MESSAGE_LENGTH],
@@ -40,6 +40,11 @@ impl FieldValue{
         self.data[0] = len as u8
     }
 
+    /// The default value of the entry, as read from an empty store
+    pub fn default_value(desc: &'static EntryDesc) -> Self {
+        Self::from_store(desc, [0u8; MESSAGE_LENGTH])
+    }
+
     pub fn from_store(desc: &'static EntryDesc, bytes: [u8; MESSAGE_LENGTH]) -> Self {
         let mut fv = Self {
             desc,
@@ -47,6 +52,7 @@ impl FieldValue{
         };
         if fv.is_empty() {
             use DefaultValue::{*};
+            let color_format = fv.color_format();
             let mut cursor = Cursor::new(fv.data.as_mut_slice());
             match desc.default {
                 Bytes(bytes) => {
@@ -74,6 +80,51 @@ impl FieldValue{
                         cursor.write(&items[i].to_le_bytes()).unwrap();
                     }
                 },
+                Enum(defaults) => {
+                    let mut indices = [0u16; 31];
+                    let len = defaults.write_indices(&mut indices);
+                    cursor.write(&[len as u8]).unwrap();
+                    for index in &indices[..len] {
+                        cursor.write(&index.to_le_bytes()).unwrap();
+                    }
+                },
+                Ipv4(octets) => {
+                    cursor.write(&[4]).unwrap();
+                    cursor.write(&octets).unwrap();
+                },
+                Ipv6(octets) => {
+                    cursor.write(&[16]).unwrap();
+                    cursor.write(&octets).unwrap();
+                },
+                Mac(octets) => {
+                    cursor.write(&[6]).unwrap();
+                    cursor.write(&octets).unwrap();
+                },
+                Port(port) => {
+                    cursor.write(&[2]).unwrap();
+                    cursor.write(&port.to_le_bytes()).unwrap();
+                },
+                Time(time) => {
+                    cursor.write(&[4]).unwrap();
+                    cursor.write(&time.as_seconds().to_le_bytes()).unwrap();
+                },
+                Date(date) => {
+                    cursor.write(&[4]).unwrap();
+                    cursor.write(&date.to_bytes()).unwrap();
+                },
+                Timestamp(timestamp) => {
+                    cursor.write(&[8]).unwrap();
+                    cursor.write(&timestamp.to_le_bytes()).unwrap();
+                },
+                Duration(seconds) => {
+                    cursor.write(&[4]).unwrap();
+                    cursor.write(&seconds.to_le_bytes()).unwrap();
+                },
+                Color(color) => {
+                    let channels = color_format.channels();
+                    cursor.write(&[channels as u8]).unwrap();
+                    cursor.write(&color.to_bytes()[..channels]).unwrap();
+                },
             }
         }
         fv
@@ -178,11 +229,21 @@ impl FieldValue{
         self.set_len(size);
     }
 
+    /// Returns the first selected option of a field backed by an [`OptionEnum`]
+    pub fn get_enum<T: OptionEnum>(&self) -> Option<T> {
+        self.get_options().as_slice().first().and_then(|index| T::from_option(*index))
+    }
+
+    pub fn set_enum<T: OptionEnum>(&mut self, value: T) {
+        self.set_options(&[value.to_option()]);
+    }
+
     pub fn get_text(&self) -> &str {
         use core::str;
 
-        let end = self.data.iter().position(|&b| b == 0).unwrap_or(self.data.len());
-        str::from_utf8(&self.data[1..end]).unwrap_or_default()
+        let text = &self.data[1..];
+        let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+        str::from_utf8(&text[..end]).unwrap_or_default()
     }
 
     pub fn set_status(&mut self, code: u8, text: &str) {
@@ -237,6 +298,121 @@ impl FieldValue{
         self.set_len(1);
     }
 
+    pub fn get_ipv4(&self) -> Ipv4Addr {
+        let octets: [u8; 4] = self.data[1..=4].try_into().unwrap();
+        Ipv4Addr::from(octets)
+    }
+
+    pub fn set_ipv4(&mut self, value: Ipv4Addr) {
+        self.data[1..=4].copy_from_slice(&value.octets());
+        self.set_len(4);
+    }
+
+    pub fn get_ipv6(&self) -> Ipv6Addr {
+        let octets: [u8; 16] = self.data[1..=16].try_into().unwrap();
+        Ipv6Addr::from(octets)
+    }
+
+    pub fn set_ipv6(&mut self, value: Ipv6Addr) {
+        self.data[1..=16].copy_from_slice(&value.octets());
+        self.set_len(16);
+    }
+
+    pub fn get_mac(&self) -> [u8; 6] {
+        self.data[1..=6].try_into().unwrap()
+    }
+
+    pub fn set_mac(&mut self, value: [u8; 6]) {
+        self.data[1..=6].copy_from_slice(&value);
+        self.set_len(6);
+    }
+
+    pub fn get_port(&self) -> u16 {
+        u16::from_le_bytes([self.data[1], self.data[2]])
+    }
+
+    pub fn set_port(&mut self, value: u16) {
+        let value = if let Constraints::Range(range) = &self.desc.constraints {
+            value.clamp(range.start.max(0) as u16, range.end.min(u16::MAX as i32) as u16)
+        } else {
+            value
+        };
+        self.data[1..=2].copy_from_slice(&value.to_le_bytes());
+        self.set_len(2);
+    }
+
+    pub fn get_time(&self) -> TimeOfDay {
+        TimeOfDay::from_seconds(u32::from_le_bytes(self.data[1..=4].try_into().unwrap()))
+    }
+
+    pub fn set_time(&mut self, value: TimeOfDay) {
+        let seconds = self.range_clamped(value.as_seconds() as i64) as u32;
+        self.data[1..=4].copy_from_slice(&seconds.to_le_bytes());
+        self.set_len(4);
+    }
+
+    pub fn get_date(&self) -> Date {
+        Date::from_bytes(self.data[1..=4].try_into().unwrap())
+    }
+
+    pub fn set_date(&mut self, value: Date) {
+        if !value.is_valid() {
+            warn!("ignoring invalid date {}-{}-{}", value.year, value.month, value.day);
+            return
+        }
+        self.data[1..=4].copy_from_slice(&value.to_bytes());
+        self.set_len(4);
+    }
+
+    /// Returns the timestamp as seconds since the Unix epoch
+    pub fn get_timestamp(&self) -> i64 {
+        i64::from_le_bytes(self.data[1..=8].try_into().unwrap())
+    }
+
+    pub fn set_timestamp(&mut self, value: i64) {
+        let value = self.range_clamped(value);
+        self.data[1..=8].copy_from_slice(&value.to_le_bytes());
+        self.set_len(8);
+    }
+
+    pub fn get_duration(&self) -> Duration {
+        Duration::from_secs(u32::from_le_bytes(self.data[1..=4].try_into().unwrap()) as u64)
+    }
+
+    pub fn set_duration(&mut self, value: Duration) {
+        let seconds = self.range_clamped(value.as_secs().min(u32::MAX as u64) as i64) as u32;
+        self.data[1..=4].copy_from_slice(&seconds.to_le_bytes());
+        self.set_len(4);
+    }
+
+    pub fn get_color(&self) -> Color {
+        Color::from_bytes(self.data[1..=4].try_into().unwrap())
+    }
+
+    pub fn set_color(&mut self, value: Color) {
+        let channels = self.color_format().channels();
+        self.data[1..=4].copy_from_slice(&value.to_bytes());
+        if channels < 4 {
+            self.data[4] = 0;
+        }
+        self.set_len(channels);
+    }
+
+    fn color_format(&self) -> ColorFormat {
+        match self.desc.constraints {
+            Constraints::Color(format) => format,
+            _ => ColorFormat::Rgb,
+        }
+    }
+
+    fn range_clamped(&self, value: i64) -> i64 {
+        if let Constraints::Range(range) = &self.desc.constraints {
+            value.clamp(range.start as i64, range.end as i64)
+        } else {
+            value
+        }
+    }
+
     pub fn clamp(&mut self) {
         match self.desc.variant {
             EntryVariant::Field(field_type) => match field_type {
@@ -253,10 +429,20 @@ impl FieldValue{
                 },
                 ValueType::Status => {},
                 ValueType::Bytes => {},
+                ValueType::Blob => {},
                 ValueType::Toggle => {},
                 ValueType::Options => {
                     self.set_options(self.get_options().as_slice());
-                }
+                },
+                ValueType::Ipv4 => self.set_ipv4(self.get_ipv4()),
+                ValueType::Ipv6 => self.set_ipv6(self.get_ipv6()),
+                ValueType::Mac => self.set_mac(self.get_mac()),
+                ValueType::Port => self.set_port(self.get_port()),
+                ValueType::Time => self.set_time(self.get_time()),
+                ValueType::Date => self.set_date(self.get_date()),
+                ValueType::Timestamp => self.set_timestamp(self.get_timestamp()),
+                ValueType::Duration => self.set_duration(self.get_duration()),
+                ValueType::Color => self.set_color(self.get_color()),
             },
             _ => {
                 warn!("tried to clamp entity variant {:?}", self.desc.variant)
@@ -279,6 +465,8 @@ impl From<FieldValue> for CommandResponse {
 #[cfg(test)]
 mod test {
 
+    use core::net::{Ipv4Addr, Ipv6Addr};
+    use crate::values::{Color, Date, TimeOfDay};
     use crate::entry::{EntryDesc, integer};
     use crate::prelude::*;
 
@@ -290,6 +478,44 @@ mod test {
         .with_default_options(&OPT1_DEFAULT)
         .with_max_options(3)
         .as_entry();
+    const DESC_IPV4VAL1: EntryDesc = ipv4("ipval")
+        .with_default_ipv4(Ipv4Addr::new(192, 168, 0, 1))
+        .as_entry();
+    const DESC_IPV6VAL1: EntryDesc = ipv6("ip6val")
+        .with_default_ipv6(Ipv6Addr::UNSPECIFIED)
+        .as_entry();
+    const DESC_PORTVAL1: EntryDesc = port("portval")
+        .with_range(1024..49151)
+        .as_entry();
+    const DESC_TIMEVAL1: EntryDesc = time("timeval")
+        .with_range(6 * 3600..22 * 3600)
+        .with_default_time(TimeOfDay::new(7, 30, 0))
+        .as_entry();
+    const DESC_DATEVAL1: EntryDesc = date("dateval").as_entry();
+    const DESC_RGBVAL1: EntryDesc = color("rgbval")
+        .with_default_color(Color::rgb(0xff, 0x80, 0x00))
+        .as_entry();
+    const DESC_RGBWVAL1: EntryDesc = color("rgbwval").with_white().as_entry();
+
+    #[repr(u16)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Security { Open, Wpa2, Wpa3 }
+    impl OptionValueProvider for Security {
+        fn get(&self, index: usize) -> Option<&'static str> {
+            OptionValueProvider::get(&["Open", "WPA2", "WPA3"], index)
+        }
+        fn len(&self) -> usize { 3 }
+    }
+    impl OptionEnum for Security {
+        const PROVIDER: &'static dyn OptionValueProvider = &Self::Open;
+        fn from_option(index: u16) -> Option<Self> {
+            [Self::Open, Self::Wpa2, Self::Wpa3].get(index as usize).copied()
+        }
+        fn to_option(self) -> u16 { self as u16 }
+    }
+    const DESC_ENUMVAL1: EntryDesc = option_enum::<Security>("enumval")
+        .with_default_enum(&[Security::Wpa2])
+        .as_entry();
 
     #[test]
     fn field_value_str_roundtrip() {
@@ -323,4 +549,91 @@ mod test {
         assert_eq!(3, fv.len());
         assert_eq!(&[1, 2, 0], fv.get_options().as_slice());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn field_value_network_roundtrip() {
+        let fv = FieldValue::from_store(&DESC_IPV4VAL1, [0u8; 64]);
+        assert_eq!(Ipv4Addr::new(192, 168, 0, 1), fv.get_ipv4());
+
+        let mut fv = FieldValue::new(&DESC_IPV4VAL1);
+        fv.set_ipv4(Ipv4Addr::new(10, 0, 0, 254));
+        let fv = FieldValue::from_store(&DESC_IPV4VAL1, fv.into_store_bytes());
+        assert_eq!(4, fv.len());
+        assert_eq!(Ipv4Addr::new(10, 0, 0, 254), fv.get_ipv4());
+
+        let mut fv = FieldValue::new(&DESC_PORTVAL1);
+        fv.set_port(80);
+        assert_eq!(1024, fv.get_port());
+        let fv = FieldValue::from_message(&DESC_PORTVAL1, &[0x50, 0x1f, 0, 0, 0]);
+        assert_eq!(2, fv.len());
+        assert_eq!(8016, fv.get_port());
+
+        let mut fv = FieldValue::new(&DESC_IPV6VAL1);
+        fv.set_ipv6(Ipv6Addr::LOCALHOST);
+        let fv = FieldValue::from_store(&DESC_IPV6VAL1, fv.into_store_bytes());
+        assert_eq!(16, fv.len());
+        assert_eq!(Ipv6Addr::LOCALHOST, fv.get_ipv6());
+    }
+
+    #[test]
+    fn field_value_time_roundtrip() {
+        let fv = FieldValue::from_store(&DESC_TIMEVAL1, [0u8; 64]);
+        assert_eq!(TimeOfDay::new(7, 30, 0), fv.get_time());
+
+        let mut fv = FieldValue::new(&DESC_TIMEVAL1);
+        fv.set_time(TimeOfDay::new(23, 15, 0));
+        let fv = FieldValue::from_store(&DESC_TIMEVAL1, fv.into_store_bytes());
+        assert_eq!(4, fv.len());
+        assert_eq!(TimeOfDay::new(22, 0, 0), fv.get_time());
+
+        let mut fv = FieldValue::new(&DESC_DATEVAL1);
+        fv.set_date(Date::new(2024, 2, 29));
+        let fv = FieldValue::from_message(&DESC_DATEVAL1, &fv.into_store_bytes()[1..]);
+        assert_eq!(4, fv.len());
+        assert_eq!(Date::new(2024, 2, 29), fv.get_date());
+    }
+
+    #[test]
+    fn date_validity() {
+        assert!(Date { year: 2024, month: 2, day: 29 }.is_valid());
+        assert!(Date { year: 2000, month: 2, day: 29 }.is_valid());
+        assert!(!Date { year: 2023, month: 2, day: 29 }.is_valid());
+        assert!(!Date { year: 1900, month: 2, day: 29 }.is_valid());
+        assert!(!Date { year: 2024, month: 2, day: 30 }.is_valid());
+        assert!(!Date { year: 2023, month: 4, day: 31 }.is_valid());
+        assert!(Date { year: 2023, month: 12, day: 31 }.is_valid());
+        assert!(!Date { year: 2023, month: 13, day: 1 }.is_valid());
+
+        let mut fv = FieldValue::new(&DESC_DATEVAL1);
+        fv.set_date(Date::new(2024, 3, 1));
+        fv.set_date(Date { year: 2024, month: 2, day: 31 });
+        assert_eq!(Date::new(2024, 3, 1), fv.get_date());
+    }
+
+    #[test]
+    fn field_value_color_roundtrip() {
+        let fv = FieldValue::from_store(&DESC_RGBVAL1, [0u8; 64]);
+        assert_eq!(3, fv.len());
+        assert_eq!(Color::rgb(0xff, 0x80, 0x00), fv.get_color());
+
+        let mut fv = FieldValue::new(&DESC_RGBVAL1);
+        fv.set_color(Color::rgba(1, 2, 3, 4));
+        assert_eq!(Color::rgb(1, 2, 3), fv.get_color());
+
+        let fv = FieldValue::from_message(&DESC_RGBWVAL1, &[1, 2, 3, 4, 0, 0]);
+        assert_eq!(4, fv.len());
+        assert_eq!(Color::rgbw(1, 2, 3, 4), fv.get_color());
+    }
+
+    #[test]
+    fn field_value_enum_roundtrip() {
+        let fv = FieldValue::from_store(&DESC_ENUMVAL1, [0u8; 64]);
+        assert_eq!(Some(Security::Wpa2), fv.get_enum::<Security>());
+
+        let mut fv = FieldValue::new(&DESC_ENUMVAL1);
+        fv.set_enum(Security::Wpa3);
+        let fv = FieldValue::from_store(&DESC_ENUMVAL1, fv.into_store_bytes());
+        assert_eq!(&[2], fv.get_options().as_slice());
+        assert_eq!(Some(Security::Wpa3), fv.get_enum::<Security>());
+    }
+}
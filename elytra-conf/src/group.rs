@@ -0,0 +1,91 @@
+use core::prelude::rust_2024::*;
+
+use crate::command::CommandResponse;
+
+/// Tracks which instances of a repeated section (group) are in use.
+///
+/// The firmware is expected to persist the [`bits`](Self::bits) along with the props,
+/// and restore it using [`from_bits`](Self::from_bits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GroupInstances {
+    used: u64,
+    max: u8,
+}
+
+impl GroupInstances {
+    pub const MAX_INSTANCES: u32 = u64::BITS;
+
+    pub const fn new(max: u8) -> Self {
+        Self { used: 0, max }
+    }
+
+    pub const fn from_bits(max: u8, bits: u64) -> Self {
+        let mask = if max as u32 >= Self::MAX_INSTANCES { u64::MAX } else { (1 << max) - 1 };
+        Self { used: bits & mask, max }
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.used
+    }
+
+    pub const fn len(&self) -> u8 {
+        self.used.count_ones() as u8
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+
+    pub const fn contains(&self, instance: u8) -> bool {
+        instance < self.max && self.used & (1 << instance) != 0
+    }
+
+    /// Marks the first free instance as used and returns it, or `None` if all are in use
+    pub fn add(&mut self) -> Option<u8> {
+        let instance = (0..self.max).find(|i| !self.contains(*i))?;
+        self.used |= 1 << instance;
+        Some(instance)
+    }
+
+    /// Marks the instance as free, returning whether it was in use
+    pub fn remove(&mut self, instance: u8) -> bool {
+        let was_used = self.contains(instance);
+        if was_used {
+            self.used &= !(1 << instance);
+        }
+        was_used
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.max).filter(|i| self.contains(*i))
+    }
+}
+
+impl From<&GroupInstances> for CommandResponse {
+    fn from(value: &GroupInstances) -> Self {
+        let mut res = CommandResponse::new();
+        res.push(value.len());
+        res.extend(value.used.to_le_bytes());
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GroupInstances;
+
+    #[test]
+    fn group_instances_add_remove() {
+        let mut instances = GroupInstances::new(3);
+        assert_eq!(Some(0), instances.add());
+        assert_eq!(Some(1), instances.add());
+        assert!(instances.remove(0));
+        assert!(!instances.remove(0));
+        assert_eq!(Some(0), instances.add());
+        assert_eq!(Some(2), instances.add());
+        assert_eq!(None, instances.add());
+        assert_eq!(0b111, instances.bits());
+        assert_eq!(0b011, GroupInstances::from_bits(2, 0b111).bits());
+    }
+}
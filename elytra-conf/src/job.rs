@@ -0,0 +1,194 @@
+use core::prelude::rust_2024::*;
+use num_enum::TryFromPrimitive;
+
+use crate::{command::{CommandError, CommandResponse}, config::MESSAGE_LENGTH};
+
+/// Max length of a job status message, filling the rest of the status response
+pub const JOB_MESSAGE_LEN: usize = MESSAGE_LENGTH - 3;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JobState {
+    Running = b'r',
+    Done = b'd',
+    Failed = b'f',
+    Cancelled = b'c',
+}
+
+impl JobState {
+    pub const fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Running)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Job {
+    id: u8,
+    state: JobState,
+    progress: u8,
+    message: [u8; JOB_MESSAGE_LEN],
+    message_len: u8,
+}
+
+impl Job {
+    pub const fn id(&self) -> u8 { self.id }
+    pub const fn state(&self) -> JobState { self.state }
+    /// Progress in percent (0-100)
+    pub const fn progress(&self) -> u8 { self.progress }
+
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len as usize]).unwrap_or_default()
+    }
+
+    fn set_message(&mut self, message: &str) {
+        let mut len = message.len().min(JOB_MESSAGE_LEN);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.message[..len].copy_from_slice(&message.as_bytes()[..len]);
+        self.message_len = len as u8;
+    }
+}
+
+impl From<&Job> for CommandResponse {
+    fn from(value: &Job) -> Self {
+        let mut res = CommandResponse::new();
+        res.push(value.state as u8);
+        res.push(value.progress);
+        res.extend(value.message().bytes());
+        res
+    }
+}
+
+/// Tracks the state of up to `N` long-running actions.
+///
+/// Starting a long-running action allocates a job id that is returned to the host, which then polls
+/// the [`status`](Self::status) of the job. The action itself reports back using
+/// [`set_progress`](Self::set_progress) and [`finish`](Self::finish), and should check
+/// [`is_cancelled`](Self::is_cancelled) to stop early when the host cancels the job.
+/// Finished jobs are kept until their slot is needed for a new job.
+pub struct Jobs<const N: usize> {
+    slots: [Option<Job>; N],
+    next_id: u8,
+}
+
+impl <const N: usize> Default for Jobs<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <const N: usize> Jobs<N> {
+    pub const fn new() -> Self {
+        Self { slots: [None; N], next_id: 1 }
+    }
+
+    /// Allocates a new running job, returning its id
+    pub fn start(&mut self) -> Result<u8, CommandError> {
+        let slot = self.slots.iter().position(Option::is_none)
+            .or_else(|| self.slots.iter().position(|j| j.is_some_and(|j| j.state.is_finished())))
+            .ok_or(CommandError::Failed)?;
+        let id = self.next_id;
+        // Job id 0 is never used, so hosts can use it as a "no job" marker
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.slots[slot] = Some(Job {
+            id,
+            state: JobState::Running,
+            progress: 0,
+            message: [0; JOB_MESSAGE_LEN],
+            message_len: 0,
+        });
+        Ok(id)
+    }
+
+    pub fn get(&self, id: u8) -> Option<&Job> {
+        self.slots.iter().flatten().find(|j| j.id == id)
+    }
+
+    fn get_mut(&mut self, id: u8) -> Result<&mut Job, CommandError> {
+        self.slots.iter_mut().flatten().find(|j| j.id == id).ok_or(CommandError::InvalidJob)
+    }
+
+    /// Updates the progress of a running job, ignoring jobs that have already finished
+    pub fn set_progress(&mut self, id: u8, progress: u8, message: &str) -> Result<(), CommandError> {
+        let job = self.get_mut(id)?;
+        if job.state == JobState::Running {
+            job.progress = progress.min(100);
+            job.set_message(message);
+        }
+        Ok(())
+    }
+
+    /// Marks a job as done or failed, unless it was cancelled
+    pub fn finish(&mut self, id: u8, success: bool, message: &str) -> Result<(), CommandError> {
+        let job = self.get_mut(id)?;
+        if job.state == JobState::Running {
+            job.state = if success { JobState::Done } else { JobState::Failed };
+            if success {
+                job.progress = 100;
+            }
+            job.set_message(message);
+        }
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, id: u8) -> Result<(), CommandError> {
+        let job = self.get_mut(id)?;
+        if job.state.is_finished() {
+            return Err(CommandError::Failed)
+        }
+        job.state = JobState::Cancelled;
+        Ok(())
+    }
+
+    pub fn is_cancelled(&self, id: u8) -> bool {
+        self.get(id).is_some_and(|j| j.state == JobState::Cancelled)
+    }
+
+    /// The ids of jobs that are still running
+    pub fn running(&self) -> impl Iterator<Item = u8> + '_ {
+        self.slots.iter().flatten().filter(|j| j.state == JobState::Running).map(|j| j.id)
+    }
+
+    pub fn status(&self, id: u8) -> Result<CommandResponse, CommandError> {
+        self.get(id).map(Into::into).ok_or(CommandError::InvalidJob)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Jobs, JobState};
+    use crate::command::CommandError;
+
+    #[test]
+    fn job_lifecycle() {
+        let mut jobs = Jobs::<2>::new();
+        let first = jobs.start().unwrap();
+        let second = jobs.start().unwrap();
+        assert_eq!(Err(CommandError::Failed), jobs.start());
+
+        jobs.set_progress(first, 150, "Writing").unwrap();
+        assert_eq!(100, jobs.get(first).unwrap().progress());
+        assert_eq!("Writing", jobs.get(first).unwrap().message());
+
+        jobs.cancel(second).unwrap();
+        assert!(jobs.is_cancelled(second));
+        jobs.finish(second, true, "Done").unwrap();
+        assert_eq!(JobState::Cancelled, jobs.get(second).unwrap().state());
+        assert_eq!(Err(CommandError::Failed), jobs.cancel(second));
+
+        // the cancelled job is replaced by a new one
+        let third = jobs.start().unwrap();
+        assert!(jobs.get(second).is_none());
+        assert_eq!(Err(CommandError::InvalidJob), jobs.status(second).map(|_| ()));
+        let mut running = jobs.running();
+        assert_eq!(Some(first), running.next());
+        assert_eq!(Some(third), running.next());
+        assert_eq!(None, running.next());
+
+        let status = jobs.status(first).unwrap();
+        assert_eq!([1, b'r', 100, b'W'], status.as_bytes()[0..4]);
+    }
+}
@@ -9,7 +9,17 @@ pub mod traits;
 pub mod entry;
 pub mod field;
 pub mod command;
+pub mod condition;
 pub mod values;
+pub mod group;
+pub mod job;
+pub mod dfu;
+pub mod store;
+pub mod blob;
+pub mod transaction;
+pub mod auth;
+#[cfg(feature = "secure")]
+pub mod secure;
 pub mod prelude;
 #[cfg(feature = "macros")]
 pub mod macros;
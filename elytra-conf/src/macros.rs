@@ -156,10 +156,10 @@ macro_rules! actions {
 #[macro_export(local_inner_macros)]
 macro_rules! infos {
     ($name:ident {}) => {
-        indexed_entry!($crate::InfoIndex: $name)
+        indexed_entry!($crate::InfoIndex: $name);
     };
     ($name:ident) => {
-        indexed_entry!($crate::InfoIndex: $name)
+        indexed_entry!($crate::InfoIndex: $name);
     };
     ($name:ident { $($s:tt)+ }) => {
         indexed_entry!($crate::InfoIndex: $name { $($s)+ } );
@@ -188,7 +188,7 @@ macro_rules! elytra {
         props: $props:ty,
         sections: $sections:ty,
         actions: $actions:ty,
-        layout: { $( $ls:path: [ $( $lf:expr ),* ] ),* }
+        layout: { $( $ls:path: [ $( $lf:expr $(=> $lc:expr)? ),* ] ),* }
         }
     ) => {
         pub type $tident = $crate::config::Config<${count($lf)}, $sections, $props, $info, $actions>;
@@ -198,7 +198,13 @@ macro_rules! elytra {
                 ($ls, $lf),
                 )*
             )*],
-        );
+        ).with_conditions(&[$(
+            $(
+                $(
+                ($ls, $lf, $lc),
+                )?
+            )*
+        )*]);
     };
     ( $cvis:vis $cident:ident: $tident:ident {
         info: { $($ix:tt)+ },
@@ -220,7 +226,7 @@ macro_rules! elytra {
         props: $([$($pattr:tt)+])? $p:ident { $($px:tt)* },
         sections: $s:ident { $($sx:tt)* },
         actions: $a:ident { $($ax:tt)* },
-        layout: { $( $ls:path: [ $( $lf:expr ),* ] ),* }
+        layout: { $( $ls:path: [ $( $lf:expr $(=> $lc:expr)? ),* ] ),* }
     }
     ) => {
         actions!($a { $($ax)* });
@@ -235,13 +241,20 @@ macro_rules! elytra {
                 ($ls, $lf),
                 )*
             )*],
-        );
+        ).with_conditions(&[$(
+            $(
+                $(
+                ($ls, $lf, $lc),
+                )?
+            )*
+        )*]);
     };
 }
 
 #[cfg(test)]
 mod test {
     use crate::prelude::*;
+    use crate::command::CommandError;
 
     #[test]
     fn test_empty() {
@@ -314,4 +327,242 @@ mod test {
         assert_eq!(3, S::count());
         assert_eq!("Top", S::ENTRIES[0].name);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_group_slots() {
+        elytra!(C: T {
+            info: InfoField { },
+            props: PropField {
+                Name: prop("Name"),
+                AlarmTime: time("Alarm"),
+                AlarmOn: toggle("Enabled"),
+                Ssid: prop("SSID")
+            },
+            sections: Section {
+                General: section("General"),
+                Alarms: section("Alarms").repeated(4),
+                Wifi: section("WiFi").repeated(2)
+            },
+            actions: Action { },
+            layout: {
+                Section::General: [
+                    Field::Prop(PropField::Name)
+                ],
+                Section::Alarms: [
+                    Field::Prop(PropField::AlarmTime),
+                    Field::Prop(PropField::AlarmOn)
+                ],
+                Section::Wifi: [
+                    Field::Prop(PropField::Ssid)
+                ]
+            }
+        });
+
+        assert_eq!(4 + 3 * 2 + 1, C.storage_slots());
+        assert_eq!(Ok(1), C.group_slot(Section::Alarms, 0, PropField::AlarmTime));
+        assert_eq!(Ok(4), C.group_slot(Section::Alarms, 1, PropField::AlarmTime));
+        assert_eq!(Ok(9), C.group_slot(Section::Alarms, 3, PropField::AlarmOn));
+        assert_eq!(Ok(10), C.group_slot(Section::Wifi, 1, PropField::Ssid));
+        assert_eq!(Err(CommandError::InvalidInstance), C.group_slot(Section::Wifi, 2, PropField::Ssid));
+        assert_eq!(Err(CommandError::InvalidField), C.group_slot(Section::Wifi, 1, PropField::Name));
+        assert_eq!(Err(CommandError::InvalidSection), C.group_slot(Section::General, 0, PropField::Name));
+    }
+
+    #[test]
+    fn test_conditions() {
+        elytra!(C: T {
+            info: InfoField { },
+            props: PropField {
+                Dhcp: toggle("DHCP"),
+                StaticIp: ipv4("Static IP"),
+                Security: option("Security", &["Open", "WPA2"]).with_default_options(&[0]),
+                Password: secret("Password")
+            },
+            sections: Section {
+                Network: section("Network"),
+                Wifi: section("WiFi")
+            },
+            actions: Action { },
+            layout: {
+                Section::Network: [
+                    Field::Prop(PropField::Dhcp),
+                    Field::Prop(PropField::StaticIp) => show_when(PropField::Dhcp).is_disabled()
+                ],
+                Section::Wifi: [
+                    Field::Prop(PropField::Security),
+                    Field::Prop(PropField::Password) => writable_when(PropField::Security).not_equals(0)
+                ]
+            }
+        });
+
+        assert_eq!(4, C.layout.len());
+        assert_eq!(2, C.conditions.len());
+        assert_eq!(None, C.layout_condition(Section::Network, 0));
+        let condition = C.layout_condition(Section::Network, 1).unwrap();
+        assert_eq!(PropField::Dhcp, condition.prop);
+
+        let mut dhcp = FieldValue::new(PropField::Dhcp.get_entry());
+        dhcp.set_enabled(true);
+        assert!(!condition.matches(&dhcp));
+        dhcp.set_enabled(false);
+        assert!(condition.matches(&dhcp));
+
+        let condition = C.layout_condition(Section::Wifi, 1).unwrap();
+        let mut security = FieldValue::new(PropField::Security.get_entry());
+        security.set_options(&[0]);
+        assert!(!condition.matches(&security));
+        security.set_options(&[1]);
+        assert!(condition.matches(&security));
+
+        assert_eq!(Ok(()), C.check_writable(PropField::Password, |_| Ok(security.clone())));
+        assert_eq!(Ok(()), C.check_writable(PropField::Dhcp, |_| unreachable!()));
+        security.set_options(&[0]);
+        assert_eq!(Err(CommandError::Locked), C.check_writable(PropField::Password, |_| Ok(security.clone())));
+    }
+
+    #[test]
+    fn test_nested_layout() {
+        use crate::config::{EntryIndex, QueryTarget};
+
+        elytra!(C: T {
+            info: InfoField { },
+            props: PropField {
+                Ssid: prop("SSID"),
+                Ip: ipv4("IP")
+            },
+            sections: Section {
+                Network: section("Network"),
+                Wifi: section("WiFi"),
+                Static: section("Static")
+            },
+            actions: Action {
+                Scan: action("Scan")
+            },
+            layout: {
+                Section::Network: [
+                    Field::Heading("Wireless"),
+                    Field::Section(Section::Wifi),
+                    Field::Separator,
+                    Field::Section(Section::Static)
+                ],
+                Section::Wifi: [
+                    Field::Prop(PropField::Ssid),
+                    Field::Action(Action::Scan)
+                ],
+                Section::Static: [
+                    Field::Prop(PropField::Ip)
+                ]
+            }
+        });
+
+        let mut subsections = C.subsections(Section::Network);
+        assert_eq!(Some(Section::Wifi), subsections.next());
+        assert_eq!(Some(Section::Static), subsections.next());
+        assert_eq!(None, subsections.next());
+        assert_eq!(1, C.group_props(Section::Wifi).count());
+        assert_eq!(
+            [b't', 0, b's', 1, b'-', 0, b's', 2], 
+            C.section_layout(Section::Network).as_bytes()[1..9]
+        );
+        assert_eq!([b'p', 0, b'a', 0], C.section_layout(Section::Wifi).as_bytes()[1..5]);
+
+        let heading = C.handle_query(EntryIndex::Section(Section::Network), QueryTarget::Heading(0)).unwrap();
+        assert_eq!(b"Wireless", &heading.as_bytes()[1..9]);
+        let not_heading = C.handle_query(EntryIndex::Section(Section::Network), QueryTarget::Heading(1));
+        assert_eq!(Some(CommandError::NoContent), not_heading.err());
+    }
+
+    #[test]
+    fn test_action_signature() {
+        use crate::command::Command;
+        use crate::config::{EntryIndex, QueryTarget};
+
+        elytra!(C: T {
+            info: InfoField { },
+            props: PropField { },
+            sections: Section { },
+            actions: Action {
+                Reset: action("Reset"),
+                Scan: action("Scan")
+                    .with_params(&[
+                        prop("SSID").as_param(),
+                        integer("Timeout").with_default_integer(5).as_param()
+                    ])
+                    .with_result(&integer("Found").as_result())
+            },
+            layout: { }
+        });
+
+        let command = Command::<Action, PropField, InfoField, Section>::from_bytes(
+            &[b'a', 1, 1, 4, b'h', b'o', b'm', b'e', 0, 0]
+        );
+        let Ok(Command::Action((Action::Scan, args))) = command else {
+            panic!("expected scan action")
+        };
+        assert_eq!(1, args.count());
+        assert_eq!("home", args.get(0).unwrap().get_text());
+        assert_eq!(5, args.get(1).unwrap().get_integer());
+        assert!(args.get(2).is_none());
+        assert!(args.result().is_some());
+
+        let too_many = Command::<Action, PropField, InfoField, Section>::from_bytes(&[b'a', 0, 1, 0]);
+        assert!(too_many.is_err());
+
+        let field = C.handle_query(EntryIndex::Action(Action::Scan), QueryTarget::Field).unwrap();
+        assert_eq!([2, 1], field.as_bytes()[4..6]);
+        let param = C.handle_query(EntryIndex::Action(Action::Scan), QueryTarget::Param(1)).unwrap();
+        assert_eq!(ValueType::Integer as u8, param.as_bytes()[3]);
+        let result = C.handle_query(EntryIndex::Action(Action::Reset), QueryTarget::Result);
+        assert_eq!(Some(CommandError::NoContent), result.err());
+    }
+
+    #[test]
+    fn test_reset_to_default() {
+        use crate::command::Command;
+        use crate::config::{EntryIndex, MESSAGE_LENGTH, QueryTarget};
+        use crate::store::PropStore;
+
+        struct ArrayStore([[u8; MESSAGE_LENGTH]; 3]);
+        impl PropStore for ArrayStore {
+            fn read(&mut self, slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+                Ok(self.0[slot])
+            }
+            fn write(&mut self, slot: usize, bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError> {
+                self.0[slot] = bytes;
+                Ok(())
+            }
+        }
+
+        elytra!(C: T {
+            info: InfoField { },
+            props: PropField {
+                Server: prop("Server").with_default_text("ntp.se"),
+                Name: prop("Name")
+            },
+            sections: Section {
+                Servers: section("Servers").repeated(2)
+            },
+            actions: Action { },
+            layout: {
+                Section::Servers: [
+                    Field::Prop(PropField::Server)
+                ]
+            }
+        });
+
+        let default = C.handle_query(EntryIndex::Prop(PropField::Server), QueryTarget::Default).unwrap();
+        assert_eq!([1, b'n', b't', b'p', b'.', b's', b'e', 0], default.as_bytes()[..8]);
+        let command = Command::<Action, PropField, InfoField, Section>::from_bytes(&[b'q', b's', 0, b'd']);
+        assert!(command.is_err());
+
+        let written = FieldValue::from_message(PropField::Server.get_entry(), b"time.example").into_store_bytes();
+        let mut store = ArrayStore([written; 3]);
+        C.reset_prop(&mut store, PropField::Server).unwrap();
+        let value = FieldValue::from_store(PropField::Server.get_entry(), store.read(0).unwrap());
+        assert_eq!("ntp.se", value.get_text());
+        assert_eq!(written, store.read(2).unwrap());
+
+        C.reset_all(&mut store).unwrap();
+        assert!(store.0.iter().all(|slot| *slot == [0; MESSAGE_LENGTH]));
+    }
+}
@@ -1,5 +1,5 @@
 #[allow(unused_imports)]
-pub use super::values::ValueType;
+pub use super::values::{ValueType, TimeOfDay, Date, Color};
 
 #[allow(unused_imports)] 
 pub use super::field::FieldValue;
@@ -14,5 +14,12 @@ pub use super::config::Config;
 pub use super::entry::{
     ActionEntry, ActionVariant, FieldEntry, InfoEntry, PropEntry, SectionEntry, Field, 
     info, bytes, section, action, secret, status, integer, option, prop, toggle,
-    options::OptionValueProvider,
-};
\ No newline at end of file
+    ipv4, ipv6, mac, port, time, date, timestamp, duration, color, blob,
+    options::{OptionEnum, OptionValueProvider}, option_enum,
+};
+
+#[allow(unused_imports)]
+pub use super::condition::{Condition, show_when, writable_when};
+
+#[allow(unused_imports)]
+pub use super::auth::AccessLevel;
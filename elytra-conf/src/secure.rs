@@ -0,0 +1,302 @@
+use core::prelude::rust_2024::*;
+
+use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, KeyInit, Nonce, Tag};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{command::CommandError, config::MESSAGE_LENGTH};
+
+/// Noise protocol used for the handshake, see <https://noiseprotocol.org/noise.html>
+pub const PROTOCOL_NAME: &[u8] = b"Noise_NNpsk0_25519_ChaChaPoly_SHA256";
+/// Mixed into the handshake hash, so that keys can't be reused with other protocols
+pub const PROLOGUE: &[u8] = b"elytra";
+
+pub const KEY_LEN: usize = 32;
+pub const TAG_LEN: usize = 16;
+/// Length of both handshake messages: an ephemeral public key and the tag of the empty payload
+pub const HANDSHAKE_LEN: usize = KEY_LEN + TAG_LEN;
+/// Length of an encrypted message frame
+pub const SECURE_FRAME_LEN: usize = MESSAGE_LENGTH + TAG_LEN;
+
+/// Authenticated and encrypted sessions, wrapping the message frames of a transport.
+///
+/// Both ends share a pre-shared key (PSK), and each generates a random ephemeral key for the
+/// handshake, so that recorded sessions can't be decrypted even if the PSK leaks later. The host
+/// sends the [`request`](Handshake::request), the device [`respond`](Handshake::respond)s, and
+/// once the host has [`finish`](Handshake::finish)ed both have a [`Session`] for the frames that
+/// follow. A wrong PSK fails the handshake with [`CommandError::InvalidCredentials`].
+pub struct Handshake {
+    symmetric: SymmetricState,
+    ephemeral: StaticSecret,
+}
+
+impl Handshake {
+    /// Starts a handshake, `ephemeral` needs to be fresh random bytes for every handshake
+    pub fn new(psk: &[u8; KEY_LEN], ephemeral: [u8; KEY_LEN]) -> Self {
+        let mut symmetric = SymmetricState::new();
+        symmetric.mix_hash(PROLOGUE);
+        symmetric.mix_key_and_hash(psk);
+        Self { symmetric, ephemeral: StaticSecret::from(ephemeral) }
+    }
+
+    /// The first handshake message, sent by the host: `-> psk, e`
+    pub fn request(&mut self) -> Result<[u8; HANDSHAKE_LEN], CommandError> {
+        let mut message = [0u8; HANDSHAKE_LEN];
+        self.write_ephemeral(&mut message);
+        self.write_payload(&mut message)?;
+        Ok(message)
+    }
+
+    /// Reads the request and responds to it: `<- e, ee`
+    pub fn respond(mut self, request: &[u8; HANDSHAKE_LEN]) -> Result<([u8; HANDSHAKE_LEN], Session), CommandError> {
+        let remote = self.read_ephemeral(request);
+        self.read_payload(request)?;
+        let mut response = [0u8; HANDSHAKE_LEN];
+        self.write_ephemeral(&mut response);
+        self.mix_dh(&remote);
+        self.write_payload(&mut response)?;
+        let (initiator, responder) = self.symmetric.split();
+        Ok((response, Session { send: responder, recv: initiator }))
+    }
+
+    /// Reads the response to the request, completing the handshake on the host
+    pub fn finish(mut self, response: &[u8; HANDSHAKE_LEN]) -> Result<Session, CommandError> {
+        let remote = self.read_ephemeral(response);
+        self.mix_dh(&remote);
+        self.read_payload(response)?;
+        let (initiator, responder) = self.symmetric.split();
+        Ok(Session { send: initiator, recv: responder })
+    }
+
+    fn write_ephemeral(&mut self, message: &mut [u8; HANDSHAKE_LEN]) {
+        let public = PublicKey::from(&self.ephemeral);
+        message[..KEY_LEN].copy_from_slice(public.as_bytes());
+        self.symmetric.mix_hash(public.as_bytes());
+        self.symmetric.mix_key(public.as_bytes());
+    }
+
+    fn read_ephemeral(&mut self, message: &[u8; HANDSHAKE_LEN]) -> PublicKey {
+        let mut remote = [0u8; KEY_LEN];
+        remote.copy_from_slice(&message[..KEY_LEN]);
+        self.symmetric.mix_hash(&remote);
+        self.symmetric.mix_key(&remote);
+        PublicKey::from(remote)
+    }
+
+    fn mix_dh(&mut self, remote: &PublicKey) {
+        self.symmetric.mix_key(self.ephemeral.diffie_hellman(remote).as_bytes());
+    }
+
+    /// Handshake messages carry an empty payload, only its tag is sent
+    fn write_payload(&mut self, message: &mut [u8; HANDSHAKE_LEN]) -> Result<(), CommandError> {
+        let tag = self.symmetric.encrypt_and_hash(&mut [])?;
+        message[KEY_LEN..].copy_from_slice(&tag);
+        Ok(())
+    }
+
+    fn read_payload(&mut self, message: &[u8; HANDSHAKE_LEN]) -> Result<(), CommandError> {
+        self.symmetric.decrypt_and_hash(&mut [], &message[KEY_LEN..])
+            .or(Err(CommandError::InvalidCredentials))
+    }
+}
+
+/// Encrypts and decrypts message frames after a completed [`Handshake`]
+pub struct Session {
+    send: CipherState,
+    recv: CipherState,
+}
+
+impl Session {
+    pub fn encrypt(&mut self, message: &[u8; MESSAGE_LENGTH]) -> Result<[u8; SECURE_FRAME_LEN], CommandError> {
+        let mut frame = [0u8; SECURE_FRAME_LEN];
+        frame[..MESSAGE_LENGTH].copy_from_slice(message);
+        let tag = self.send.encrypt(&[], &mut frame[..MESSAGE_LENGTH])?;
+        frame[MESSAGE_LENGTH..].copy_from_slice(&tag);
+        Ok(frame)
+    }
+
+    /// Decrypts a frame, failing with [`CommandError::InvalidData`] if it was tampered with or replayed
+    pub fn decrypt(&mut self, frame: &[u8; SECURE_FRAME_LEN]) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+        let mut message = [0u8; MESSAGE_LENGTH];
+        message.copy_from_slice(&frame[..MESSAGE_LENGTH]);
+        self.recv.decrypt(&[], &mut message, &frame[MESSAGE_LENGTH..])?;
+        Ok(message)
+    }
+}
+
+struct CipherState {
+    key: Option<[u8; KEY_LEN]>,
+    nonce: u64,
+}
+
+impl CipherState {
+    const fn empty() -> Self {
+        Self { key: None, nonce: 0 }
+    }
+
+    fn with_key(key: [u8; KEY_LEN]) -> Self {
+        Self { key: Some(key), nonce: 0 }
+    }
+
+    /// The cipher and nonce for the next message, failing once the nonces run out
+    fn cipher(&self) -> Result<(ChaCha20Poly1305, Nonce), CommandError> {
+        let key = self.key.ok_or(CommandError::Failed)?;
+        if self.nonce == u64::MAX {
+            return Err(CommandError::Failed)
+        }
+        let mut nonce = Nonce::default();
+        nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+        Ok((ChaCha20Poly1305::new(&key.into()), nonce))
+    }
+
+    fn encrypt(&mut self, ad: &[u8], buf: &mut [u8]) -> Result<[u8; TAG_LEN], CommandError> {
+        let (cipher, nonce) = self.cipher()?;
+        let tag = cipher.encrypt_in_place_detached(&nonce, ad, buf).or(Err(CommandError::Failed))?;
+        self.nonce += 1;
+        Ok(tag.into())
+    }
+
+    fn decrypt(&mut self, ad: &[u8], buf: &mut [u8], tag: &[u8]) -> Result<(), CommandError> {
+        let (cipher, nonce) = self.cipher()?;
+        cipher.decrypt_in_place_detached(&nonce, ad, buf, Tag::from_slice(tag)).or(Err(CommandError::InvalidData))?;
+        self.nonce += 1;
+        Ok(())
+    }
+}
+
+struct SymmetricState {
+    chaining_key: [u8; KEY_LEN],
+    hash: [u8; KEY_LEN],
+    cipher: CipherState,
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        let hash: [u8; KEY_LEN] = Sha256::digest(PROTOCOL_NAME).into();
+        Self { chaining_key: hash, hash, cipher: CipherState::empty() }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.hash = Sha256::new().chain_update(self.hash).chain_update(data).finalize().into();
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        let [chaining_key, key, _] = hkdf(&self.chaining_key, input);
+        self.chaining_key = chaining_key;
+        self.cipher = CipherState::with_key(key);
+    }
+
+    fn mix_key_and_hash(&mut self, input: &[u8]) {
+        let [chaining_key, hash, key] = hkdf(&self.chaining_key, input);
+        self.chaining_key = chaining_key;
+        self.mix_hash(&hash);
+        self.cipher = CipherState::with_key(key);
+    }
+
+    fn encrypt_and_hash(&mut self, buf: &mut [u8]) -> Result<[u8; TAG_LEN], CommandError> {
+        let tag = self.cipher.encrypt(&self.hash, buf)?;
+        let hash = Sha256::new().chain_update(self.hash).chain_update(&*buf).chain_update(tag).finalize();
+        self.hash = hash.into();
+        Ok(tag)
+    }
+
+    fn decrypt_and_hash(&mut self, buf: &mut [u8], tag: &[u8]) -> Result<(), CommandError> {
+        let hash: [u8; KEY_LEN] = Sha256::new().chain_update(self.hash).chain_update(&*buf).chain_update(tag).finalize().into();
+        self.cipher.decrypt(&self.hash, buf, tag)?;
+        self.hash = hash;
+        Ok(())
+    }
+
+    fn split(&self) -> (CipherState, CipherState) {
+        let [initiator, responder, _] = hkdf(&self.chaining_key, &[]);
+        (CipherState::with_key(initiator), CipherState::with_key(responder))
+    }
+}
+
+/// The HKDF of the Noise spec, always deriving three outputs
+fn hkdf(chaining_key: &[u8; KEY_LEN], input: &[u8]) -> [[u8; KEY_LEN]; 3] {
+    let hmac = |key: &[u8], parts: &[&[u8]]| -> [u8; KEY_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+        parts.iter().for_each(|part| mac.update(part));
+        mac.finalize().into_bytes().into()
+    };
+    let temp = hmac(chaining_key, &[input]);
+    let first = hmac(&temp, &[&[1]]);
+    let second = hmac(&temp, &[&first, &[2]]);
+    let third = hmac(&temp, &[&second, &[3]]);
+    [first, second, third]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PSK: [u8; KEY_LEN] = [0x11; KEY_LEN];
+    const HOST_EPHEMERAL: [u8; KEY_LEN] = {
+        let mut key = [0u8; KEY_LEN];
+        let mut i = 0;
+        while i < KEY_LEN { key[i] = i as u8; i += 1; }
+        key
+    };
+    const DEVICE_EPHEMERAL: [u8; KEY_LEN] = {
+        let mut key = [0u8; KEY_LEN];
+        let mut i = 0;
+        while i < KEY_LEN { key[i] = 32 + i as u8; i += 1; }
+        key
+    };
+
+    fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    /// Checked against the `snow` crate using the same PSK, prologue and ephemeral keys
+    #[test]
+    fn handshake_test_vectors() {
+        let mut host = Handshake::new(&PSK, HOST_EPHEMERAL);
+        let request = host.request().unwrap();
+        assert_eq!(unhex::<HANDSHAKE_LEN>("8f40c5adb68f25624ae5b214ea767a6ec94d829d3d7b5e1ad1ba6f3e2138285f2e0b1b434ff477ede7f5b31d6fdcfda2"), request);
+
+        let (response, mut device) = Handshake::new(&PSK, DEVICE_EPHEMERAL).respond(&request).unwrap();
+        assert_eq!(unhex::<HANDSHAKE_LEN>("358072d6365880d1aeea329adf9121383851ed21a28e3b75e965d0d2cd166254fae7e5fa2ddecb357ecb62d5791612cd"), response);
+        let mut host = host.finish(&response).unwrap();
+
+        let mut message = [0u8; MESSAGE_LENGTH];
+        message[0] = b'm';
+        let frame = host.encrypt(&message).unwrap();
+        assert_eq!(unhex::<SECURE_FRAME_LEN>("ae1bf6a721eb850982b4640747643212e761b5439f8b6fc82ffa2b45da420f1f626866ed345edf9bf517bfa244a1a6fbf551da1fa32736f664dba1c7a18d63e24bb57a106611d4afc9abd6bdb298d815"), frame);
+        assert_eq!(message, device.decrypt(&frame).unwrap());
+
+        let mut response = [0u8; MESSAGE_LENGTH];
+        response[..2].copy_from_slice(&[1, 2]);
+        let frame = device.encrypt(&response).unwrap();
+        assert_eq!(unhex::<SECURE_FRAME_LEN>("cf8def041691c39cec6e37b3d3dc916b28b43803dcf3097f1490d465257c707737581e13d0b63386d8fc44aae174ddfac497eba81435ccf13496645437bdd2b91f78cf6c6b985c3602851a7abcfb883a"), frame);
+        assert_eq!(response, host.decrypt(&frame).unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_psk_and_tampering() {
+        let request = Handshake::new(&PSK, HOST_EPHEMERAL).request().unwrap();
+        let wrong = Handshake::new(&[0x22; KEY_LEN], DEVICE_EPHEMERAL).respond(&request);
+        assert_eq!(Some(CommandError::InvalidCredentials), wrong.err());
+
+        let mut host = Handshake::new(&PSK, HOST_EPHEMERAL);
+        let (response, mut device) = Handshake::new(&PSK, DEVICE_EPHEMERAL).respond(&host.request().unwrap()).unwrap();
+        let mut host = host.finish(&response).unwrap();
+
+        let first = host.encrypt(&[b'm'; MESSAGE_LENGTH]).unwrap();
+        let mut tampered = host.encrypt(&[b'r'; MESSAGE_LENGTH]).unwrap();
+        tampered[1] ^= 1;
+        assert_eq!(Ok([b'm'; MESSAGE_LENGTH]), device.decrypt(&first));
+        assert_eq!(Err(CommandError::InvalidData), device.decrypt(&tampered));
+        // replaying a frame fails, as the nonce has moved on
+        assert_eq!(Err(CommandError::InvalidData), device.decrypt(&first));
+        // rejected frames don't desynchronize the session
+        tampered[1] ^= 1;
+        assert_eq!(Ok([b'r'; MESSAGE_LENGTH]), device.decrypt(&tampered));
+    }
+}
@@ -0,0 +1,102 @@
+use core::prelude::rust_2024::*;
+
+use crate::{command::CommandError, config::MESSAGE_LENGTH};
+
+/// Persistent storage for prop values and blobs, by storage slot.
+///
+/// Values are kept in their store representation (see [`FieldValue::from_store`](crate::field::FieldValue::from_store)
+/// and [`into_store_bytes`](crate::field::FieldValue::into_store_bytes)), where the slot of a prop is
+/// its index, or the one given by [`Config::group_slot`](crate::config::Config::group_slot) for
+/// repeated section instances. Stores that don't hold any blob props can skip the blob methods.
+pub trait PropStore {
+    /// Reads the stored value, all zeroes if the slot has not been written
+    fn read(&mut self, slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError>;
+
+    fn write(&mut self, slot: usize, bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError>;
+
+    /// Clears the stored value, so that it reads as the default value of the prop
+    fn reset(&mut self, slot: usize) -> Result<(), CommandError> {
+        self.write(slot, [0; MESSAGE_LENGTH])
+    }
+
+    /// The current length of a blob, 0 if it has not been written
+    fn blob_len(&mut self, _slot: usize) -> Result<u32, CommandError> {
+        Err(CommandError::NotSupported)
+    }
+
+    /// Reads blob data starting at `offset`, returning the number of bytes read
+    fn read_blob(&mut self, _slot: usize, _offset: u32, _buf: &mut [u8]) -> Result<usize, CommandError> {
+        Err(CommandError::NotSupported)
+    }
+
+    /// Writes blob data at `offset`, which is never past the current length of the blob
+    fn write_blob(&mut self, _slot: usize, _offset: u32, _data: &[u8]) -> Result<(), CommandError> {
+        Err(CommandError::NotSupported)
+    }
+
+    /// Shortens a blob to `len` bytes
+    fn truncate_blob(&mut self, _slot: usize, _len: u32) -> Result<(), CommandError> {
+        Err(CommandError::NotSupported)
+    }
+}
+
+/// A [`PropStore`] that keeps everything in memory, for mock devices and tests
+#[cfg(feature = "alloc")]
+#[derive(Default, Debug, Clone)]
+pub struct MemoryStore {
+    values: alloc::collections::BTreeMap<usize, [u8; MESSAGE_LENGTH]>,
+    blobs: alloc::collections::BTreeMap<usize, alloc::vec::Vec<u8>>,
+}
+
+#[cfg(feature = "alloc")]
+impl MemoryStore {
+    pub const fn new() -> Self {
+        Self {
+            values: alloc::collections::BTreeMap::new(),
+            blobs: alloc::collections::BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PropStore for MemoryStore {
+    fn read(&mut self, slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+        Ok(self.values.get(&slot).copied().unwrap_or([0; MESSAGE_LENGTH]))
+    }
+
+    fn write(&mut self, slot: usize, bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError> {
+        self.values.insert(slot, bytes);
+        Ok(())
+    }
+
+    fn blob_len(&mut self, slot: usize) -> Result<u32, CommandError> {
+        Ok(self.blobs.get(&slot).map_or(0, |b| b.len() as u32))
+    }
+
+    fn read_blob(&mut self, slot: usize, offset: u32, buf: &mut [u8]) -> Result<usize, CommandError> {
+        let blob = self.blobs.get(&slot).map(|b| b.as_slice()).unwrap_or_default();
+        let data = blob.get(offset as usize..).ok_or(CommandError::InvalidData)?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn write_blob(&mut self, slot: usize, offset: u32, data: &[u8]) -> Result<(), CommandError> {
+        let blob = self.blobs.entry(slot).or_default();
+        let offset = offset as usize;
+        if offset > blob.len() {
+            return Err(CommandError::InvalidData)
+        }
+        let overlap = (blob.len() - offset).min(data.len());
+        blob[offset..offset + overlap].copy_from_slice(&data[..overlap]);
+        blob.extend_from_slice(&data[overlap..]);
+        Ok(())
+    }
+
+    fn truncate_blob(&mut self, slot: usize, len: u32) -> Result<(), CommandError> {
+        if let Some(blob) = self.blobs.get_mut(&slot) {
+            blob.truncate(len as usize);
+        }
+        Ok(())
+    }
+}
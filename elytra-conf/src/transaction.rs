@@ -0,0 +1,180 @@
+use core::prelude::rust_2024::*;
+
+use crate::{command::CommandError, field::FieldValue, store::PropStore};
+
+/// Stages prop writes between a transaction begin and commit, holding up to `N` values.
+///
+/// Values are staged by storage slot (see [`PropStore`]), so writes to the same prop replace the
+/// staged value. On [`commit`](Self::commit) the staged values are checked by a validation hook,
+/// and only written to the store if it accepts them. Beginning a new transaction discards any
+/// values that were left staged, e.g. by a host that disconnected.
+pub struct Transaction<const N: usize> {
+    staged: [Option<(usize, FieldValue)>; N],
+    active: bool,
+}
+
+impl <const N: usize> Default for Transaction<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <const N: usize> Transaction<N> {
+    pub const fn new() -> Self {
+        Self { staged: [const { None }; N], active: false }
+    }
+
+    pub fn begin(&mut self) {
+        self.clear();
+        self.active = true;
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Stages a value, failing if the staging buffer is full
+    pub fn stage(&mut self, slot: usize, value: FieldValue) -> Result<(), CommandError> {
+        if !self.active {
+            return Err(CommandError::Failed)
+        }
+        let index = self.staged.iter().position(|s| s.as_ref().is_some_and(|(s, _)| *s == slot))
+            .or_else(|| self.staged.iter().position(Option::is_none))
+            .ok_or(CommandError::Failed)?;
+        self.staged[index] = Some((slot, value));
+        Ok(())
+    }
+
+    /// The staged value for a slot, if any
+    pub fn get(&self, slot: usize) -> Option<&FieldValue> {
+        self.values().find(|(s, _)| *s == slot).map(|(_, value)| value)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = (usize, &FieldValue)> {
+        self.staged.iter().flatten().map(|(slot, value)| (*slot, value))
+    }
+
+    /// Validates the staged values and writes them to `store`.
+    ///
+    /// The transaction ends either way; if `validate` fails nothing is written. The previous values
+    /// are read before writing, and if a write fails the slots already written are restored to them,
+    /// so that the store is left as it was before the commit.
+    pub fn commit<S: PropStore>(
+        &mut self,
+        store: &mut S,
+        validate: impl FnOnce(&Self, &mut S) -> Result<(), CommandError>
+    ) -> Result<(), CommandError> {
+        if !self.active {
+            return Err(CommandError::Failed)
+        }
+        let result = validate(self, store).and_then(|_| self.write_all(store));
+        self.abort();
+        result
+    }
+
+    fn write_all(&mut self, store: &mut impl PropStore) -> Result<(), CommandError> {
+        let mut backup = [None; N];
+        for (backup, staged) in backup.iter_mut().zip(&self.staged) {
+            if let Some((slot, _)) = staged {
+                *backup = Some((*slot, store.read(*slot)?));
+            }
+        }
+        for (i, staged) in self.staged.iter_mut().enumerate() {
+            let Some((slot, value)) = staged.take() else { continue };
+            if let Err(e) = store.write(slot, value.into_store_bytes()) {
+                for (slot, bytes) in backup[..i].iter().flatten() {
+                    // Best effort, the original error is what the host needs to know about
+                    let _ = store.write(*slot, *bytes);
+                }
+                return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards the staged values
+    pub fn abort(&mut self) {
+        self.clear();
+        self.active = false;
+    }
+
+    fn clear(&mut self) {
+        self.staged.iter_mut().for_each(|s| *s = None);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::MESSAGE_LENGTH, entry::{EntryDesc, prop}};
+
+    const NAME: EntryDesc = prop("Name").as_entry();
+
+    #[derive(Default)]
+    struct TestStore {
+        slots: [Option<[u8; MESSAGE_LENGTH]>; 4],
+        /// Slot that fails to be written, like a full flash page
+        failing: Option<usize>,
+    }
+
+    impl PropStore for TestStore {
+        fn read(&mut self, slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+            Ok(self.slots[slot].unwrap_or([0; MESSAGE_LENGTH]))
+        }
+        fn write(&mut self, slot: usize, bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError> {
+            if self.failing == Some(slot) {
+                return Err(CommandError::Failed)
+            }
+            self.slots[slot] = Some(bytes);
+            Ok(())
+        }
+    }
+
+    fn text(value: &str) -> FieldValue {
+        FieldValue::from_message(&NAME, value.as_bytes())
+    }
+
+    #[test]
+    fn transaction_commit_and_rollback() {
+        let mut store = TestStore::default();
+        let mut tx = Transaction::<2>::new();
+        assert_eq!(Err(CommandError::Failed), tx.stage(0, text("a")));
+
+        tx.begin();
+        tx.stage(0, text("a")).unwrap();
+        tx.stage(1, text("b")).unwrap();
+        tx.stage(0, text("c")).unwrap();
+        assert_eq!(Err(CommandError::Failed), tx.stage(2, text("d")));
+        assert_eq!("c", tx.get(0).unwrap().get_text());
+
+        // a failed validation discards the staged values
+        assert_eq!(Err(CommandError::InvalidData), tx.commit(&mut store, |_, _| Err(CommandError::InvalidData)));
+        assert!(!tx.is_active());
+        assert!(store.slots.iter().all(Option::is_none));
+
+        tx.begin();
+        tx.stage(1, text("b")).unwrap();
+        tx.commit(&mut store, |tx, _| if tx.get(1).is_some() { Ok(()) } else { Err(CommandError::InvalidData) }).unwrap();
+        assert_eq!(b"\x01b", &store.slots[1].unwrap()[..2]);
+        assert_eq!(Err(CommandError::Failed), tx.commit(&mut store, |_, _| Ok(())));
+    }
+
+    #[test]
+    fn transaction_rollback_on_write_error() {
+        let mut store = TestStore::default();
+        store.write(0, text("old").into_store_bytes()).unwrap();
+        store.failing = Some(2);
+
+        let mut tx = Transaction::<3>::new();
+        tx.begin();
+        tx.stage(0, text("a")).unwrap();
+        tx.stage(1, text("b")).unwrap();
+        tx.stage(2, text("c")).unwrap();
+        assert_eq!(Err(CommandError::Failed), tx.commit(&mut store, |_, _| Ok(())));
+        assert!(!tx.is_active());
+        // the slots written before the failing one are restored
+        assert_eq!("old", FieldValue::from_store(&NAME, store.slots[0].unwrap()).get_text());
+        assert_eq!(Some([0; MESSAGE_LENGTH]), store.slots[1]);
+        assert_eq!(None, store.slots[2]);
+    }
+}
@@ -1,7 +1,10 @@
 use core::prelude::rust_2024::{*};
 
+use core::net::{Ipv4Addr, Ipv6Addr};
 use num_enum::{TryFromPrimitive};
 
+use crate::entry::options::OptionDefaults;
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, strum::Display, TryFromPrimitive)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -12,7 +15,18 @@ pub enum ValueType {
     Status = 'c' as u8,
     Bytes = 'b' as u8,
     Options = 'o' as u8,
-    Toggle = 'x' as u8
+    Toggle = 'x' as u8,
+    Ipv4 = '4' as u8,
+    Ipv6 = '6' as u8,
+    Mac = 'h' as u8,
+    Port = 'p' as u8,
+    Time = 'T' as u8,
+    Date = 'd' as u8,
+    Timestamp = 'u' as u8,
+    Duration = 'D' as u8,
+    Color = 'r' as u8,
+    /// Large values read and written in chunks, see [`BlobCommand`](crate::blob::BlobCommand)
+    Blob = 'B' as u8,
 }
 impl ValueType {
     pub(crate) fn is_options(&self) -> bool {
@@ -21,6 +35,23 @@ impl ValueType {
             _ => false
         }
     }
+
+    /// The number of bytes used by value types that have a fixed size encoding
+    pub const fn fixed_len(&self) -> Option<usize> {
+        match self {
+            Self::Integer => Some(8),
+            Self::Toggle => Some(1),
+            Self::Ipv4 => Some(4),
+            Self::Ipv6 => Some(16),
+            Self::Mac => Some(6),
+            Self::Port => Some(2),
+            Self::Time => Some(4),
+            Self::Date => Some(4),
+            Self::Timestamp => Some(8),
+            Self::Duration => Some(4),
+            _ => None
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -30,8 +61,20 @@ pub enum DefaultValue {
     Text(&'static str),
     Integer(i64),
     Options(&'static [u16]),
+    /// Options given as [`OptionEnum`](crate::entry::options::OptionEnum) values
+    Enum(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] &'static dyn OptionDefaults),
     Bytes(&'static [u8]),
-    Enabled(bool)
+    Enabled(bool),
+    Ipv4([u8; 4]),
+    Ipv6([u8; 16]),
+    Mac([u8; 6]),
+    Port(u16),
+    Time(TimeOfDay),
+    Date(Date),
+    Timestamp(i64),
+    /// Duration in seconds
+    Duration(u32),
+    Color(Color),
 }
 
 impl From<&'static str> for DefaultValue {
@@ -50,4 +93,143 @@ impl From<&'static [u8]> for DefaultValue {
     fn from(value: &'static [u8]) -> Self {
         Self::Bytes(value)
     }
-}
\ No newline at end of file
+}
+
+impl From<Ipv4Addr> for DefaultValue {
+    fn from(value: Ipv4Addr) -> Self {
+        Self::Ipv4(value.octets())
+    }
+}
+
+impl From<Ipv6Addr> for DefaultValue {
+    fn from(value: Ipv6Addr) -> Self {
+        Self::Ipv6(value.octets())
+    }
+}
+
+/// A wall clock time, encoded as the number of seconds since midnight
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl TimeOfDay {
+    pub const fn new(hour: u8, minute: u8, second: u8) -> Self {
+        if hour > 23 || minute > 59 || second > 59 { panic!("invalid time of day") }
+        Self { hour, minute, second }
+    }
+
+    pub const fn from_seconds(seconds: u32) -> Self {
+        let seconds = seconds % 86400;
+        Self {
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    pub const fn as_seconds(&self) -> u32 {
+        self.hour as u32 * 3600 + self.minute as u32 * 60 + self.second as u32
+    }
+}
+
+/// A calendar date, encoded as a little endian year followed by month and day
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    pub const fn new(year: u16, month: u8, day: u8) -> Self {
+        let date = Self { year, month, day };
+        if !date.is_valid() { panic!("invalid date") }
+        date
+    }
+
+    /// Number of days in the month, 0 if the month is out of range
+    pub const fn days_in_month(year: u16, month: u8) -> u8 {
+        let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => 0,
+        }
+    }
+
+    pub const fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            year: u16::from_le_bytes([bytes[0], bytes[1]]),
+            month: bytes[2],
+            day: bytes[3],
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 4] {
+        let year = self.year.to_le_bytes();
+        [year[0], year[1], self.month, self.day]
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        self.day >= 1 && self.day <= Self::days_in_month(self.year, self.month)
+    }
+}
+
+/// The channels used by a [`ValueType::Color`] field
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ColorFormat {
+    Rgb = 0,
+    Rgba = 1,
+    Rgbw = 2,
+}
+
+impl ColorFormat {
+    pub const fn channels(&self) -> usize {
+        match self {
+            Self::Rgb => 3,
+            Self::Rgba | Self::Rgbw => 4,
+        }
+    }
+}
+
+/// An RGB888 colour with an optional fourth channel, which is either alpha or white
+/// depending on the [`ColorFormat`] of the field
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub ext: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, ext: 0 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, ext: a }
+    }
+
+    pub const fn rgbw(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self { r, g, b, ext: w }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.ext]
+    }
+
+    pub const fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self { r: bytes[0], g: bytes[1], b: bytes[2], ext: bytes[3] }
+    }
+}
@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 
 mod derive;
+mod option_enum;
 
 #[proc_macro_derive(Proto, attributes(section))]
 pub fn derive_elytra_proto(input: TokenStream) -> TokenStream {
@@ -10,4 +11,9 @@ pub fn derive_elytra_proto(input: TokenStream) -> TokenStream {
 #[proc_macro_attribute]
 pub fn section(input: TokenStream, annotated_item: TokenStream) -> TokenStream {
     derive::section(input, annotated_item)
-}
\ No newline at end of file
+}
+
+#[proc_macro_derive(OptionEnum, attributes(option))]
+pub fn derive_option_enum(input: TokenStream) -> TokenStream {
+    option_enum::derive_option_enum(input)
+}
@@ -0,0 +1,90 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitStr, parse_macro_input, spanned::Spanned};
+
+/// Implements `OptionValueProvider` and `OptionEnum` for a `#[repr(u16)]` unit enum.
+///
+/// Option names default to the variant identifier and can be overridden using
+/// `#[option(name = "...")]` on the variant.
+pub fn derive_option_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new(input.span(), "OptionEnum can only be derived for enums"));
+    };
+
+    let is_repr_u16 = input.attrs.iter()
+        .filter(|a| a.path().is_ident("repr"))
+        .any(|a| a.parse_args::<syn::Ident>().is_ok_and(|r| r == "u16"));
+    if !is_repr_u16 {
+        return Err(Error::new(ident.span(), "OptionEnum requires #[repr(u16)]"));
+    }
+
+    let mut variants = Vec::new();
+    let mut names = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new(variant.span(), "OptionEnum variants cannot have fields"));
+        }
+        if let Some((_, discriminant)) = &variant.discriminant {
+            return Err(Error::new(discriminant.span(), "OptionEnum variants cannot have explicit discriminants"));
+        }
+        let mut name = LitStr::new(&variant.ident.to_string(), variant.ident.span());
+        for attr in variant.attrs.iter().filter(|a| a.path().is_ident("option")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported option attribute"))
+                }
+            })?;
+        }
+        variants.push(&variant.ident);
+        names.push(name);
+    }
+
+    let Some(first) = variants.first() else {
+        return Err(Error::new(ident.span(), "OptionEnum needs at least one variant"));
+    };
+    let count = variants.len();
+    let indices = (0..count).collect::<Vec<_>>();
+    let option_indices = (0..count as u16).collect::<Vec<_>>();
+
+    Ok(quote! {
+        impl elytra_conf::entry::options::OptionValueProvider for #ident {
+            fn get(&self, index: usize) -> Option<&'static str> {
+                match index {
+                    #(#indices => Some(#names),)*
+                    _ => None
+                }
+            }
+
+            fn len(&self) -> usize {
+                #count
+            }
+        }
+
+        impl elytra_conf::entry::options::OptionEnum for #ident {
+            const PROVIDER: &'static dyn elytra_conf::entry::options::OptionValueProvider = &Self::#first;
+
+            fn from_option(index: u16) -> Option<Self> {
+                match index {
+                    #(#option_indices => Some(Self::#variants),)*
+                    _ => None
+                }
+            }
+
+            fn to_option(self) -> u16 {
+                self as u16
+            }
+        }
+    })
+}
@@ -0,0 +1,37 @@
+use elytra_conf::{entry::{option_enum, options::OptionEnum, EntryDesc}, field::FieldValue};
+use elytra_macros::OptionEnum;
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, OptionEnum)]
+enum Unit {
+    Celsius,
+    #[option(name = "°F")]
+    Fahrenheit,
+    Kelvin,
+}
+
+#[test]
+fn names_and_indices() {
+    assert_eq!(3, Unit::PROVIDER.len());
+    assert_eq!(Some("Celsius"), Unit::PROVIDER.get(0));
+    assert_eq!(Some("°F"), Unit::PROVIDER.get(1));
+    assert_eq!(None, Unit::PROVIDER.get(3));
+
+    for (index, unit) in [Unit::Celsius, Unit::Fahrenheit, Unit::Kelvin].into_iter().enumerate() {
+        assert_eq!(index as u16, unit.to_option());
+        assert_eq!(Some(unit), Unit::from_option(index as u16));
+    }
+    assert_eq!(None, Unit::from_option(3));
+}
+
+const UNITS: EntryDesc = option_enum::<Unit>("Units")
+    .with_max_options(2)
+    .with_default_enum(&[Unit::Kelvin, Unit::Fahrenheit])
+    .as_entry();
+
+#[test]
+fn typed_defaults() {
+    let fv = FieldValue::from_store(&UNITS, [0; 64]);
+    assert_eq!(&[2, 1], fv.get_options().as_slice());
+    assert_eq!(Some(Unit::Kelvin), fv.get_enum::<Unit>());
+}
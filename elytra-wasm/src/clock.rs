@@ -0,0 +1,12 @@
+use core::time::Duration;
+
+#[link(wasm_import_module = "elytra")]
+unsafe extern "C" {
+    /// Milliseconds since the Unix epoch, as told by the host
+    fn now_millis() -> u64;
+}
+
+/// The current time as the duration since the Unix epoch, from the host running the module
+pub fn now() -> Duration {
+    Duration::from_millis(unsafe { now_millis() })
+}
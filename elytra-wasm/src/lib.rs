@@ -1,5 +1,13 @@
 use std::io::Write;
 
+pub mod store;
+pub mod logger;
+pub mod clock;
+
+pub use store::WasmStore;
+pub use logger::init_logger;
+pub use clock::now;
+
 #[macro_export]
 macro_rules! elytra_wasm {
     ( $e:expr ) => {
@@ -11,6 +19,7 @@ macro_rules! elytra_wasm {
         #[allow(unused)]
         #[unsafe(no_mangle)]
         pub extern "C" fn send(a: u64, b: u64, c: u64, d: u64, e: u64, f: u64, g: u64, h: u64) -> u8 {
+            elytra_wasm::init_logger();
             let in_bytes = elytra_wasm::unpack64([a, b, c, d, e, f, g, h]);
             let res = match elytra_conf::command::Command::from_bytes(&in_bytes) {
                 Ok(command) => $e(command),
@@ -0,0 +1,38 @@
+use std::sync::Once;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+#[link(wasm_import_module = "elytra")]
+unsafe extern "C" {
+    /// Logs the UTF-8 message at `ptr` with the [`log::Level`] as a number, 1 being the most severe
+    fn log_message(level: u32, ptr: *const u8, len: usize);
+}
+
+/// A [`log`] backend forwarding the records to the host running the module
+pub struct HostLogger;
+
+impl Log for HostLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+        unsafe { log_message(record.level() as u32, message.as_ptr(), message.len()) }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: HostLogger = HostLogger;
+static INIT: Once = Once::new();
+
+/// Installs the [`HostLogger`], unless another logger already has been.
+/// Called by [`elytra_wasm!`](crate::elytra_wasm) before handling each command.
+pub fn init_logger() {
+    INIT.call_once(|| {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(LevelFilter::Trace);
+        }
+    });
+}
@@ -0,0 +1,76 @@
+use elytra_conf::{command::CommandError, config::MESSAGE_LENGTH, store::PropStore};
+
+use crate::{pack64, unpack64};
+
+#[link(wasm_import_module = "elytra")]
+unsafe extern "C" {
+    /// Reads 8 bytes of the stored value at `index` (0-7), all zeroes if the slot has not been written
+    fn store_read(slot: u32, index: u32) -> u64;
+    /// Writes the packed value, returns 0 if the host failed to persist it
+    fn store_write(slot: u32, a: u64, b: u64, c: u64, d: u64, e: u64, f: u64, g: u64, h: u64) -> u32;
+    /// Length of the blob at `slot`, 0 if it has not been written
+    fn blob_len(slot: u32) -> u32;
+    /// Copies up to `len` bytes of the blob from `offset` to `ptr`, returns the number of bytes copied
+    fn blob_read(slot: u32, offset: u32, ptr: *mut u8, len: usize) -> u32;
+    /// Writes `len` bytes from `ptr` to the blob at `offset`, returns 0 if the host failed to persist them
+    fn blob_write(slot: u32, offset: u32, ptr: *const u8, len: usize) -> u32;
+    /// Truncates the blob to `len` bytes, returns 0 if the host failed to persist it
+    fn blob_truncate(slot: u32, len: u32) -> u32;
+}
+
+/// A [`PropStore`] that keeps prop values and blobs in the host running the module,
+/// so that they persist across restarts of it.
+#[derive(Default, Debug)]
+pub struct WasmStore;
+
+impl WasmStore {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+fn persisted(res: u32) -> Result<(), CommandError> {
+    match res {
+        0 => Err(CommandError::Failed),
+        _ => Ok(()),
+    }
+}
+
+impl PropStore for WasmStore {
+    fn read(&mut self, slot: usize) -> Result<[u8; MESSAGE_LENGTH], CommandError> {
+        let slot = u32::try_from(slot).map_err(|_| CommandError::InvalidField)?;
+        let packed = core::array::from_fn(|i| unsafe { store_read(slot, i as u32) });
+        Ok(unpack64(packed))
+    }
+
+    fn write(&mut self, slot: usize, bytes: [u8; MESSAGE_LENGTH]) -> Result<(), CommandError> {
+        let slot = u32::try_from(slot).map_err(|_| CommandError::InvalidField)?;
+        let [a, b, c, d, e, f, g, h] = pack64(bytes);
+        persisted(unsafe { store_write(slot, a, b, c, d, e, f, g, h) })
+    }
+
+    fn blob_len(&mut self, slot: usize) -> Result<u32, CommandError> {
+        let slot = u32::try_from(slot).map_err(|_| CommandError::InvalidField)?;
+        Ok(unsafe { blob_len(slot) })
+    }
+
+    fn read_blob(&mut self, slot: usize, offset: u32, buf: &mut [u8]) -> Result<usize, CommandError> {
+        if offset > self.blob_len(slot)? {
+            return Err(CommandError::InvalidData)
+        }
+        let len = unsafe { blob_read(slot as u32, offset, buf.as_mut_ptr(), buf.len()) };
+        Ok(len as usize)
+    }
+
+    fn write_blob(&mut self, slot: usize, offset: u32, data: &[u8]) -> Result<(), CommandError> {
+        if offset > self.blob_len(slot)? {
+            return Err(CommandError::InvalidData)
+        }
+        persisted(unsafe { blob_write(slot as u32, offset, data.as_ptr(), data.len()) })
+    }
+
+    fn truncate_blob(&mut self, slot: usize, len: u32) -> Result<(), CommandError> {
+        let slot = u32::try_from(slot).map_err(|_| CommandError::InvalidField)?;
+        persisted(unsafe { blob_truncate(slot, len) })
+    }
+}
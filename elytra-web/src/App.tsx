@@ -1,11 +1,11 @@
 /// <reference types="@types/w3c-web-usb" />
 import { FC, useState, useEffect } from 'react'
 
-import { Box, Button, Card, CardBody, CardHeader, Flex, Heading, Spinner, Alert, AlertDescription, AlertTitle, Group, Textarea, CardDescription, Show, HStack, IconButton, Menu, Portal, VStack, Text, Stack, DataList, Link } from "@chakra-ui/react";
-import { SaveIcon, UsbIcon, UnplugIcon, BugIcon, WashingMachineIcon, UploadCloudIcon, ChevronDownIcon } from "lucide-react";
+import { Box, Button, Card, CardBody, CardHeader, Flex, Heading, Spinner, Alert, AlertDescription, AlertTitle, Group, Textarea, CardDescription, Show, HStack, IconButton, Menu, Portal, VStack, Text, Stack, DataList, Link, Separator } from "@chakra-ui/react";
+import { SaveIcon, UsbIcon, UnplugIcon, BugIcon, WashingMachineIcon, UploadCloudIcon, ChevronDownIcon, CableIcon } from "lucide-react";
 import { DynamicIcon, IconName } from 'lucide-react/dynamic';
-import { FieldDesc, EntryTypes, ElytraConfig, DeviceInfo } from './types';
-import { cloudWasmFileDevice, Elytra, ElytraUSBDevice, localWasmFileDevice } from './elytra';
+import { FieldDesc, EntryTypes, ElytraConfig, DeviceInfo, LayoutTypes } from './types';
+import { cloudWasmFileDevice, Elytra, ElytraUSBDevice, localHelperDevice, localWasmFileDevice } from './elytra';
 import { ElytraField } from './components'
 
 type Status = 'Disconnected' | 'Connecting' | 'Connected';
@@ -57,6 +57,7 @@ export const App = () => {
     try {
       const device = deviceStr === 'usb' ? new ElytraUSBDevice() 
         : deviceStr === "upload" ? await localWasmFileDevice() 
+        : deviceStr === "helper" ? localHelperDevice()
         : await cloudWasmFileDevice(deviceStr);
       await device.init();
       const proto = new Elytra(device);
@@ -146,12 +147,23 @@ export const App = () => {
                 {section.help ? <CardDescription>{section.help}</CardDescription> : <></>}
               </CardHeader>
               <CardBody display='flex' flexDir='column' gap={4}>
-                {section.fields.map(field => <>
-                  <ElytraField field={field}
-                    onReloadValue={() => reloadValue(field)}
-                    value={fieldValues[field.valueKey]} 
-                    onValueChange={(v) => setFieldValues(cv => ({ ...cv, [field.valueKey]: v }))} />
-                </>)}
+                {section.layout.map(key => {
+                  const field = config.fields[key];
+                  if (field) return <>
+                    <ElytraField field={field}
+                      onReloadValue={() => reloadValue(field)}
+                      value={fieldValues[field.valueKey]} 
+                      onValueChange={(v) => setFieldValues(cv => ({ ...cv, [field.valueKey]: v }))} />
+                  </>;
+                  const index = Number(key.substring(1));
+                  switch (key[0]) {
+                    case LayoutTypes.Heading: return <Heading size='sm'>{config.headings[key]}</Heading>;
+                    case LayoutTypes.Separator: return <Separator />;
+                    case EntryTypes.Section: return <Text color="fg.muted">{sections[index]?.name}</Text>;
+                    case EntryTypes.Action: return <Text color="fg.muted">{config.actions[index]?.name}</Text>;
+                    default: return <></>;
+                  }
+                })}
                 {section.fields.some(field => !field.flags.readonly) ? (
                   <Button paddingX={10} colorPalette="brand" onClick={() => doSaveSection(si)}><SaveIcon /> Save</Button>
                 ) : <></>}
@@ -207,7 +219,7 @@ const z = (n: number) => n.toString(10).padStart(2, '0');
 
 const ConnectStatus: FC<{
   status: Status, error?: string,
-  onConnect: (device: "usb" | "upload" | string) => void, onDisconnect: () => void
+  onConnect: (device: "usb" | "upload" | "helper" | string) => void, onDisconnect: () => void
 
 }> = (props) => {
 
@@ -256,6 +268,10 @@ const ConnectStatus: FC<{
                     <UploadCloudIcon />
                     Upload WASM Dummy file
                   </Menu.Item>
+                  <Menu.Item value="helper">
+                    <CableIcon />
+                    Connect via Local Helper
+                  </Menu.Item>
                 </Menu.Content>
               </Menu.Positioner>
             </Portal>
@@ -5,7 +5,9 @@ import {
     RwEntryType,
     EntryName,
     Command,
-    ElytraConfig
+    ElytraConfig,
+    LayoutType,
+    LayoutTypes
 } from "./types";
 
 export class Elytra {
@@ -43,15 +45,40 @@ export class Elytra {
       const actions = await this.readActionInfo(action_count);
 
       const layouts = await this.queryLayouts(section_count);
-      const sectionsWithLayout = sections.map((s, si) => ({
-        ...s, 
-        // fields: layouts[si].map(([l, li]) => `${l}${li}`)
-        fields: layouts[si].map(([l, li]) => l === EntryTypes.Prop ? propFields[li] : infoFields[li])
-      }));
-      const fields = {
+      const fields: Record<string, FieldDesc> = {
         ...(Object.fromEntries(propFields.map((f,i) => [`${EntryTypes.Prop}${i}`, f]))),
         ...(Object.fromEntries(infoFields.map((f,i) => [`${EntryTypes.Info}${i}`, f]))),
       }
+      const headings: Record<string, string> = {};
+      const sectionsWithLayout = await Promise.all(sections.map(async (s, si) => {
+        const layout: string[] = [];
+        for (const [pos, [l, li]] of layouts[si].entries()) {
+          switch (l) {
+            case EntryTypes.Prop:
+            case EntryTypes.Info:
+            case EntryTypes.Section:
+            case EntryTypes.Action:
+              layout.push(`${l}${li}`);
+              break;
+            case LayoutTypes.Heading: {
+              const key = `${l}${si}.${pos}`;
+              headings[key] = await this.queryExtraText(EntryTypes.Section, si, QueryTargets.Heading, pos) ?? '';
+              layout.push(key);
+              break;
+            }
+            case LayoutTypes.Separator:
+              layout.push(`${l}${si}.${pos}`);
+              break;
+            default:
+              console.warn('Unknown layout item %o in section %o', l, si);
+          }
+        }
+        return {
+          ...s,
+          fields: layout.filter(key => key in fields).map(key => fields[key]),
+          layout,
+        };
+      }));
 
 
       console.info("Layouts: %o", layouts);
@@ -63,6 +90,7 @@ export class Elytra {
         },
         sections: sectionsWithLayout,
         fields,
+        headings,
         actions,
       }
     }
@@ -187,11 +215,11 @@ export class Elytra {
 
     private parseEntry(dv: DataView, fieldIndex: number, entryType: EntryType): FieldDesc {
         return {
-            flags: parseFlags(dv.getUint8(0)),
-            valueType: String.fromCharCode(dv.getUint8(1)),
-            min: Number(dv.getInt32(2, true)),
-            max: Number(dv.getInt32(6, true)),
-            name: this.decodeZeroPadString(dv.buffer.slice(10)),
+            flags: parseFlags(dv.getUint16(0, true)),
+            valueType: String.fromCharCode(dv.getUint8(2)),
+            min: Number(dv.getInt32(3, true)),
+            max: Number(dv.getInt32(7, true)),
+            name: this.decodeZeroPadString(dv.buffer.slice(11)),
             options: [],
             valueKey: entryType + fieldIndex,
             entryIndex: fieldIndex,
@@ -248,6 +276,7 @@ export class Elytra {
             flags: entry.flags,
             name: entry.name,
             fields: [],
+            layout: [],
             help: entry.help,
             icon: entry.icon,
         }));
@@ -262,7 +291,7 @@ export class Elytra {
                 const entryType = dv.getUint8(i * 2);
                 if (entryType === 0) break;
                 const fieldIndex = dv.getUint8((i * 2) + 1);
-                fieldRefs[i] = [String.fromCharCode(entryType) as EntryType, fieldIndex];
+                fieldRefs[i] = [String.fromCharCode(entryType) as LayoutType, fieldIndex];
             }
             return fieldRefs;
         });
@@ -440,6 +469,58 @@ export class ElytraUSBDevice implements ElytraDevice {
     }
 }
 
+/** Prop values stored by a wasm module, by slot. Kept in localStorage if available, in memory otherwise */
+class WasmStore {
+    private prefix: string;
+    private memory = new Map<string, Uint8Array>();
+
+    constructor(sourceName: string) {
+        this.prefix = `elytra-wasm:${sourceName}:`;
+    }
+
+    private get storage(): Storage | undefined {
+        try {
+            return window.localStorage;
+        } catch {
+            return undefined;
+        }
+    }
+
+    private load(key: string): Uint8Array | undefined {
+        const storage = this.storage;
+        if (!storage) return this.memory.get(key);
+        const hex = storage.getItem(this.prefix + key);
+        return hex ? Uint8Array.from(hex.match(/../g), b => parseInt(b, 16)) : undefined;
+    }
+
+    private save(key: string, value: Uint8Array | undefined) {
+        const storage = this.storage;
+        if (!storage) {
+            value ? this.memory.set(key, value) : this.memory.delete(key);
+        } else if (!value) {
+            storage.removeItem(this.prefix + key);
+        } else {
+            storage.setItem(this.prefix + key, Array.from(value, b => b.toString(16).padStart(2, '0')).join(''));
+        }
+    }
+
+    get(slot: number): Uint8Array | undefined {
+        return this.load(`${slot}`);
+    }
+
+    set(slot: number, value: Uint8Array) {
+        this.save(`${slot}`, value.every(b => b === 0) ? undefined : value);
+    }
+
+    getBlob(slot: number): Uint8Array {
+        return this.load(`blob:${slot}`) ?? new Uint8Array(0);
+    }
+
+    setBlob(slot: number, blob: Uint8Array) {
+        this.save(`blob:${slot}`, blob.length ? blob : undefined);
+    }
+}
+
 export class ElytraWasmDevice implements ElytraDevice {
     sourceName: string;
     bytes: BufferSource;
@@ -454,18 +535,91 @@ export class ElytraWasmDevice implements ElytraDevice {
     send: Function;
     recieve: Function;
 
+    /** Functions the module can import from the "elytra" module, like the native host provides them */
+    hostImports(): Record<string, Function> {
+        const store = new WasmStore(this.sourceName);
+        return {
+            store_read: (slot: number, index: number): bigint => {
+                const value = store.get(slot);
+                return value ? new DataView(value.buffer).getBigUint64(index * 8, false) : 0n;
+            },
+            store_write: (slot: number, ...packed: bigint[]): number => {
+                const value = new Uint8Array(64);
+                const dv = new DataView(value.buffer);
+                packed.forEach((p, i) => dv.setBigUint64(i * 8, BigInt.asUintN(64, p), false));
+                try {
+                    store.set(slot, value);
+                    return 1;
+                } catch (e) {
+                    console.warn("Failed to save the value of slot %o: %o", slot, e);
+                    return 0;
+                }
+            },
+            blob_len: (slot: number): number => store.getBlob(slot).length,
+            blob_read: (slot: number, offset: number, ptr: number, len: number): number => {
+                const memory = this.instance?.exports.memory as WebAssembly.Memory | undefined;
+                const data = store.getBlob(slot).subarray(offset, offset + len);
+                if (!memory || ptr + data.length > memory.buffer.byteLength) return 0;
+                new Uint8Array(memory.buffer, ptr, data.length).set(data);
+                return data.length;
+            },
+            blob_write: (slot: number, offset: number, ptr: number, len: number): number => {
+                const memory = this.instance?.exports.memory as WebAssembly.Memory | undefined;
+                const blob = store.getBlob(slot);
+                if (!memory || ptr + len > memory.buffer.byteLength || offset > blob.length) return 0;
+                const written = new Uint8Array(Math.max(blob.length, offset + len));
+                written.set(blob);
+                written.set(new Uint8Array(memory.buffer, ptr, len), offset);
+                try {
+                    store.setBlob(slot, written);
+                    return 1;
+                } catch (e) {
+                    console.warn("Failed to save the blob of slot %o: %o", slot, e);
+                    return 0;
+                }
+            },
+            blob_truncate: (slot: number, len: number): number => {
+                try {
+                    store.setBlob(slot, store.getBlob(slot).slice(0, len));
+                    return 1;
+                } catch (e) {
+                    console.warn("Failed to save the blob of slot %o: %o", slot, e);
+                    return 0;
+                }
+            },
+            log_message: (level: number, ptr: number, len: number) => {
+                // the memory isn't known yet while the start function runs
+                const memory = this.instance?.exports.memory as WebAssembly.Memory | undefined;
+                const message = memory && ptr + len <= memory.buffer.byteLength
+                    ? new TextDecoder().decode(new Uint8Array(memory.buffer, ptr, len))
+                    : "<invalid log message>";
+                const log = [console.error, console.warn, console.info, console.debug][level - 1] ?? console.debug;
+                log("[%s] %s", this.sourceName, message);
+            },
+            now_millis: (): bigint => BigInt(Date.now()),
+        };
+    }
+
     async init() {
         if (this.initFailed || this.instance) return;
 
-        const results = await WebAssembly.instantiate(this.bytes, {});
-        if (typeof results.instance.exports['send'] !== 'function' 
-                || typeof  results.instance.exports['recieve'] !== 'function') {
+        const module = await WebAssembly.compile(this.bytes);
+        const imports = { elytra: this.hostImports() };
+        for (const { module: from, name } of WebAssembly.Module.imports(module)) {
+            if (!imports[from]?.[name]) {
+                this.initFailed = true;
+                throw new Error(`The module imports "${from}.${name}", which this page doesn't provide`);
+            }
+        }
+        const instance = await WebAssembly.instantiate(module, imports);
+        if (typeof instance.exports['send'] !== 'function' 
+                || typeof  instance.exports['recieve'] !== 'function') {
             this.initFailed = true;
             throw new Error("provided file is not a valid elytra wasm dummy")
         }
-        this.send = results.instance.exports.send;
-        this.recieve = results.instance.exports.recieve;
-        this.instance = results.instance;
+        this.send = instance.exports.send as Function;
+        this.recieve = instance.exports.recieve as Function;
+        this.instance = instance;
     }
 
     sync(): Promise<ArrayBufferLike> {
@@ -514,6 +668,71 @@ export class ElytraWasmDevice implements ElytraDevice {
     }
 }
 
+/** Device behind a local helper (`elytra-server --ws`), which forwards the frames to it */
+export class ElytraWebSocketDevice implements ElytraDevice {
+    url: string;
+    private socket: WebSocket;
+    private pending: { resolve: (data: ArrayBufferLike) => void, reject: (error: Error) => void }[] = [];
+
+    constructor(url: string) {
+        this.url = url;
+    }
+
+    init(): Promise<void> {
+        return new Promise((resolve, reject) => {
+            const socket = new WebSocket(this.url);
+            socket.binaryType = 'arraybuffer';
+            socket.onopen = () => {
+                this.socket = socket;
+                resolve();
+            };
+            socket.onerror = () => reject(new Error(`Failed to connect to ${this.url}`));
+            socket.onmessage = (event) => this.pending.shift()?.resolve(event.data);
+            socket.onclose = (event) => {
+                const error = new Error(`Connection closed: ${event.reason || event.code}`);
+                this.pending.splice(0).forEach(p => p.reject(error));
+            };
+        });
+    }
+
+    private next(): Promise<ArrayBufferLike> {
+        return new Promise((resolve, reject) => {
+            if (this.socket?.readyState !== WebSocket.OPEN) {
+                return reject(new Error("Not connected"));
+            }
+            this.pending.push({ resolve, reject });
+        });
+    }
+
+    exchange(input: ArrayBuffer): Promise<ArrayBufferLike> {
+        const response = this.next();
+        this.socket?.send(input);
+        return response;
+    }
+
+    sync(): Promise<ArrayBufferLike> {
+        return this.next();
+    }
+
+    async disconnect() {
+        this.socket?.close();
+    }
+
+    name(): string {
+        return new URL(this.url).host;
+    }
+
+    manufacturer(): string {
+        return "Local Helper";
+    }
+}
+
+export const localHelperDevice = () => {
+    const url = prompt("Local helper address", "ws://localhost:48001");
+    if (!url) throw new Error("No local helper address given");
+    return new ElytraWebSocketDevice(url);
+}
+
 export const localWasmFileDevice = async () => {
     if (!('showOpenFilePicker' in window && typeof window.showOpenFilePicker === "function")) {
         throw new Error("showOpenFilePicker is not supported by the browser")
@@ -3,6 +3,11 @@ import { Action, ActionVariants, EntryType, EntryTypes, ExtraFlags, FieldDesc, S
 const fieldFromRef = (propFields: FieldDesc[], infoFields: FieldDesc[]): (value: readonly [EntryType, number]) => FieldDesc =>
     (([et, index]) => et === EntryTypes.Info ? infoFields[index] : propFields[index]);
 
+const withLayout = (refs: (readonly [EntryType, number])[]) => ({
+    fields: refs.map(fieldFromRef(MOCK_PROP_FIELDS, MOCK_INFO_FIELDS)),
+    layout: refs.map(([et, index]) => `${et}${index}`),
+});
+
 const FLAGS_RO: ExtraFlags = {hasHelp: false, hasIcon: false, readonly: true, hasOptions: false} as const;
 const FLAGS_RW = {...FLAGS_RO, readonly: false} as const;
 
@@ -83,26 +88,26 @@ export const MOCK_SECTIONS: Section[] = [
     {
         flags: FLAGS_RO,
         "name": "WiFi",
-        fields: [
+        ...withLayout([
             [EntryTypes.Info, 0] as const,
             [EntryTypes.Prop, 0] as const,
             [EntryTypes.Prop, 1] as const,
-        ].map(fieldFromRef(MOCK_PROP_FIELDS, MOCK_INFO_FIELDS))
+        ])
     },
     {
         flags: FLAGS_RO,
         "name": "Display",
-        fields: [
+        ...withLayout([
             [EntryTypes.Prop, 2] as const,
-        ].map(fieldFromRef(MOCK_PROP_FIELDS, MOCK_INFO_FIELDS))
+        ])
     },
     {
         flags: FLAGS_RO,
         "name": "Hardware Info",
-        fields: [
+        ...withLayout([
             [EntryTypes.Info, 1] as const,
             [EntryTypes.Info, 2] as const
-        ].map(fieldFromRef(MOCK_PROP_FIELDS, MOCK_INFO_FIELDS))
+        ])
     }
 ];
 
@@ -8,6 +8,8 @@ export interface EntryBase {
 export interface Section extends EntryBase {
   icon?: string | undefined;
   fields: FieldDesc[];
+  /** Keys of the layout items in order, `${type}${index}` for entries and `${type}${section}.${position}` for headings */
+  layout: string[];
 }
 export interface FieldDesc extends EntryBase {
   options: string[];
@@ -39,7 +41,7 @@ export interface EntryCommandData {
     write: Command;
 }
 
-export type FieldRef = readonly [EntryType, number];
+export type FieldRef = readonly [LayoutType, number];
 
 export const ValueTypes = {
     Text: 't',
@@ -66,6 +68,13 @@ export const EntryTypes = {
 } as const;
 export type EntryType = typeof EntryTypes[keyof typeof EntryTypes];
 
+/** Layout items that are not entries, headings have their text read with a `Heading` query */
+export const LayoutTypes = {
+    Heading: 't',
+    Separator: '-'
+} as const;
+export type LayoutType = EntryType | typeof LayoutTypes[keyof typeof LayoutTypes];
+
 export const Commands = {
     Read: 'r',
     Write: 'w',
@@ -82,7 +91,8 @@ export const QueryTargets = {
     Icon: 'i',
     Help: 'h',
     Layout: 'l',
-    Option: 'o'
+    Option: 'o',
+    Heading: 't'
 } as const;
 export type QueryTarget = typeof QueryTargets[keyof typeof QueryTargets];
 
@@ -109,6 +119,7 @@ export const EntryName: Record<EntryType, string> = {
 export interface ElytraConfig {
     sections: Section[];
     fields: Record<string, FieldDesc>;
+    headings: Record<string, string>;
     actions: Action[];
     deviceInfo: {
         name: string;
@@ -1,19 +1,161 @@
-use elytra_conf::{command::{CommandError, CommandResponse}, field::FieldValue, traits::*};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use chrono::Timelike;
+use std::sync::Mutex;
+use elytra_conf::{auth::{AccessLevel, Auth, Verifier}, blob::BlobCommand, command::{CommandError, CommandResponse}, config::MESSAGE_LENGTH, dfu::{Dfu, FirmwareTarget, RamPartition}, entry::EntryVariant, field::FieldValue, group::GroupInstances, job::Jobs, store::PropStore, transaction::Transaction, traits::*, values::{TimeOfDay, ValueType}};
 use log::debug;
 use crate::{Action, InfoField, MOCK_CONF, PropField, Section};
 type Command = elytra_conf::command::Command<Action, PropField, InfoField, Section>;
 
+/// Prop values are kept by the host when running as WASM, so they persist across restarts
+#[cfg(target_arch = "wasm32")]
+type Store = elytra_wasm::WasmStore;
+#[cfg(not(target_arch = "wasm32"))]
+type Store = elytra_conf::store::MemoryStore;
+
+static ALARM_INSTANCES: AtomicU64 = AtomicU64::new(0);
+static JOBS: Mutex<Jobs<2>> = Mutex::new(Jobs::new());
+static STORE: Mutex<Store> = Mutex::new(Store::new());
+static TRANSACTION: Mutex<Transaction<8>> = Mutex::new(Transaction::new());
+static AUTH: Mutex<Auth<MockPins>> = Mutex::new(Auth::new(MockPins, SESSION_TIMEOUT));
+/// Milliseconds after which an idle session expires
+const SESSION_TIMEOUT: u64 = 5 * 60 * 1000;
+static FIRMWARE: Mutex<Dfu<MockFirmware>> = Mutex::new(Dfu::new(MockFirmware { partition: RamPartition::new() }));
+
+/// Receives firmware images into RAM, as the example has no flash to boot from
+struct MockFirmware {
+    partition: RamPartition<{ 64 * 1024 }>,
+}
+
+impl FirmwareTarget for MockFirmware {
+    type Partition = RamPartition<{ 64 * 1024 }>;
+
+    fn partition(&mut self) -> &mut Self::Partition {
+        &mut self.partition
+    }
+
+    fn commit(&mut self, size: u32, crc: u32) -> Result<(), CommandError> {
+        debug!("Committed firmware image of {} bytes with CRC {:08x}", size, crc);
+        Ok(())
+    }
+}
+
+/// Hardcoded credentials, a real device would keep a hash of them in its store
+struct MockPins;
+
+impl Verifier for MockPins {
+    fn verify(&mut self, level: AccessLevel, secret: &[u8]) -> bool {
+        match level {
+            AccessLevel::Public => true,
+            AccessLevel::User => secret == b"1234",
+            AccessLevel::Admin => secret == b"elytra",
+        }
+    }
+}
+
+/// Fakes progress of the running jobs, as there is no actual work being done in the example
+fn advance_jobs(jobs: &mut Jobs<2>) {
+    let running: Vec<u8> = jobs.running().collect();
+    for id in running {
+        let progress = jobs.get(id).map(|j| j.progress()).unwrap_or_default() + 10;
+        let _ = if progress >= 100 {
+            jobs.finish(id, true, "Firmware updated")
+        } else {
+            jobs.set_progress(id, progress, "Writing firmware")
+        };
+    }
+}
+
+/// The value of a prop as staged in the transaction, or as stored when it isn't
+fn staged_or_stored(tx: &Transaction<8>, prop: PropField, store: &mut Store) -> Result<FieldValue, CommandError> {
+    match tx.get(prop.as_index()) {
+        Some(value) => Ok(value.clone()),
+        None => Ok(FieldValue::from_store(prop.get_entry(), store.read(prop.as_index())?)),
+    }
+}
+
+/// Writes that can't be staged are refused during a transaction, as reads of staged values would miss them
+fn not_in_transaction() -> Result<(), CommandError> {
+    if TRANSACTION.lock().unwrap().is_active() {
+        return Err(CommandError::NotSupported)
+    }
+    Ok(())
+}
+
+/// Cross-field validation of transactions: a WiFi network can't be set without a password
+fn validate(tx: &Transaction<8>, store: &mut Store) -> Result<(), CommandError> {
+    let network = staged_or_stored(tx, PropField::WifiNetwork, store)?;
+    let password = staged_or_stored(tx, PropField::WifiPassword, store)?;
+    if !network.get_text().is_empty() && password.get_text().is_empty() {
+        return Err(CommandError::InvalidData)
+    }
+    Ok(())
+}
+
+/// Time since the Unix epoch, told by the host when running as WASM
+#[cfg(target_arch = "wasm32")]
+fn now() -> Duration {
+    elytra_wasm::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Duration {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+}
+
+/// The current time of day in the configured timezone
+fn local_time() -> TimeOfDay {
+    let stored = STORE.lock().unwrap().read(PropField::TimeZone.as_index()).unwrap_or([0; 64]);
+    let tz = FieldValue::from_store(PropField::TimeZone.get_entry(), stored).get_text()
+        .parse().unwrap_or(chrono_tz::UTC);
+    let time = chrono::DateTime::from_timestamp(now().as_secs() as i64, 0).unwrap_or_default().with_timezone(&tz);
+    TimeOfDay::new(time.hour() as u8, time.minute() as u8, time.second() as u8)
+}
+
+fn alarm_instances() -> GroupInstances {
+    let max = Section::Alarms.get_entry().repeat_count().unwrap_or_default();
+    GroupInstances::from_bits(max, ALARM_INSTANCES.load(Ordering::Relaxed))
+}
+
+/// The store slot of a prop of an added alarm instance
+fn alarm_slot(section: Section, instance: u8, prop: PropField) -> Result<usize, CommandError> {
+    if section != Section::Alarms {
+        return Err(CommandError::InvalidSection)
+    }
+    if !alarm_instances().contains(instance) {
+        return Err(CommandError::InvalidInstance)
+    }
+    MOCK_CONF.group_slot(section, instance, prop)
+}
+
 pub fn handle_command(command: Command) -> CommandResponse {
+    let now = now().as_millis() as u64;
+    let authorized = AUTH.lock().unwrap().authorize(&command, now);
+    if let Err(e) = authorized {
+        debug!("CMD: denied: {:?}", e);
+        return CommandResponse::error(e)
+    }
     match command {
         Command::ReadProp(p) => {
             debug!("CMD: ReadProp: {:?}", p);
-            let bytes_from_store = [0u8; 64];
-            FieldValue::from_store(p.get_entry(), bytes_from_store).into()
+            if let Some(value) = TRANSACTION.lock().unwrap().get(p.as_index()) {
+                return value.clone().into()
+            }
+            STORE.lock().unwrap().read(p.as_index())
+                .map(|bytes| FieldValue::from_store(p.get_entry(), bytes).into())
+                .into()
         },
         Command::WriteProp((prop_field, field_value)) => {
             debug!("CMD: WriteProp: {:?}", prop_field);
             debug!(" => {:x?}", field_value);
-            CommandResponse::ok()
+            let mut tx = TRANSACTION.lock().unwrap();
+            let writable = MOCK_CONF.check_writable(prop_field, |prop| staged_or_stored(&tx, prop, &mut STORE.lock().unwrap()));
+            let result = writable.and_then(|_| if tx.is_active() {
+                tx.stage(prop_field.as_index(), field_value)
+            } else {
+                STORE.lock().unwrap().write(prop_field.as_index(), field_value.into_store_bytes())
+            });
+            result.map(|_| CommandResponse::ok()).into()
         },
         Command::ReadInfo(i) => {
             debug!("CMD: ReadInfo: {:?}", i);
@@ -24,7 +166,7 @@ pub fn handle_command(command: Command) -> CommandResponse {
                 FlashUUID => fv.set_bytes(&[0, 1, 2, 3, 4, 5, 6, 7]),
                 FlashJEDEC => fv.set_bytes(&[0x0a, 0xbc, 0xde, 0xf0]),
                 PicoROM => fv.set_text("ROM Version: 0 (BADC0FFE)"),
-                Time => fv.set_text("01:23"),
+                Time => fv.set_time(local_time()),
             };
             fv.into()
         },
@@ -37,18 +179,210 @@ pub fn handle_command(command: Command) -> CommandResponse {
             debug!("CMD: query: {:?} {:?}", entry_index, target);
             MOCK_CONF.handle_query(entry_index, target).into()
         },
-        Command::Action(action) => {
+        Command::Action((action, args)) => {
             debug!("CMD: action: {:?}", action);
-            CommandResponse::ok()
+            match action {
+                Action::SetTime => {
+                    let time = args.get(0).map(|t| t.get_time());
+                    debug!(" => {:?}", time);
+                    CommandResponse::ok()
+                },
+                Action::DFU => JOBS.lock().unwrap().start()
+                    .map(|id| CommandResponse::from_payload([id])).into(),
+                Action::SelfTest => match args.result() {
+                    Some(mut result) => {
+                        result.set_status(0, "All systems nominal");
+                        result.into()
+                    },
+                    None => CommandResponse::error(CommandError::Failed),
+                },
+                _ => CommandResponse::ok()
+            }
         },
         Command::Meta => {
             debug!("CMD: meta");
             MOCK_CONF.handle_meta().into()
         },
+        Command::ReadGroupProp((section, instance, prop)) => {
+            debug!("CMD: ReadGroupProp: {:?}[{}] {:?}", section, instance, prop);
+            alarm_slot(section, instance, prop)
+                .and_then(|slot| STORE.lock().unwrap().read(slot))
+                .map(|bytes| FieldValue::from_store(prop.get_entry(), bytes).into())
+                .into()
+        },
+        Command::WriteGroupProp((section, instance, prop, field_value)) => {
+            debug!("CMD: WriteGroupProp: {:?}[{}] {:?}", section, instance, prop);
+            debug!(" => {:x?}", field_value);
+            not_in_transaction()
+                .and_then(|_| alarm_slot(section, instance, prop))
+                .and_then(|slot| STORE.lock().unwrap().write(slot, field_value.into_store_bytes()))
+                .map(|_| CommandResponse::ok())
+                .into()
+        },
+        Command::AddInstance(section) => {
+            debug!("CMD: AddInstance: {:?}", section);
+            if let Err(e) = not_in_transaction() {
+                return CommandResponse::error(e)
+            }
+            if section != Section::Alarms {
+                return CommandResponse::error(CommandError::InvalidSection)
+            }
+            let mut instances = alarm_instances();
+            match instances.add() {
+                Some(instance) => {
+                    ALARM_INSTANCES.store(instances.bits(), Ordering::Relaxed);
+                    CommandResponse::from_payload([instance])
+                },
+                None => CommandResponse::error(CommandError::Failed),
+            }
+        },
+        Command::RemoveInstance((section, instance)) => {
+            debug!("CMD: RemoveInstance: {:?}[{}]", section, instance);
+            if let Err(e) = not_in_transaction() {
+                return CommandResponse::error(e)
+            }
+            if section != Section::Alarms {
+                return CommandResponse::error(CommandError::InvalidSection)
+            }
+            let mut instances = alarm_instances();
+            if !instances.remove(instance) {
+                return CommandResponse::error(CommandError::InvalidInstance)
+            }
+            ALARM_INSTANCES.store(instances.bits(), Ordering::Relaxed);
+            // a later instance with the same index starts out with the defaults
+            let mut store = STORE.lock().unwrap();
+            MOCK_CONF.group_props(section)
+                .try_for_each(|prop| MOCK_CONF.group_slot(section, instance, prop).and_then(|slot| store.reset(slot)))
+                .map(|_| CommandResponse::ok())
+                .into()
+        },
+        Command::ListInstances(section) => {
+            debug!("CMD: ListInstances: {:?}", section);
+            if section != Section::Alarms {
+                return CommandResponse::error(CommandError::InvalidSection)
+            }
+            (&alarm_instances()).into()
+        },
+        Command::JobStatus(id) => {
+            debug!("CMD: JobStatus: {}", id);
+            let mut jobs = JOBS.lock().unwrap();
+            advance_jobs(&mut jobs);
+            jobs.status(id).into()
+        },
+        Command::CancelJob(id) => {
+            debug!("CMD: CancelJob: {}", id);
+            JOBS.lock().unwrap().cancel(id).map(|_| CommandResponse::ok()).into()
+        },
+        Command::Dfu(command) => {
+            debug!("CMD: Dfu: {:?}", command);
+            FIRMWARE.lock().unwrap().handle(command)
+        },
+        Command::Blob(command) => {
+            debug!("CMD: Blob: {:?}", command);
+            if let (BlobCommand::Write { .. }, Err(e)) = (&command, not_in_transaction()) {
+                return CommandResponse::error(e)
+            }
+            command.handle(&mut *STORE.lock().unwrap())
+        },
+        Command::BeginTransaction => {
+            debug!("CMD: BeginTransaction");
+            TRANSACTION.lock().unwrap().begin();
+            CommandResponse::ok()
+        },
+        Command::CommitTransaction => {
+            debug!("CMD: CommitTransaction");
+            let mut tx = TRANSACTION.lock().unwrap();
+            let mut store = STORE.lock().unwrap();
+            tx.commit(&mut *store, validate)
+                .map(|_| CommandResponse::ok())
+                .into()
+        },
+        Command::AbortTransaction => {
+            debug!("CMD: AbortTransaction");
+            TRANSACTION.lock().unwrap().abort();
+            CommandResponse::ok()
+        },
+        Command::ResetProp(p) => {
+            debug!("CMD: ResetProp: {:?}", p);
+            let mut tx = TRANSACTION.lock().unwrap();
+            let result = if !tx.is_active() {
+                MOCK_CONF.reset_prop(&mut *STORE.lock().unwrap(), p)
+            } else if p.get_entry().variant == EntryVariant::Field(ValueType::Blob) {
+                Err(CommandError::NotSupported)
+            } else {
+                // an empty value reads as the default
+                tx.stage(p.as_index(), FieldValue::from_store(p.get_entry(), [0; MESSAGE_LENGTH]))
+            };
+            result.map(|_| CommandResponse::ok()).into()
+        },
+        Command::ResetAll => {
+            debug!("CMD: ResetAll");
+            not_in_transaction()
+                .and_then(|_| MOCK_CONF.reset_all(&mut *STORE.lock().unwrap()))
+                .map(|_| CommandResponse::ok())
+                .into()
+        },
+        Command::Auth(command) => {
+            debug!("CMD: Auth: {:?}", command);
+            AUTH.lock().unwrap().handle(command, now)
+        },
         Command::Noop => {
             debug!("CMD: noop");
             CommandResponse::ok()
         },
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use std::sync::PoisonError;
+    use super::*;
+
+    /// The handler state is global, so tests that change it take turns
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn write(prop: PropField, set: &dyn Fn(&mut FieldValue)) -> CommandResponse {
+        let mut value = FieldValue::new(prop.get_entry());
+        set(&mut value);
+        handle_command(Command::WriteProp((prop, value)))
+    }
+
+    #[test]
+    fn test_writable_condition() {
+        let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let locked = CommandResponse::error(CommandError::Locked);
+
+        assert_eq!(CommandResponse::ok().as_bytes(), write(PropField::NtpServer, &|v| v.set_text("pool.ntp.org")).as_bytes());
+        write(PropField::TimeSource, &|v| v.set_options(&[0]));
+        assert_eq!(locked.as_bytes(), write(PropField::NtpServer, &|v| v.set_text("ntp.se")).as_bytes());
+
+        // The staged value of the condition prop counts within a transaction
+        handle_command(Command::BeginTransaction);
+        write(PropField::TimeSource, &|v| v.set_options(&[1]));
+        assert_eq!(CommandResponse::ok().as_bytes(), write(PropField::NtpServer, &|v| v.set_text("ntp.se")).as_bytes());
+        handle_command(Command::CommitTransaction);
+    }
+
+    #[test]
+    fn test_transaction_staging() {
+        let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let read = || handle_command(Command::ReadProp(PropField::NtpServer));
+        let default = CommandResponse::from(FieldValue::from_store(PropField::NtpServer.get_entry(), [0; MESSAGE_LENGTH]));
+        let not_supported = CommandResponse::error(CommandError::NotSupported);
+
+        write(PropField::TimeSource, &|v| v.set_options(&[1]));
+        write(PropField::NtpServer, &|v| v.set_text("pool.ntp.org"));
+        let written = read();
+        assert_ne!(default.as_bytes(), written.as_bytes());
+
+        handle_command(Command::BeginTransaction);
+        assert_eq!(CommandResponse::ok().as_bytes(), handle_command(Command::ResetProp(PropField::NtpServer)).as_bytes());
+        assert_eq!(default.as_bytes(), read().as_bytes());
+        let alarm = FieldValue::new(PropField::AlarmTime.get_entry());
+        let group_write = Command::WriteGroupProp((Section::Alarms, 0, PropField::AlarmTime, alarm));
+        assert_eq!(not_supported.as_bytes(), handle_command(group_write).as_bytes());
+        assert_eq!(not_supported.as_bytes(), handle_command(Command::AddInstance(Section::Alarms)).as_bytes());
+        handle_command(Command::AbortTransaction);
+        assert_eq!(written.as_bytes(), read().as_bytes());
+    }
+}
@@ -2,7 +2,9 @@
 
 use elytra_conf::elytra;
 use elytra_conf::prelude::{*};
+use elytra_macros::OptionEnum;
 use chrono_tz::TZ_VARIANTS;
+use core::time::Duration;
 
 pub mod handler;
 
@@ -18,6 +20,16 @@ impl OptionValueProvider for TimeZoneOpts {
     }
 }
 const TIME_ZONE_OPTS: TimeZoneOpts = TimeZoneOpts{};
+const WEEKDAY_OPTS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, OptionEnum)]
+pub enum HourFormat {
+    #[option(name = "24 hour")]
+    H24,
+    #[option(name = "12 hour (AM/PM)")]
+    H12,
+}
 
 elytra!( pub MOCK_CONF: MockConf {
     info: InfoField {
@@ -31,14 +43,16 @@ elytra!( pub MOCK_CONF: MockConf {
             .with_help("The manufacturer flash chip designation"),
         PicoROM: info("Pico ROM")
             .with_help("The version of the Read Only firmware of the Pico"),
-        Time: info("Time")
+        Time: time("Time")
+            .readonly()
             .with_help("The current time, as would be displayed on the clock")
     },
     props: PropField {
         WifiNetwork: prop("Network (SSID)")
             .with_help("The name the WiFi network to connect to"),
         WifiPassword: secret("Password")
-            .with_help("The password for the WiFi network"),
+            .with_help("The password for the WiFi network")
+            .with_access(AccessLevel::User),
         BrightOffset: integer("Brightness Offset")
             .with_help("Adjustment of the display brightness auto value")
             .writable()
@@ -52,7 +66,35 @@ elytra!( pub MOCK_CONF: MockConf {
             .with_default_text("Europe/Stockholm"),
         NtpServer: prop("NTP Server")
             .with_help("The Network Time Protocol server to query for the current time")
-            .with_default_text("ntp.se")
+            .with_default_text("ntp.se"),
+        AlarmTime: time("Alarm")
+            .with_help("The time of day when the alarm goes off")
+            .with_icon("alarm-clock")
+            .with_default_time(TimeOfDay::new(7, 0, 0)),
+        SnoozeDuration: duration("Snooze")
+            .with_help("How long the alarm stays quiet after pressing snooze")
+            .with_range(60..1800)
+            .with_default_duration(Duration::from_secs(9 * 60)),
+        DigitColor: color("Digit Color")
+            .with_help("The color of the clock digits")
+            .with_icon("palette")
+            .with_default_color(Color::rgb(0xff, 0x40, 0x00)),
+        HourFormat: option_enum::<HourFormat>("Hour Format")
+            .with_help("Whether to display the time using 24 or 12 hours")
+            .with_default_enum(&[HourFormat::H24]),
+        AlarmEnabled: toggle("Enabled")
+            .with_default_enabled(true),
+        AlarmDays: option("Days", &WEEKDAY_OPTS)
+            .with_help("The days of the week when the alarm is active")
+            .with_max_options(7)
+            .with_default_options(&[0, 1, 2, 3, 4]),
+        CaCertificate: blob("CA Certificate", 4096)
+            .with_help("PEM encoded certificate used to verify the NTP and update servers")
+            .with_digest()
+            .with_access(AccessLevel::Admin),
+        TimeSource: option("Time Source", &["Manual", "NTP"])
+            .with_help("Whether the time is set by hand or synced from the NTP server")
+            .with_default_options(&[1])
     },
     sections: Section {
         Wifi: section("WiFi")
@@ -66,34 +108,66 @@ elytra!( pub MOCK_CONF: MockConf {
             .with_icon("clock"),
 
         Hardware: section("Hardware Info")
-            .with_icon("cog")
+            .with_icon("cog"),
+
+        Alarms: section("Alarms")
+            .with_icon("alarm-clock")
+            .repeated(8)
     },
     actions: Action {
         Reset: action("Reset")
-            .with_icon("power"),
+            .with_icon("power")
+            .with_access(AccessLevel::Admin),
 
         DFU: action("DFU")
             .with_icon("hard-drive-download")
+            .long_running(),
+
+        SetTime: action("Set time")
+            .with_icon("clock")
+            .with_params(&[time("Time").as_param()]),
+
+        SelfTest: action("Self-test")
+            .with_icon("stethoscope")
+            .with_result(&status("Result").as_result())
     },
     layout: {
         Section::Wifi: [
             Field::Info(InfoField::WifiStatus),
             Field::Prop(PropField::WifiNetwork),
-            Field::Prop(PropField::WifiPassword)
+            Field::Prop(PropField::WifiPassword),
+            Field::Prop(PropField::CaCertificate)
         ],
         Section::Display: [
-            Field::Prop(PropField::BrightOffset)
+            Field::Prop(PropField::BrightOffset),
+            Field::Prop(PropField::DigitColor)
         ],
         Section::Clock: [
             Field::Info(InfoField::Time),
-            Field::Prop(PropField::NtpServer),
-            Field::Prop(PropField::TimeZone)
+            Field::Action(Action::SetTime),
+            Field::Prop(PropField::TimeSource),
+            Field::Prop(PropField::NtpServer) => writable_when(PropField::TimeSource).equals(1),
+            Field::Prop(PropField::TimeZone),
+            Field::Prop(PropField::HourFormat),
+            Field::Prop(PropField::SnoozeDuration),
+            Field::Separator,
+            Field::Section(Section::Alarms)
         ],
         Section::Hardware: [
             Field::Prop(PropField::Serial),
+            Field::Heading("Flash"),
             Field::Info(InfoField::FlashUUID),
             Field::Info(InfoField::FlashJEDEC),
-            Field::Info(InfoField::PicoROM)
+            Field::Info(InfoField::PicoROM),
+            Field::Separator,
+            Field::Action(Action::Reset),
+            Field::Action(Action::DFU),
+            Field::Action(Action::SelfTest)
+        ],
+        Section::Alarms: [
+            Field::Prop(PropField::AlarmTime),
+            Field::Prop(PropField::AlarmEnabled),
+            Field::Prop(PropField::AlarmDays) => show_when(PropField::AlarmEnabled).is_enabled()
         ]
     }
 }